@@ -0,0 +1,440 @@
+//! Reading and writing ADIF files.
+
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+};
+
+use adif::{parse_adif, AdifFile, AdifHeader, AdifRecord, AdifType};
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use indexmap::map::IndexMap;
+use rusqlite::Connection;
+
+use crate::contest::Exchange;
+use crate::dxcc::{self, CountryFile};
+use crate::mode::Mode;
+use crate::models::LogbookExt;
+use crate::qsl::{Qsl, QslService};
+use crate::store::{ProfileStore, QsoStore, SettingsStore};
+
+/// Serialises `logs` as an ADIF file, one record per QSO.
+pub fn write_adif(logs: Vec<LogbookExt>) -> Result<String> {
+    let mut records: Vec<AdifRecord> = Vec::new();
+    for log in logs {
+        let mut map: IndexMap<&str, AdifType> = IndexMap::new();
+        map.insert("CALL", AdifType::Str(log.call));
+        map.insert(
+            "QSO_DATE",
+            AdifType::Str(log.timestamp.format("%Y%m%d").to_string()),
+        );
+        map.insert(
+            "TIME_ON",
+            AdifType::Str(log.timestamp.format("%H%M%S").to_string()),
+        );
+        if let Some(time_off) = log.time_off {
+            map.insert(
+                "QSO_DATE_OFF",
+                AdifType::Str(time_off.format("%Y%m%d").to_string()),
+            );
+            map.insert(
+                "TIME_OFF",
+                AdifType::Str(time_off.format("%H%M%S").to_string()),
+            );
+        }
+        if let Some(frequency) = log.frequency {
+            map.insert("FREQ", AdifType::Str(frequency.to_string()));
+        }
+        map.insert("BAND", AdifType::Str(log.band.clone()));
+        if let Some(frequency) = log.frequency {
+            map.insert("FREQ_RX", AdifType::Str(frequency.to_string()));
+        }
+        map.insert("BAND_RX", AdifType::Str(log.band));
+        map.insert("COMMENT", AdifType::Str(log.comments));
+        for (field, value) in [
+            ("NAME", log.name),
+            ("QTH", log.qth),
+            ("STATE", log.state),
+            ("GRIDSQUARE", log.gridsquare),
+        ] {
+            if !value.is_empty() {
+                map.insert(field, AdifType::Str(value));
+            }
+        }
+        match Mode::from_name(&log.mode) {
+            Some(mode) => {
+                map.insert("MODE", AdifType::Str(mode.mode.to_string()));
+                if let Some(submode) = mode.submode {
+                    map.insert("SUBMODE", AdifType::Str(submode.to_string()));
+                }
+            }
+            None => {
+                map.insert("MODE", AdifType::Str(log.mode.clone()));
+            }
+        }
+        map.insert("MY_GRIDSQUARE", AdifType::Str(log.operator.grid));
+        map.insert("STATION_CALLSIGN", AdifType::Str(log.operator.call.clone()));
+        // Our own location goes in the MY_ fields; the plain ones describe the contacted station
+        for (field, value) in [
+            ("MY_CQ_ZONE", log.operator.cqz),
+            ("MY_ITU_ZONE", log.operator.ituz),
+            ("MY_DXCC", log.operator.dxcc),
+        ] {
+            if !value.trim().is_empty() {
+                map.insert(field, AdifType::Str(value));
+            }
+        }
+        if !log.country.is_empty() {
+            map.insert("COUNTRY", AdifType::Str(log.country));
+        }
+        for (field, value) in [("DXCC", log.dxcc), ("CQZ", log.cqz), ("ITUZ", log.ituz)] {
+            if let Some(value) = value {
+                map.insert(field, AdifType::Str(value.to_string()));
+            }
+        }
+        if !log.cont.is_empty() {
+            map.insert("CONT", AdifType::Str(log.cont));
+        }
+        if let Some(distance) = log.distance {
+            map.insert("DISTANCE", AdifType::Str(format!("{:.0}", distance)));
+        }
+        if !log.contest_id.is_empty() {
+            map.insert("CONTEST_ID", AdifType::Str(log.contest_id));
+        }
+        for (serial_field, text_field, exchange) in [
+            ("STX", "STX_STRING", log.exchange_sent),
+            ("SRX", "SRX_STRING", log.exchange_rcvd),
+        ] {
+            if let Some(serial) = exchange.serial {
+                map.insert(serial_field, AdifType::Str(serial.to_string()));
+            }
+            if !exchange.text.is_empty() {
+                map.insert(text_field, AdifType::Str(exchange.text));
+            }
+        }
+        for (service, qsl) in [
+            (QslService::Paper, &log.paper_qsl),
+            (QslService::Lotw, &log.lotw_qsl),
+            (QslService::Eqsl, &log.eqsl_qsl),
+        ] {
+            let [sent, rcvd, sent_date, rcvd_date] = service.adif_fields();
+            for (field, status) in [(sent, &qsl.sent), (rcvd, &qsl.rcvd)] {
+                if status != "N" {
+                    map.insert(field, AdifType::Str(status.clone()));
+                }
+            }
+            for (field, date) in [(sent_date, qsl.sent_date), (rcvd_date, qsl.rcvd_date)] {
+                if let Some(date) = date {
+                    map.insert(field, AdifType::Str(date.format("%Y%m%d").to_string()));
+                }
+            }
+        }
+        map.insert("OPERATOR", AdifType::Str(log.operator.call));
+        map.insert("RST_SENT", AdifType::Str(log.rsttx));
+        map.insert("RST_RCVD", AdifType::Str(log.rstrx));
+        map.insert("TX_PWR", AdifType::Str(log.power));
+        records.push(map.into());
+    }
+    let mut map: IndexMap<&str, AdifType> = IndexMap::new();
+    map.insert("PROGRAMVERSION", AdifType::Str("1.0.0".to_string()));
+    map.insert("PROGRAMID", AdifType::Str("TUILOG".to_string()));
+    let header: AdifHeader = map.into();
+    let file_out = AdifFile {
+        header,
+        body: records,
+    };
+    file_out
+        .serialize()
+        .map_err(|_| anyhow!("Failed to serialize data"))
+}
+
+/// A record field as text, or `None` when it is missing or blank.
+pub fn adif_field(record: &AdifRecord, key: &str) -> Option<String> {
+    let value = match record.get(key)? {
+        AdifType::Str(value) => value.trim().to_string(),
+        AdifType::Boolean(value) => if *value { "Y" } else { "N" }.to_string(),
+        AdifType::Number(value) => value.to_string(),
+        AdifType::Date(value) => value.format("%Y%m%d").to_string(),
+        AdifType::Time(value) => value.format("%H%M%S").to_string(),
+    };
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Combines a `YYYYMMDD` date field and a `HHMM[SS]` time field.
+pub fn adif_timestamp(
+    record: &AdifRecord,
+    date_field: &str,
+    time_field: &str,
+) -> Result<NaiveDateTime> {
+    let date = adif_field(record, date_field).ok_or(anyhow!("missing {}", date_field))?;
+    let date = NaiveDate::parse_from_str(&date, "%Y%m%d")
+        .map_err(|_| anyhow!("invalid {} '{}'", date_field, date))?;
+    let time = adif_field(record, time_field).ok_or(anyhow!("missing {}", time_field))?;
+    let time = match time.len() {
+        4 => NaiveTime::parse_from_str(&time, "%H%M"),
+        _ => NaiveTime::parse_from_str(&time, "%H%M%S"),
+    }
+    .map_err(|_| anyhow!("invalid {} '{}'", time_field, time))?;
+    Ok(date.and_time(time))
+}
+
+/// The end of a QSO that started at `time_on`, if the record has a `TIME_OFF`.
+pub fn adif_time_off(record: &AdifRecord, time_on: NaiveDateTime) -> Result<Option<NaiveDateTime>> {
+    if adif_field(record, "TIME_OFF").is_none() {
+        return Ok(None);
+    }
+    if adif_field(record, "QSO_DATE_OFF").is_some() {
+        return adif_timestamp(record, "QSO_DATE_OFF", "TIME_OFF").map(Some);
+    }
+    // Without QSO_DATE_OFF, an end time before the start means the QSO ran past midnight
+    let time_off = adif_timestamp(record, "QSO_DATE", "TIME_OFF")?;
+    Ok(Some(if time_off < time_on {
+        time_off + Duration::days(1)
+    } else {
+        time_off
+    }))
+}
+
+// QSL statuses default to N(o), like ADIF says they should
+fn adif_qsl(record: &AdifRecord, service: QslService) -> Result<Qsl> {
+    let [sent, rcvd, sent_date, rcvd_date] = service.adif_fields();
+    let date = |field: &str| {
+        adif_field(record, field)
+            .map(|date| {
+                NaiveDate::parse_from_str(&date, "%Y%m%d")
+                    .map_err(|_| anyhow!("invalid {} '{}'", field, date))
+            })
+            .transpose()
+    };
+    let qsl = Qsl {
+        sent: adif_field(record, sent).map_or(String::from("N"), |status| status.to_uppercase()),
+        sent_date: date(sent_date)?,
+        rcvd: adif_field(record, rcvd).map_or(String::from("N"), |status| status.to_uppercase()),
+        rcvd_date: date(rcvd_date)?,
+    };
+    qsl.validate()
+        .map_err(|err| anyhow!("{}: {}", service.name(), err))?;
+    Ok(qsl)
+}
+
+fn adif_exchange(record: &AdifRecord, serial_field: &str, text_field: &str) -> Result<Exchange> {
+    let serial = adif_field(record, serial_field)
+        .map(|serial| {
+            serial
+                .parse()
+                .map_err(|_| anyhow!("invalid {} '{}'", serial_field, serial))
+        })
+        .transpose()?;
+    Ok(Exchange {
+        serial,
+        text: adif_field(record, text_field)
+            .unwrap_or_default()
+            .to_uppercase(),
+    })
+}
+
+fn import_record(
+    profiles: &ProfileStore,
+    countries: Option<&CountryFile>,
+    record: &AdifRecord,
+    profile: Option<u64>,
+) -> Result<LogbookExt> {
+    let call = adif_field(record, "CALL").ok_or(anyhow!("missing CALL"))?;
+    let timestamp = adif_timestamp(record, "QSO_DATE", "TIME_ON")?;
+    let time_off = adif_time_off(record, timestamp)?;
+    let band = adif_field(record, "BAND").ok_or(anyhow!("missing BAND"))?;
+    let mode = adif_field(record, "MODE").ok_or(anyhow!("missing MODE"))?;
+    // The logbook stores the submode as the mode itself, mirroring export
+    let mode = Mode::from_adif(&mode, adif_field(record, "SUBMODE").as_deref())
+        .ok_or(anyhow!("unknown MODE {}", mode))?;
+    let operator = match profile {
+        Some(profile) => profiles.get(profile)?,
+        None => {
+            let station = adif_field(record, "STATION_CALLSIGN")
+                .or(adif_field(record, "OPERATOR"))
+                .ok_or(anyhow!("missing STATION_CALLSIGN"))?;
+            profiles
+                .find_by_call(&station)?
+                .ok_or(anyhow!("no profile with callsign {}", station))?
+        }
+    };
+    let mut log = LogbookExt {
+        id: 0,
+        timestamp,
+        call: call.to_uppercase(),
+        rsttx: adif_field(record, "RST_SENT").unwrap_or_default(),
+        rstrx: adif_field(record, "RST_RCVD").unwrap_or_default(),
+        band: band.to_uppercase(),
+        frequency: adif_field(record, "FREQ")
+            .map(|frequency| frequency.parse())
+            .transpose()?,
+        mode: mode.name().to_string(),
+        power: adif_field(record, "TX_PWR").unwrap_or_default(),
+        comments: adif_field(record, "COMMENT").unwrap_or_default(),
+        time_off,
+        name: adif_field(record, "NAME").unwrap_or_default(),
+        qth: adif_field(record, "QTH").unwrap_or_default(),
+        state: adif_field(record, "STATE").unwrap_or_default(),
+        gridsquare: adif_field(record, "GRIDSQUARE").unwrap_or_default(),
+        license_class: String::new(),
+        country: String::new(),
+        dxcc: None,
+        cqz: None,
+        ituz: None,
+        cont: String::new(),
+        distance: None,
+        bearing: None,
+        paper_qsl: adif_qsl(record, QslService::Paper)?,
+        lotw_qsl: adif_qsl(record, QslService::Lotw)?,
+        eqsl_qsl: adif_qsl(record, QslService::Eqsl)?,
+        contest: None,
+        contest_id: adif_field(record, "CONTEST_ID").unwrap_or_default(),
+        exchange_sent: adif_exchange(record, "STX", "STX_STRING")?,
+        exchange_rcvd: adif_exchange(record, "SRX", "SRX_STRING")?,
+        operator,
+    };
+    // Entity fields in the file win over what the country file says
+    log.set_entity(countries.and_then(|countries| countries.resolve(&log.call)));
+    if let Some(country) = adif_field(record, "COUNTRY") {
+        log.country = country;
+    }
+    if let Some(cont) = adif_field(record, "CONT") {
+        log.cont = cont.to_uppercase();
+    }
+    for (field, value) in [
+        ("DXCC", &mut log.dxcc),
+        ("CQZ", &mut log.cqz),
+        ("ITUZ", &mut log.ituz),
+    ] {
+        if let Some(number) = adif_field(record, field) {
+            *value = Some(
+                number
+                    .parse()
+                    .map_err(|_| anyhow!("invalid {} '{}'", field, number))?,
+            );
+        }
+    }
+    log.set_path();
+    if let Some(distance) = adif_field(record, "DISTANCE") {
+        log.distance = Some(
+            distance
+                .parse()
+                .map_err(|_| anyhow!("invalid DISTANCE '{}'", distance))?,
+        );
+    }
+    Ok(log)
+}
+
+/// Checks every typed field the adif parser converts, the way it reads them, since it
+/// panics on a value it cannot convert instead of returning an error.
+fn check_typed_fields(data: &str) -> Result<()> {
+    let data = data.replace("<eoh>", "<EOH>").replace("<eor>", "<EOR>");
+    if data.matches("<EOH>").count() > 1 {
+        return Err(anyhow!("more than one <EOH>"));
+    }
+    for (start, _) in data.match_indices('<') {
+        let rest = &data[start + 1..];
+        let Some(close) = rest.find('>') else {
+            continue;
+        };
+        let parts: Vec<&str> = rest[..close].split(':').collect();
+        let (name, length, kind) = match parts.as_slice() {
+            [name, length] => (*name, *length, None),
+            [name, length, kind] if kind.len() == 1 => (*name, *length, kind.chars().next()),
+            _ => continue,
+        };
+        let is_name = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic() || c == '_');
+        // The parser takes any Unicode digit as part of the length, then fails to read it
+        if !is_name || length.is_empty() || !length.chars().all(char::is_numeric) {
+            continue;
+        }
+        if kind.is_some_and(|kind| !kind.is_ascii_alphabetic()) {
+            continue;
+        }
+        length
+            .parse::<usize>()
+            .map_err(|_| anyhow!("{}: '{}' is not a field length", name, length))?;
+        let value = rest[close + 1..].split('<').next().unwrap_or("").trim_end();
+        let invalid = match kind.map(|kind| kind.to_ascii_uppercase()) {
+            Some('N') => lexical::parse::<f64, _>(value).is_err().then_some("number"),
+            Some('D') => NaiveDate::parse_from_str(value, "%Y%m%d")
+                .is_err()
+                .then_some("date (YYYYMMDD)"),
+            Some('T') => NaiveTime::parse_from_str(value, "%H%M%S")
+                .is_err()
+                .then_some("time (HHMMSS)"),
+            _ => None,
+        };
+        if let Some(expected) = invalid {
+            return Err(anyhow!("{}: '{}' is not a {}", name, value, expected));
+        }
+    }
+    Ok(())
+}
+
+/// Parses ADIF text, returning an error for a value the adif parser would panic on.
+pub fn parse(data: &str) -> Result<AdifFile> {
+    check_typed_fields(data)?;
+    Ok(parse_adif(data))
+}
+
+/// Imports every record of the ADIF file at `path` in one transaction.
+///
+/// Returns the number of QSOs imported and a reason for each record that was skipped.
+pub fn import_adif(
+    connection: Arc<Mutex<Connection>>,
+    path: &str,
+    profile: Option<u64>,
+) -> Result<(usize, Vec<String>)> {
+    let data = fs::read_to_string(path)?;
+    let file = parse(&data).map_err(|err| anyhow!("Failed to parse {}: {}", path, err))?;
+    let profiles = ProfileStore::new(connection.clone());
+    let countries = dxcc::configured(&SettingsStore::new(connection.clone()))?;
+    let mut logs: Vec<LogbookExt> = Vec::new();
+    let mut skipped: Vec<String> = Vec::new();
+    for (index, record) in file.body.iter().enumerate() {
+        if record.is_empty() {
+            continue;
+        }
+        match import_record(&profiles, countries.as_deref(), record, profile) {
+            Ok(log) => logs.push(log),
+            Err(err) => skipped.push(format!(
+                "Record {} ({}): {}",
+                index + 1,
+                adif_field(record, "CALL").unwrap_or("?".to_string()),
+                err
+            )),
+        }
+    }
+    QsoStore::new(connection).insert_all(&logs)?;
+    Ok((logs.len(), skipped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reports_malformed_typed_values_as_errors() {
+        let file = parse("<CALL:5>DL1AB <QSO_DATE:8:D>20240301 <FREQ:6:N>14.025 <EOR>").unwrap();
+        assert_eq!(adif_field(&file.body[0], "CALL").as_deref(), Some("DL1AB"));
+
+        for record in [
+            "<CALL:5>DL1AB <QSO_DATE:8:D>2024XX01 <EOR>",
+            "<CALL:5>DL1AB <TIME_ON:6:T>126000 <EOR>",
+            "<CALL:5>DL1AB <FREQ:3:N>abc <EOR>",
+            "<EOH> <CALL:5>DL1AB <EOR> <EOH>",
+        ] {
+            assert!(parse(record).is_err(), "{}", record);
+        }
+        let err = parse("<QSO_DATE:8:D>2024XX01 <EOR>").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "QSO_DATE: '2024XX01' is not a date (YYYYMMDD)"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use cursive::{
+    align::HAlign,
+    view::{Nameable, Resizable, Scrollable},
+    views::{Button, Dialog, DummyView, LinearLayout, SelectView, TextView},
+    Cursive,
+};
+use cursive_aligned_view::Alignable;
+use rusqlite::Connection;
+
+use crate::log::edit_log;
+use tuilog::awards::{Award, AwardRow, Matrix, ModeClass};
+use tuilog::bandplan::BandPlan;
+use tuilog::store::{QsoFilter, QsoStore, SettingsStore};
+
+const CREDIT_WIDTH: usize = 32;
+const BAND_WIDTH: usize = 6;
+
+fn cell_text(row: &AwardRow, band: &str) -> &'static str {
+    match row.bands.get(band) {
+        Some(cell) if cell.confirmed => "C",
+        Some(_) => "W",
+        None => ".",
+    }
+}
+
+fn row_text(matrix: &Matrix, row: &AwardRow) -> String {
+    let mut text = format!("{:<width$}", row.credit, width = CREDIT_WIDTH);
+    for band in &matrix.bands {
+        text.push_str(&format!(
+            "{:^width$}",
+            cell_text(row, band),
+            width = BAND_WIDTH
+        ));
+    }
+    text
+}
+
+/// Rebuilds the matrix for the award and mode picked at the top of the screen.
+fn refresh_awards(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let award = s
+        .call_on_name("award", |view: &mut SelectView<Award>| {
+            view.selection().map(|award| *award)
+        })
+        .flatten()
+        .unwrap_or(Award::Dxcc);
+    let class = s
+        .call_on_name("award_mode", |view: &mut SelectView<Option<ModeClass>>| {
+            view.selection().and_then(|class| *class)
+        })
+        .flatten();
+    let plan = BandPlan::load(&SettingsStore::new(connection.clone()))?;
+    let logs = QsoStore::new(connection).query(&QsoFilter::default())?;
+    let matrix = Matrix::build(award, class, &logs, &plan);
+    let mut header = format!("{:<width$}", "", width = CREDIT_WIDTH);
+    for band in &matrix.bands {
+        header.push_str(&format!("{:^width$}", band, width = BAND_WIDTH));
+    }
+    let summary = format!(
+        "{} worked, {} confirmed of {}",
+        matrix.worked(),
+        matrix.confirmed(),
+        award.target()
+    );
+    s.call_on_name("award_header", |view: &mut TextView| {
+        view.set_content(header)
+    });
+    s.call_on_name("award_summary", |view: &mut TextView| {
+        view.set_content(summary)
+    });
+    s.call_on_name("award_rows", |view: &mut SelectView<AwardRow>| {
+        view.clear();
+        for row in &matrix.rows {
+            view.add_item(row_text(&matrix, row), row.clone());
+        }
+    });
+    Ok(())
+}
+
+/// Lists the QSOs behind a cell; Enter opens one for editing.
+fn show_qsos(
+    s: &mut Cursive,
+    connection: Arc<Mutex<Connection>>,
+    title: String,
+    ids: &[u64],
+) -> Result<()> {
+    let qsos = QsoStore::new(connection.clone());
+    let mut select = SelectView::<u64>::new();
+    for id in ids {
+        let log = qsos.get(*id)?;
+        let confirmed = if log.confirmed() { "confirmed" } else { "" };
+        select.add_item(
+            format!(
+                "{}  {:<12} {:<6} {:<8} {}",
+                log.timestamp, log.call, log.band, log.mode, confirmed
+            ),
+            *id,
+        );
+    }
+    select.set_on_submit(move |s, id: &u64| {
+        if let Err(err) = edit_log(s, connection.clone(), *id) {
+            s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+        }
+    });
+    s.add_layer(
+        Dialog::around(select.scrollable().max_height(20))
+            .title(title)
+            .dismiss_button("Close"),
+    );
+    Ok(())
+}
+
+/// Breaks a credit down by band, then into its QSOs.
+fn show_credit(s: &mut Cursive, connection: Arc<Mutex<Connection>>, row: &AwardRow) {
+    let mut select = SelectView::<(String, Vec<u64>)>::new();
+    let all = row.qsos();
+    select.add_item(
+        format!("All bands: {} QSOs", all.len()),
+        (String::from("all bands"), all),
+    );
+    for (band, cell) in &row.bands {
+        let status = if cell.confirmed {
+            "confirmed"
+        } else {
+            "worked"
+        };
+        select.add_item(
+            format!("{}: {} QSOs, {}", band, cell.qsos.len(), status),
+            (band.clone(), cell.qsos.clone()),
+        );
+    }
+    let credit = row.credit.clone();
+    select.set_on_submit(move |s, (band, ids): &(String, Vec<u64>)| {
+        let title = format!("{} on {}", credit, band);
+        if let Err(err) = show_qsos(s, connection.clone(), title, ids) {
+            s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+        }
+    });
+    s.add_layer(
+        Dialog::around(select)
+            .title(row.credit.as_str())
+            .dismiss_button("Close"),
+    );
+}
+
+pub fn awards(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let award_connection = connection.clone();
+    let mode_connection = connection.clone();
+    let row_connection = connection.clone();
+    let mut award = SelectView::<Award>::new().popup();
+    for item in Award::all() {
+        award.add_item(item.name(), item);
+    }
+    award.set_on_submit(move |s, _: &Award| {
+        if let Err(err) = refresh_awards(s, award_connection.clone()) {
+            s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+        }
+    });
+    let mut mode = SelectView::<Option<ModeClass>>::new().popup();
+    mode.add_item("Mixed", None);
+    for class in ModeClass::all() {
+        mode.add_item(class.name(), Some(class));
+    }
+    mode.set_on_submit(move |s, _: &Option<ModeClass>| {
+        if let Err(err) = refresh_awards(s, mode_connection.clone()) {
+            s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+        }
+    });
+    let mut rows = SelectView::<AwardRow>::new().h_align(HAlign::Left);
+    rows.set_on_submit(move |s, row: &AwardRow| {
+        show_credit(s, row_connection.clone(), row);
+    });
+    s.pop_layer();
+    s.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(
+                    LinearLayout::horizontal()
+                        .child(Dialog::around(award.with_name("award")).title("Award"))
+                        .child(Dialog::around(mode.with_name("award_mode")).title("Mode"))
+                        .child(DummyView)
+                        .child(TextView::new("").with_name("award_summary"))
+                        .align_center(),
+                )
+                .child(TextView::new("C = confirmed, W = worked").align_center())
+                .child(DummyView)
+                .child(TextView::new("").with_name("award_header"))
+                .child(rows.with_name("award_rows").scrollable().fixed_height(20))
+                .child(DummyView)
+                .child(
+                    Button::new("Close", |s| {
+                        s.pop_layer();
+                    })
+                    .align_center(),
+                ),
+        )
+        .title("Awards"),
+    );
+    refresh_awards(s, connection)
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+
+use crate::bandplan::BandPlan;
+use crate::mode::Mode;
+use crate::models::LogbookExt;
+use crate::report::Report;
+
+/// An award whose progress is tracked from the logbook.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Award {
+    /// DX Century Club: DXCC entities
+    Dxcc,
+    /// Worked All States: the 50 US states
+    Was,
+    /// Worked All Zones: the 40 CQ zones
+    Waz,
+    /// VHF/UHF Century Club: 4 character grid squares on 6m and up
+    Vucc,
+}
+
+const US_STATES: &[&str] = &[
+    "AK", "AL", "AR", "AZ", "CA", "CO", "CT", "DE", "FL", "GA", "HI", "IA", "ID", "IL", "IN", "KS",
+    "KY", "LA", "MA", "MD", "ME", "MI", "MN", "MO", "MS", "MT", "NC", "ND", "NE", "NH", "NJ", "NM",
+    "NV", "NY", "OH", "OK", "OR", "PA", "RI", "SC", "SD", "TN", "TX", "UT", "VA", "VT", "WA", "WI",
+    "WV", "WY",
+];
+
+// DXCC numbers and cty.dat names of the entities that count for WAS
+const US_ENTITIES: &[u32] = &[6, 110, 291];
+const US_COUNTRIES: &[&str] = &["United States", "Alaska", "Hawaii"];
+
+// VUCC starts at 50 MHz
+const VUCC_LOWEST_MHZ: f64 = 50.0;
+
+impl Award {
+    pub fn all() -> [Award; 4] {
+        [Award::Dxcc, Award::Was, Award::Waz, Award::Vucc]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Award::Dxcc => "DXCC",
+            Award::Was => "WAS",
+            Award::Waz => "WAZ",
+            Award::Vucc => "VUCC",
+        }
+    }
+
+    /// How many credits the basic award needs.
+    pub fn target(&self) -> usize {
+        match self {
+            Award::Dxcc => 100,
+            Award::Was => 50,
+            Award::Waz => 40,
+            Award::Vucc => 100,
+        }
+    }
+
+    /// The credit `log` counts towards, such as an entity or a state, if any.
+    pub fn credit(&self, log: &LogbookExt, plan: &BandPlan) -> Option<String> {
+        match self {
+            Award::Dxcc => match (log.dxcc, log.country.is_empty()) {
+                (Some(dxcc), false) => Some(format!("{} ({})", log.country, dxcc)),
+                (None, false) => Some(log.country.clone()),
+                (_, true) => None,
+            },
+            Award::Was => {
+                let state = log.state.trim().to_uppercase();
+                let in_us = log.dxcc.is_some_and(|dxcc| US_ENTITIES.contains(&dxcc))
+                    || US_COUNTRIES.contains(&log.country.as_str())
+                    || (log.dxcc.is_none() && log.country.is_empty());
+                (in_us && US_STATES.contains(&state.as_str())).then_some(state)
+            }
+            Award::Waz => log
+                .cqz
+                .filter(|cqz| (1..=40).contains(cqz))
+                .map(|cqz| format!("{:02}", cqz)),
+            Award::Vucc => {
+                let vhf = match plan.band(&log.band) {
+                    Some(band) => band.lower >= VUCC_LOWEST_MHZ,
+                    None => log
+                        .frequency
+                        .is_some_and(|frequency| frequency.mhz() >= VUCC_LOWEST_MHZ),
+                };
+                let field = log.gridsquare.trim().get(..4).filter(|_| vhf);
+                field.map(|field| field.to_uppercase())
+            }
+        }
+    }
+}
+
+/// Groups modes the way awards endorse them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModeClass {
+    Cw,
+    Phone,
+    Digital,
+}
+
+impl ModeClass {
+    pub fn all() -> [ModeClass; 3] {
+        [ModeClass::Cw, ModeClass::Phone, ModeClass::Digital]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModeClass::Cw => "CW",
+            ModeClass::Phone => "Phone",
+            ModeClass::Digital => "Digital",
+        }
+    }
+
+    /// The class of a logged mode; anything that is neither CW nor phone is digital.
+    pub fn of(mode: &str) -> ModeClass {
+        match Mode::from_name(mode) {
+            Some(mode) if mode.mode == "CW" => ModeClass::Cw,
+            Some(mode) if Report::for_mode(&mode) == Report::Rs => ModeClass::Phone,
+            _ => ModeClass::Digital,
+        }
+    }
+}
+
+/// The QSOs behind one credit on one band.
+#[derive(Clone, Debug, Default)]
+pub struct Cell {
+    pub qsos: Vec<u64>,
+    pub confirmed: bool,
+}
+
+/// One credit, such as an entity, with its QSOs by band.
+#[derive(Clone, Debug)]
+pub struct AwardRow {
+    pub credit: String,
+    pub bands: BTreeMap<String, Cell>,
+}
+
+impl AwardRow {
+    pub fn confirmed(&self) -> bool {
+        self.bands.values().any(|cell| cell.confirmed)
+    }
+
+    /// Every QSO for this credit, across all bands.
+    pub fn qsos(&self) -> Vec<u64> {
+        self.bands
+            .values()
+            .flat_map(|cell| cell.qsos.iter().cloned())
+            .collect()
+    }
+}
+
+/// Worked and confirmed credits for an award, by band.
+#[derive(Clone, Debug)]
+pub struct Matrix {
+    pub award: Award,
+    /// Bands with at least one credit, in band plan order
+    pub bands: Vec<String>,
+    /// Credits in order
+    pub rows: Vec<AwardRow>,
+}
+
+impl Matrix {
+    /// Aggregates `logs` for `award`, keeping only modes of `class` when one is given.
+    pub fn build(
+        award: Award,
+        class: Option<ModeClass>,
+        logs: &[LogbookExt],
+        plan: &BandPlan,
+    ) -> Matrix {
+        let mut rows: BTreeMap<String, BTreeMap<String, Cell>> = BTreeMap::new();
+        for log in logs {
+            if class.is_some_and(|class| ModeClass::of(&log.mode) != class) {
+                continue;
+            }
+            if let Some(credit) = award.credit(log, plan) {
+                let cell = rows
+                    .entry(credit)
+                    .or_default()
+                    .entry(log.band.to_uppercase())
+                    .or_default();
+                cell.qsos.push(log.id);
+                cell.confirmed |= log.confirmed();
+            }
+        }
+        let mut bands: Vec<String> = plan
+            .bands
+            .iter()
+            .map(|band| band.name.clone())
+            .filter(|band| rows.values().any(|row| row.contains_key(band)))
+            .collect();
+        // Bands missing from the plan go last rather than being dropped
+        for row in rows.values() {
+            for band in row.keys() {
+                if !bands.contains(band) {
+                    bands.push(band.clone());
+                }
+            }
+        }
+        Matrix {
+            award,
+            bands,
+            rows: rows
+                .into_iter()
+                .map(|(credit, bands)| AwardRow { credit, bands })
+                .collect(),
+        }
+    }
+
+    pub fn worked(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn confirmed(&self) -> usize {
+        self.rows.iter().filter(|row| row.confirmed()).count()
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs;
+
+use anyhow::{anyhow, Result};
+
+use crate::frequency::Frequency;
+use crate::store::SettingsStore;
+
+#[derive(Clone, Debug)]
+pub struct Band {
+    pub name: String,
+    /// Lower edge in MHz
+    pub lower: f64,
+    /// Upper edge in MHz
+    pub upper: f64,
+    /// Frequency in MHz filled in when the band is picked
+    pub default: f64,
+}
+
+impl Band {
+    pub fn contains(&self, frequency: Frequency) -> bool {
+        frequency.mhz() >= self.lower && frequency.mhz() <= self.upper
+    }
+}
+
+// name, region (0 = every region), lower, upper, default; all frequencies in MHz
+const BUILTIN: &[(&str, u8, f64, f64, f64)] = &[
+    ("2200M", 0, 0.1357, 0.1378, 0.1357),
+    ("630M", 0, 0.472, 0.479, 0.472),
+    ("160M", 1, 1.81, 2.0, 1.81),
+    ("160M", 2, 1.8, 2.0, 1.8),
+    ("160M", 3, 1.8, 2.0, 1.8),
+    ("80M", 1, 3.5, 3.8, 3.5),
+    ("80M", 2, 3.5, 4.0, 3.5),
+    ("80M", 3, 3.5, 3.9, 3.5),
+    ("60M", 1, 5.3515, 5.3665, 5.3515),
+    ("60M", 2, 5.33, 5.405, 5.33),
+    ("60M", 3, 5.3515, 5.3665, 5.3515),
+    ("40M", 1, 7.0, 7.2, 7.0),
+    ("40M", 2, 7.0, 7.3, 7.0),
+    ("40M", 3, 7.0, 7.2, 7.0),
+    ("30M", 0, 10.1, 10.15, 10.1),
+    ("20M", 0, 14.0, 14.35, 14.0),
+    ("17M", 0, 18.068, 18.168, 18.1),
+    ("15M", 0, 21.0, 21.45, 21.0),
+    ("12M", 0, 24.89, 24.99, 24.9),
+    ("10M", 0, 28.0, 29.7, 28.0),
+    ("6M", 1, 50.0, 52.0, 50.0),
+    ("6M", 2, 50.0, 54.0, 50.0),
+    ("6M", 3, 50.0, 54.0, 50.0),
+    ("4M", 1, 70.0, 70.5, 70.0),
+    ("2M", 1, 144.0, 146.0, 144.0),
+    ("2M", 2, 144.0, 148.0, 144.0),
+    ("2M", 3, 144.0, 148.0, 144.0),
+    ("1.25M", 2, 222.0, 225.0, 222.0),
+    ("70CM", 1, 430.0, 440.0, 432.0),
+    ("70CM", 2, 420.0, 450.0, 432.0),
+    ("70CM", 3, 430.0, 440.0, 432.0),
+    ("33CM", 2, 902.0, 928.0, 902.0),
+    ("23CM", 0, 1240.0, 1300.0, 1296.0),
+];
+
+/// The bands available in one ITU region, ordered by frequency.
+#[derive(Clone, Debug)]
+pub struct BandPlan {
+    pub region: u8,
+    pub bands: Vec<Band>,
+}
+
+impl BandPlan {
+    pub fn builtin(region: u8) -> BandPlan {
+        let mut plan = BandPlan {
+            region,
+            bands: Vec::new(),
+        };
+        for (name, band_region, lower, upper, default) in BUILTIN {
+            if *band_region == 0 || *band_region == region {
+                plan.set(Band {
+                    name: name.to_string(),
+                    lower: *lower,
+                    upper: *upper,
+                    default: *default,
+                });
+            }
+        }
+        plan
+    }
+
+    /// Builds the plan for `region`, overridden by the bands in `file` when given.
+    ///
+    /// Override files hold one `name,region,lower,upper,default` line per band, in MHz, with
+    /// region 0 meaning every region. A line replaces the built-in band of the same name.
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn new(region: u8, file: Option<&str>) -> Result<BandPlan> {
+        let mut plan = BandPlan::builtin(region);
+        if let Some(path) = file.filter(|path| !path.is_empty()) {
+            let data = fs::read_to_string(path)
+                .map_err(|err| anyhow!("Could not read band plan {}: {}", path, err))?;
+            plan.apply_overrides(&data)
+                .map_err(|err| anyhow!("Invalid band plan {}: {}", path, err))?;
+        }
+        Ok(plan)
+    }
+
+    /// Loads the plan for the region and override file saved in the logbook settings.
+    pub fn load(settings: &SettingsStore) -> Result<BandPlan> {
+        let region = settings
+            .get("itu_region")?
+            .and_then(|region| region.parse().ok())
+            .unwrap_or(2);
+        BandPlan::new(region, settings.get("bandplan_file")?.as_deref())
+    }
+
+    /// Saves the region and override file, refusing a file that does not load.
+    pub fn save(settings: &SettingsStore, region: u8, file: &str) -> Result<()> {
+        settings.set_all(&BandPlan::values(region, file)?)
+    }
+
+    /// Checks the region and override file, and returns the settings keys and values they
+    /// are stored as.
+    pub fn values(region: u8, file: &str) -> Result<Vec<(&'static str, String)>> {
+        if !(1..=3).contains(&region) {
+            return Err(anyhow!("ITU region must be 1, 2 or 3"));
+        }
+        BandPlan::new(region, Some(file))?;
+        Ok(vec![
+            ("itu_region", region.to_string()),
+            ("bandplan_file", file.to_string()),
+        ])
+    }
+
+    fn apply_overrides(&mut self, data: &str) -> Result<()> {
+        for (index, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+            if fields.len() != 5 {
+                return Err(anyhow!("line {}: expected 5 fields", index + 1));
+            }
+            let number = |field: &str| -> Result<f64> {
+                field
+                    .parse()
+                    .map_err(|_| anyhow!("line {}: '{}' is not a number", index + 1, field))
+            };
+            let region: u8 = fields[1]
+                .parse()
+                .map_err(|_| anyhow!("line {}: '{}' is not a region", index + 1, fields[1]))?;
+            if region != 0 && region != self.region {
+                continue;
+            }
+            let band = Band {
+                name: fields[0].to_uppercase(),
+                lower: number(fields[2])?,
+                upper: number(fields[3])?,
+                default: number(fields[4])?,
+            };
+            if band.lower > band.upper || !band.contains(Frequency::from_mhz(band.default)) {
+                return Err(anyhow!(
+                    "line {}: default must lie between the edges",
+                    index + 1
+                ));
+            }
+            self.set(band);
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, band: Band) {
+        self.bands.retain(|existing| existing.name != band.name);
+        self.bands.push(band);
+        self.bands
+            .sort_by(|a, b| a.lower.partial_cmp(&b.lower).unwrap());
+    }
+
+    pub fn band(&self, name: &str) -> Option<&Band> {
+        self.bands
+            .iter()
+            .find(|band| band.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Finds the band a frequency falls in.
+    pub fn band_for(&self, frequency: Frequency) -> Option<&Band> {
+        self.bands.iter().find(|band| band.contains(frequency))
+    }
+
+    /// Checks that `frequency` lies inside the edges of `band`.
+    pub fn check(&self, band: &str, frequency: Frequency) -> Result<()> {
+        let edges = self.band(band).ok_or_else(|| {
+            if !band.is_empty() {
+                anyhow!("{} is not in the band plan", band)
+            } else if self.band_for(frequency).is_none() {
+                anyhow!("{} MHz is not inside any band in the band plan", frequency)
+            } else {
+                anyhow!("No band selected")
+            }
+        })?;
+        if !edges.contains(frequency) {
+            return Err(anyhow!(
+                "{} MHz is outside {} ({} - {} MHz)",
+                frequency,
+                edges.name,
+                Frequency::from_mhz(edges.lower),
+                Frequency::from_mhz(edges.upper)
+            ));
+        }
+        Ok(())
+    }
+}
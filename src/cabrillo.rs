@@ -0,0 +1,81 @@
+use anyhow::{anyhow, Result};
+
+use crate::mode::Mode;
+use crate::models::LogbookExt;
+
+pub struct CabrilloHeader {
+    pub contest: String,
+    pub location: String,
+    pub category_operator: String,
+    pub category_band: String,
+    pub category_mode: String,
+    pub category_power: String,
+    pub category_station: String,
+    pub sent_exchange: String,
+}
+
+fn cabrillo_mode(mode: &str) -> &'static str {
+    let mode = Mode::from_name(mode).map(|mode| mode.mode).unwrap_or_default();
+    match mode {
+        "CW" => "CW",
+        "SSB" | "AM" => "PH",
+        "FM" => "FM",
+        "RTTY" => "RY",
+        _ => "DG",
+    }
+}
+
+fn cabrillo_frequency(log: &LogbookExt) -> Result<String> {
+    let frequency = log.frequency.ok_or_else(|| {
+        anyhow!("QSO with {} at {} has no frequency", log.call, log.timestamp)
+    })?;
+    Ok(format!("{}", frequency.khz().round() as u64))
+}
+
+fn header_line(out: &mut String, tag: &str, value: &str) {
+    if !value.is_empty() {
+        out.push_str(&format!("{}: {}\n", tag, value));
+    }
+}
+
+/// Writes a Cabrillo 3.0 log for a single station, in the order the QSOs are given.
+///
+/// QSOs logged in a contest carry their own sent and received exchange; for the rest the
+/// header's sent exchange is used and nothing follows the received report.
+pub fn write_cabrillo(header: &CabrilloHeader, logs: &[LogbookExt]) -> Result<String> {
+    let station = logs.first().map(|log| &log.operator);
+    let mut out = String::from("START-OF-LOG: 3.0\n");
+    header_line(&mut out, "CALLSIGN", station.map(|op| op.call.as_str()).unwrap_or(""));
+    header_line(&mut out, "CONTEST", &header.contest);
+    header_line(&mut out, "LOCATION", &header.location);
+    header_line(&mut out, "CATEGORY-OPERATOR", &header.category_operator);
+    header_line(&mut out, "CATEGORY-BAND", &header.category_band);
+    header_line(&mut out, "CATEGORY-MODE", &header.category_mode);
+    header_line(&mut out, "CATEGORY-POWER", &header.category_power);
+    header_line(&mut out, "CATEGORY-STATION", &header.category_station);
+    header_line(&mut out, "GRID-LOCATOR", station.map(|op| op.grid.as_str()).unwrap_or(""));
+    header_line(&mut out, "CREATED-BY", "TUILog 1.0.0");
+    for log in logs {
+        let sent_exchange = if log.exchange_sent.is_empty() {
+            header.sent_exchange.clone()
+        } else {
+            log.exchange_sent.to_string()
+        };
+        let line = format!(
+            "QSO: {:>5} {} {} {:<13} {:<3} {:<6} {:<13} {:<3} {}",
+            cabrillo_frequency(log)?,
+            cabrillo_mode(&log.mode),
+            log.timestamp.format("%Y-%m-%d %H%M"),
+            log.operator.call.to_uppercase(),
+            log.rsttx,
+            sent_exchange,
+            log.call.to_uppercase(),
+            log.rstrx,
+            log.exchange_rcvd,
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out.push_str("END-OF-LOG:\n");
+    Ok(out)
+}
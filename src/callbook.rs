@@ -0,0 +1,190 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+
+use crate::store::SettingsStore;
+
+/// What a callbook knows about a station.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CallbookEntry {
+    pub call: String,
+    pub name: String,
+    pub qth: String,
+    pub state: String,
+    pub grid: String,
+    pub class: String,
+}
+
+/// A source of station details, looked up by callsign.
+pub trait Callbook {
+    fn lookup(&self, call: &str) -> Result<Option<CallbookEntry>>;
+}
+
+/// Looks up `call`, falling back to the home call of a portable callsign such as `VE3/W1AW/P`.
+pub fn lookup(callbook: &dyn Callbook, call: &str) -> Result<Option<CallbookEntry>> {
+    let call = call.trim().to_uppercase();
+    if call.is_empty() {
+        return Ok(None);
+    }
+    if let Some(entry) = callbook.lookup(&call)? {
+        return Ok(Some(entry));
+    }
+    match call.split('/').max_by_key(|part| part.len()) {
+        Some(home) if home != call => callbook.lookup(home),
+        _ => Ok(None),
+    }
+}
+
+/// Opens the callbook configured in the `callbook_file` setting, if there is one.
+pub fn configured(settings: &SettingsStore) -> Result<Option<Box<dyn Callbook>>> {
+    match settings.get("callbook_file")?.filter(|path| !path.trim().is_empty()) {
+        Some(path) if Path::new(path.trim()).exists() => Ok(Some(Box::new(
+            LocalCallbook::open_read_only(Path::new(path.trim()))?,
+        ))),
+        _ => Ok(None),
+    }
+}
+
+/// A callbook dump loaded into its own SQLite file, keyed by callsign.
+pub struct LocalCallbook {
+    connection: Connection,
+}
+
+impl LocalCallbook {
+    /// Opens the callbook at `path`, creating it if needed.
+    pub fn open(path: &Path) -> Result<LocalCallbook> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS callbook (call TEXT PRIMARY KEY, name TEXT, qth TEXT, state TEXT, grid TEXT, class TEXT);",
+        )?;
+        Ok(LocalCallbook { connection })
+    }
+
+    fn open_read_only(path: &Path) -> Result<LocalCallbook> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(LocalCallbook { connection })
+    }
+
+    pub fn len(&self) -> Result<usize> {
+        let count: i64 = self
+            .connection
+            .query_row("SELECT COUNT(*) FROM callbook", (), |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Replaces the callbook with the active licenses in an extracted FCC ULS amateur
+    /// dump (`l_amat.zip`), reading `HD.dat`, `EN.dat` and `AM.dat` from `dir`.
+    ///
+    /// Returns the number of callsigns loaded.
+    pub fn import_uls(&mut self, dir: &Path) -> Result<usize> {
+        // Records are tied together by the unique system identifier in field 1
+        let mut active: HashMap<String, CallbookEntry> = HashMap::new();
+        read_uls(&dir.join("HD.dat"), |fields| {
+            if fields.len() > 5 && fields[5] == "A" {
+                active.insert(
+                    fields[1].to_string(),
+                    CallbookEntry {
+                        call: fields[4].to_uppercase(),
+                        ..Default::default()
+                    },
+                );
+            }
+        })?;
+        read_uls(&dir.join("EN.dat"), |fields| {
+            if fields.len() > 18 {
+                if let Some(entry) = active.get_mut(fields[1]) {
+                    entry.name = if fields[8].is_empty() {
+                        fields[7].to_string()
+                    } else {
+                        [fields[8], fields[9], fields[10], fields[11]]
+                            .iter()
+                            .filter(|part| !part.is_empty())
+                            .cloned()
+                            .collect::<Vec<&str>>()
+                            .join(" ")
+                    };
+                    entry.qth = fields[16].to_string();
+                    entry.state = fields[17].to_string();
+                }
+            }
+        })?;
+        read_uls(&dir.join("AM.dat"), |fields| {
+            if fields.len() > 5 {
+                if let Some(entry) = active.get_mut(fields[1]) {
+                    entry.class = match fields[5] {
+                        "E" => "Extra",
+                        "A" => "Advanced",
+                        "G" => "General",
+                        "T" => "Technician",
+                        "P" => "Technician Plus",
+                        "N" => "Novice",
+                        other => other,
+                    }
+                    .to_string();
+                }
+            }
+        })?;
+        let tx = self.connection.transaction()?;
+        tx.execute("DELETE FROM callbook", ())?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO callbook (call, name, qth, state, grid, class) VALUES (?, ?, ?, ?, ?, ?)",
+            )?;
+            for entry in active.values() {
+                stmt.execute((
+                    &entry.call,
+                    &entry.name,
+                    &entry.qth,
+                    &entry.state,
+                    &entry.grid,
+                    &entry.class,
+                ))?;
+            }
+        }
+        tx.commit()?;
+        self.len()
+    }
+}
+
+fn read_uls(path: &Path, mut record: impl FnMut(&[&str])) -> Result<()> {
+    let file = File::open(path).map_err(|err| anyhow!("Could not open {}: {}", path.display(), err))?;
+    for line in BufReader::new(file).split(b'\n') {
+        // ULS dumps are not reliably UTF-8
+        let line = String::from_utf8_lossy(&line?).trim_end().to_string();
+        let fields: Vec<&str> = line.split('|').collect();
+        record(&fields);
+    }
+    Ok(())
+}
+
+impl Callbook for LocalCallbook {
+    fn lookup(&self, call: &str) -> Result<Option<CallbookEntry>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT call, name, qth, state, grid, class FROM callbook WHERE call = ?",
+                (call.to_uppercase(),),
+                |row| {
+                    Ok(CallbookEntry {
+                        call: row.get(0)?,
+                        name: row.get(1)?,
+                        qth: row.get(2)?,
+                        state: row.get(3)?,
+                        grid: row.get(4)?,
+                        class: row.get(5)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+}
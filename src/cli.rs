@@ -0,0 +1,410 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use rusqlite::Connection;
+
+use crate::log::worked_before_text;
+use tuilog::adif_io::{import_adif, write_adif};
+use tuilog::bandplan::BandPlan;
+use tuilog::callbook::{self, LocalCallbook};
+use tuilog::dxcc::{self, CountryFile};
+use tuilog::frequency::Frequency;
+use tuilog::lotw;
+use tuilog::mode::Mode;
+use tuilog::qso::{is_dupe, parse_timestamp, LogForm};
+use tuilog::report::Report;
+use tuilog::settings::DupeSettings;
+use tuilog::store::{ProfileStore, QsoFilter, QsoStore, SettingsStore};
+
+/// Simple cross-platform ham radio logger. Runs the TUI when no command is given.
+#[derive(Parser)]
+#[command(name = "tuilog", version)]
+pub struct Cli {
+    /// Logbook database to use instead of ~/.tuilog/tuilog.db
+    #[arg(long, global = true, env = "TUILOG_DB", value_name = "FILE")]
+    pub db: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// Parsed once at startup, so the size of the Add variant does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+pub enum Command {
+    /// Log a QSO at the current UTC time
+    Add {
+        /// Callsign of the contacted station
+        call: String,
+        /// Operator profile id (see `tuilog profiles`)
+        #[arg(long)]
+        profile: u64,
+        /// Band name, worked out from the frequency when omitted
+        #[arg(long)]
+        band: Option<String>,
+        /// Frequency in MHz, or with a kHz/Hz unit such as "7074 kHz"
+        #[arg(long)]
+        frequency: String,
+        #[arg(long)]
+        mode: String,
+        /// Report sent, defaulting to 59, 599 or -10 by mode
+        #[arg(long, allow_hyphen_values = true)]
+        rst_sent: Option<String>,
+        /// Report received, defaulting to 59, 599 or -10 by mode
+        #[arg(long, allow_hyphen_values = true)]
+        rst_rcvd: Option<String>,
+        /// Power in watts
+        #[arg(long, default_value = "")]
+        power: String,
+        #[arg(long, default_value = "")]
+        comments: String,
+        /// Start of the QSO, YYYY-MM-DD HH:MM:SS (defaults to the end time)
+        #[arg(long)]
+        time_on: Option<String>,
+        /// End of the QSO, YYYY-MM-DD HH:MM:SS (defaults to now)
+        #[arg(long)]
+        time_off: Option<String>,
+        /// Grid square of the contacted station, e.g. FN31pr
+        #[arg(long, default_value = "")]
+        grid: String,
+        /// Contest session id to log the QSO in, which numbers and dupe-checks it
+        #[arg(long)]
+        contest: Option<u64>,
+        /// Exchange received in the contest, e.g. a serial, state, zone or "2A EMA"
+        #[arg(long, default_value = "")]
+        exchange: String,
+    },
+    /// Print logged QSOs, newest first, as tab-separated lines
+    List {
+        /// Start timestamp, YYYY-MM-DD HH:MM:SS
+        #[arg(long)]
+        from: Option<String>,
+        /// End timestamp, YYYY-MM-DD HH:MM:SS
+        #[arg(long)]
+        to: Option<String>,
+        #[arg(long)]
+        profile: Option<u64>,
+    },
+    /// Export QSOs to an ADIF file
+    Export {
+        #[arg(long, value_name = "FILE")]
+        adif: String,
+        /// Start timestamp, YYYY-MM-DD HH:MM:SS
+        #[arg(long)]
+        from: Option<String>,
+        /// End timestamp, YYYY-MM-DD HH:MM:SS
+        #[arg(long)]
+        to: Option<String>,
+        #[arg(long)]
+        profile: Option<u64>,
+    },
+    /// Import QSOs from an ADIF file
+    Import {
+        file: String,
+        /// Attach every QSO to this profile instead of matching STATION_CALLSIGN
+        #[arg(long)]
+        profile: Option<u64>,
+    },
+    /// Sign a profile's QSOs for LoTW into a .tq8 file and mark them sent
+    Lotw {
+        /// File to write, e.g. upload.tq8
+        #[arg(long, value_name = "FILE")]
+        out: String,
+        /// Profile whose certificate and station location sign the QSOs
+        #[arg(long)]
+        profile: u64,
+        /// Start timestamp, YYYY-MM-DD HH:MM:SS
+        #[arg(long)]
+        from: Option<String>,
+        /// End timestamp, YYYY-MM-DD HH:MM:SS
+        #[arg(long)]
+        to: Option<String>,
+        /// Sign QSOs already marked as sent to LoTW too
+        #[arg(long)]
+        resend: bool,
+        /// Password of the profile's .p12 certificate
+        #[arg(long, env = "TUILOG_LOTW_PASSWORD", default_value = "", hide_env_values = true)]
+        password: String,
+    },
+    /// List operator profiles
+    Profiles,
+    /// Manage the local callbook used to fill in station details
+    Callbook {
+        #[command(subcommand)]
+        action: CallbookCommand,
+    },
+    /// Resolve callsigns to DXCC entities with a cty.dat or cty.csv country file
+    Dxcc {
+        #[command(subcommand)]
+        action: DxccCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DxccCommand {
+    /// Fill in the entity of logged QSOs that lack one, e.g. after downloading a newer
+    /// country file
+    Update {
+        /// Country file to use; remembered for this logbook
+        #[arg(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+        /// Re-resolve every QSO, replacing entity details already logged
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the entity, DXCC number, CQ zone, ITU zone and continent of a callsign
+    Lookup { call: String },
+}
+
+#[derive(Subcommand)]
+pub enum CallbookCommand {
+    /// Load an extracted FCC ULS amateur dump (l_amat.zip), replacing the callbook contents
+    ImportUls {
+        /// Folder holding HD.dat, EN.dat and AM.dat
+        dir: PathBuf,
+        /// Callbook database to create or replace; remembered for this logbook
+        #[arg(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+    },
+    /// Print what the callbook knows about a callsign
+    Lookup { call: String },
+}
+
+pub fn run(command: Command, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    match command {
+        Command::Add {
+            call,
+            profile,
+            band,
+            frequency,
+            mode,
+            rst_sent,
+            rst_rcvd,
+            power,
+            comments,
+            time_on,
+            time_off,
+            grid,
+            contest,
+            exchange,
+        } => {
+            let band = match band {
+                Some(band) => band.to_uppercase(),
+                None => {
+                    let parsed: Frequency = frequency.parse()?;
+                    BandPlan::load(&SettingsStore::new(connection.clone()))?
+                        .band_for(parsed)
+                        .map(|band| band.name.clone())
+                        .ok_or_else(|| anyhow!("{} MHz is not inside any band", parsed))?
+                }
+            };
+            let default_report = Mode::from_name(&mode)
+                .map(|mode| Report::for_mode(&mode).default_report())
+                .unwrap_or_default();
+            let form = LogForm {
+                callsign: call.to_uppercase(),
+                profile,
+                band,
+                frequency,
+                mode: mode.to_uppercase(),
+                rsttx: rst_sent.unwrap_or_else(|| default_report.to_string()),
+                rstrx: rst_rcvd.unwrap_or_else(|| default_report.to_string()),
+                power,
+                comments,
+                time_on: time_on.as_deref().map(parse_timestamp).transpose()?,
+                time_off: time_off.as_deref().map(parse_timestamp).transpose()?,
+                name: String::new(),
+                qth: String::new(),
+                state: String::new(),
+                gridsquare: grid,
+                license_class: String::new(),
+                contest,
+                exchange,
+            };
+            // Checked before inserting, so the new QSO is not one of its own dupes
+            let qsos = QsoStore::new(connection.clone());
+            let worked = qsos.query(&QsoFilter {
+                call: Some(form.callsign.clone()),
+                ..Default::default()
+            })?;
+            let settings = DupeSettings::load(&SettingsStore::new(connection))?;
+            let time = form
+                .time_on
+                .or(form.time_off)
+                .unwrap_or_else(|| Utc::now().naive_utc());
+            let dupe = worked
+                .iter()
+                .any(|entry| is_dupe(&settings, form.contest, entry, &form.band, &form.mode, time));
+            // Refused when dupes are blocked; otherwise only warned about, as in the form
+            qsos.insert_log(&form)?;
+            let text = worked_before_text(&worked, dupe);
+            if !text.is_empty() {
+                eprint!("{}", text);
+            }
+        }
+        Command::List { from, to, profile } => {
+            let from = from.as_deref().map(parse_timestamp).transpose()?;
+            let to = to.as_deref().map(parse_timestamp).transpose()?;
+            let mut out = io::stdout().lock();
+            let logs = QsoStore::new(connection).query(&QsoFilter {
+                start: from,
+                end: to,
+                profile,
+                ..Default::default()
+            })?;
+            for log in logs {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    log.timestamp,
+                    log.call,
+                    log.band,
+                    log.frequency
+                        .map(|frequency| frequency.to_string())
+                        .unwrap_or_default(),
+                    log.mode,
+                    log.rsttx,
+                    log.rstrx,
+                    log.operator.call,
+                    log.comments
+                )?;
+            }
+        }
+        Command::Export {
+            adif,
+            from,
+            to,
+            profile,
+        } => {
+            let from = from.as_deref().map(parse_timestamp).transpose()?;
+            let to = to.as_deref().map(parse_timestamp).transpose()?;
+            let logs = QsoStore::new(connection).query(&QsoFilter {
+                start: from,
+                end: to,
+                profile,
+                ..Default::default()
+            })?;
+            let count = logs.len();
+            let mut file = File::create(&adif)?;
+            file.write_all(write_adif(logs)?.as_bytes())?;
+            println!("Exported {} QSOs to {}", count, adif);
+        }
+        Command::Lotw {
+            out,
+            profile,
+            from,
+            to,
+            resend,
+            password,
+        } => {
+            let from = from.as_deref().map(parse_timestamp).transpose()?;
+            let to = to.as_deref().map(parse_timestamp).transpose()?;
+            let logs = QsoStore::new(connection.clone()).query(&QsoFilter {
+                start: from,
+                end: to,
+                profile: Some(profile),
+                ..Default::default()
+            })?;
+            let count = lotw::sign_to_file(connection, logs, &password, &out, resend)?;
+            println!("Signed {} QSOs into {}", count, out);
+        }
+        Command::Import { file, profile } => {
+            let (imported, skipped) = import_adif(connection, &file, profile)?;
+            println!("Imported {} QSOs, skipped {}", imported, skipped.len());
+            for reason in skipped {
+                eprintln!("{}", reason);
+            }
+        }
+        Command::Callbook { action } => {
+            let settings = SettingsStore::new(connection);
+            match action {
+                CallbookCommand::ImportUls { dir, file } => {
+                    let file = match file {
+                        Some(file) => {
+                            let file = file.to_string_lossy().to_string();
+                            settings.set("callbook_file", &file)?;
+                            file
+                        }
+                        None => settings
+                            .get("callbook_file")?
+                            .filter(|file| !file.is_empty())
+                            .ok_or(anyhow!("No callbook file configured; pass --file"))?,
+                    };
+                    let count = LocalCallbook::open(Path::new(&file))?.import_uls(&dir)?;
+                    println!("Loaded {} callsigns into {}", count, file);
+                }
+                CallbookCommand::Lookup { call } => {
+                    let callbook = callbook::configured(&settings)?
+                        .ok_or(anyhow!("No callbook file configured"))?;
+                    match callbook::lookup(callbook.as_ref(), &call)? {
+                        Some(entry) => println!(
+                            "{}\t{}\t{}\t{}\t{}\t{}",
+                            entry.call, entry.name, entry.qth, entry.state, entry.grid, entry.class
+                        ),
+                        None => return Err(anyhow!("{} is not in the callbook", call)),
+                    }
+                }
+            }
+        }
+        Command::Dxcc { action } => {
+            let settings = SettingsStore::new(connection.clone());
+            match action {
+                DxccCommand::Update { file, force } => {
+                    if let Some(file) = file {
+                        let file = file.to_string_lossy().to_string();
+                        CountryFile::load(&file)?;
+                        settings.set("cty_file", &file)?;
+                    }
+                    let countries = dxcc::configured(&settings)?
+                        .ok_or(anyhow!("No country file configured; pass --file"))?;
+                    let qsos = QsoStore::new(connection);
+                    let mut logs = qsos.query(&QsoFilter::default())?;
+                    let count = logs.len();
+                    logs.retain_mut(|log| {
+                        let entity = countries.resolve(&log.call);
+                        if force {
+                            log.set_entity(entity);
+                            true
+                        } else {
+                            log.fill_entity(entity)
+                        }
+                    });
+                    qsos.update_all(&logs)?;
+                    println!("Updated {} of {} QSOs", logs.len(), count);
+                }
+                DxccCommand::Lookup { call } => {
+                    let countries = dxcc::configured(&settings)?
+                        .ok_or(anyhow!("No country file configured"))?;
+                    match countries.resolve(&call) {
+                        Some(entity) => println!(
+                            "{}\t{}\t{}\t{}\t{}",
+                            entity.name,
+                            entity.dxcc.map(|dxcc| dxcc.to_string()).unwrap_or_default(),
+                            entity.cqz,
+                            entity.ituz,
+                            entity.cont
+                        ),
+                        None => return Err(anyhow!("{} does not match any entity", call)),
+                    }
+                }
+            }
+        }
+        Command::Profiles => {
+            let mut out = io::stdout().lock();
+            for profile in ProfileStore::new(connection).list()? {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}",
+                    profile.id, profile.name, profile.call, profile.grid
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+
+use crate::awards::ModeClass;
+use crate::models::LogbookExt;
+use crate::score::{Multiplier, Scoring};
+
+/// The exchange a contest asks for besides the callsigns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Template {
+    /// Report and a serial number counting up from 1, as in CQ WPX or most sprints
+    SerialRst,
+    /// Report and US state or Canadian province, as in state QSO parties
+    State,
+    /// Report and CQ zone, as in CQ WW
+    CqZone,
+    /// Class and ARRL/RAC section, e.g. `2A EMA`, with no report
+    FieldDay,
+}
+
+impl Template {
+    pub fn all() -> [Template; 4] {
+        [
+            Template::SerialRst,
+            Template::State,
+            Template::CqZone,
+            Template::FieldDay,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Template::SerialRst => "Serial + RST",
+            Template::State => "State/Province + RST",
+            Template::CqZone => "CQ Zone + RST",
+            Template::FieldDay => "Field Day (class + section)",
+        }
+    }
+
+    /// How the template is stored in the `contests` table.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Template::SerialRst => "serial",
+            Template::State => "state",
+            Template::CqZone => "cqzone",
+            Template::FieldDay => "fieldday",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Result<Template> {
+        Template::all()
+            .into_iter()
+            .find(|template| template.key() == key)
+            .ok_or_else(|| anyhow!("Unknown contest template '{}'", key))
+    }
+
+    /// Whether signal reports are part of the exchange.
+    pub fn uses_rst(&self) -> bool {
+        *self != Template::FieldDay
+    }
+
+    /// Title of the exchange field, e.g. `Zone`.
+    pub fn field_title(&self) -> &'static str {
+        match self {
+            Template::SerialRst => "Serial",
+            Template::State => "State/Prov",
+            Template::CqZone => "Zone",
+            Template::FieldDay => "Class Section",
+        }
+    }
+
+    /// A starting point for scoring a contest with this exchange, to be adjusted to its rules.
+    pub fn scoring(&self) -> Scoring {
+        let (rules, multipliers, per_band) = match self {
+            Template::SerialRst => (
+                "dx=3, continent=1, country=1",
+                vec![Multiplier::Prefix],
+                false,
+            ),
+            Template::State => ("phone=1, *=2", vec![Multiplier::Exchange], false),
+            Template::CqZone => (
+                "dx=3, continent=1, country=0",
+                vec![Multiplier::Dxcc, Multiplier::CqZone],
+                true,
+            ),
+            Template::FieldDay => ("phone=1, *=2", Vec::new(), false),
+        };
+        Scoring {
+            rules: Scoring::parse_rules(rules).unwrap_or_default(),
+            multipliers,
+            per_band,
+        }
+    }
+
+    /// Checks an exchange has this template's shape and puts it in the form it is logged in.
+    pub fn parse(&self, value: &str) -> Result<Exchange> {
+        let value = value.trim().to_uppercase();
+        let letters = |part: &str| {
+            (2..=3).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphabetic())
+        };
+        match self {
+            Template::SerialRst => match value.parse::<u32>() {
+                Ok(serial) if serial > 0 => Ok(Exchange {
+                    serial: Some(serial),
+                    text: String::new(),
+                }),
+                _ => Err(anyhow!("'{}' is not a serial number", value)),
+            },
+            Template::State if letters(&value) => Ok(Exchange::text(value)),
+            Template::State => Err(anyhow!("'{}' is not a state or province", value)),
+            Template::CqZone => match value.parse::<u32>() {
+                Ok(zone) if (1..=40).contains(&zone) => Ok(Exchange::text(zone.to_string())),
+                _ => Err(anyhow!("'{}' is not a CQ zone (1-40)", value)),
+            },
+            Template::FieldDay => {
+                let parts: Vec<&str> = value.split_whitespace().collect();
+                let valid = match parts.as_slice() {
+                    [class, section] if class.len() > 1 && class.is_ascii() => {
+                        let (transmitters, category) = class.split_at(class.len() - 1);
+                        transmitters.parse::<u32>().is_ok_and(|count| count > 0)
+                            && ["A", "B", "C", "D", "E", "F"].contains(&category)
+                            && letters(section)
+                    }
+                    _ => false,
+                };
+                if !valid {
+                    return Err(anyhow!(
+                        "'{}' is not a class and section (e.g. 2A EMA)",
+                        value
+                    ));
+                }
+                Ok(Exchange::text(parts.join(" ")))
+            }
+        }
+    }
+}
+
+/// One side of a contest exchange as logged: a serial number (ADIF `STX`/`SRX`) and the
+/// text of any other exchange (`STX_STRING`/`SRX_STRING`).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Exchange {
+    pub serial: Option<u32>,
+    pub text: String,
+}
+
+impl Exchange {
+    fn text(text: String) -> Exchange {
+        Exchange { serial: None, text }
+    }
+
+    /// Reads an exchange typed without a template: a lone number is a serial, anything
+    /// else is text.
+    pub fn from_text(value: &str) -> Exchange {
+        let value = value.trim().to_uppercase();
+        match value.parse::<u32>() {
+            Ok(serial) => Exchange {
+                serial: Some(serial),
+                text: String::new(),
+            },
+            Err(_) => Exchange::text(value),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.serial.is_none() && self.text.is_empty()
+    }
+}
+
+impl fmt::Display for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.serial, self.text.is_empty()) {
+            (Some(serial), true) => write!(f, "{:03}", serial),
+            (Some(serial), false) => write!(f, "{:03} {}", serial, self.text),
+            (None, _) => write!(f, "{}", self.text),
+        }
+    }
+}
+
+/// A contest session. Its QSOs share one run of sent serial numbers and are only dupes
+/// of each other, not of QSOs outside the contest.
+#[derive(Clone, Debug)]
+pub struct Contest {
+    pub id: u64,
+    pub name: String,
+    /// Cabrillo `CONTEST:` name, also logged as ADIF `CONTEST_ID`, e.g. `CQ-WW-CW`
+    pub cabrillo: String,
+    pub template: Template,
+    /// What is sent besides the report and serial, e.g. our state or `1D EMA`
+    pub sent_exchange: String,
+    pub profile: u64,
+    pub started: NaiveDateTime,
+    pub scoring: Scoring,
+}
+
+impl Contest {
+    /// The exchange sent in the QSO given serial number `serial`.
+    pub fn sent(&self, serial: u32) -> Exchange {
+        match self.template {
+            Template::SerialRst => Exchange {
+                serial: Some(serial),
+                text: String::new(),
+            },
+            _ => Exchange::text(self.sent_exchange.clone()),
+        }
+    }
+}
+
+/// Whether a new QSO on `band` and `mode` repeats `log`, its station's earlier QSO in the
+/// same contest. Each station counts once per band and per CW, phone or digital.
+pub fn is_dupe(log: &LogbookExt, band: &str, mode: &str) -> bool {
+    log.band.eq_ignore_ascii_case(band) && ModeClass::of(&log.mode) == ModeClass::of(mode)
+}
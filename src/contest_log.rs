@@ -0,0 +1,583 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use cursive::{
+    align::HAlign,
+    view::{Nameable, Resizable, Scrollable},
+    views::{Button, Checkbox, Dialog, DummyView, EditView, LinearLayout, SelectView, TextView},
+    Cursive,
+};
+use cursive_aligned_view::Alignable;
+use rusqlite::Connection;
+
+use crate::log::{
+    check_dupes, current_profile, field_text, form_label, profile_label, radio_row, set_mode,
+    set_reports,
+};
+use crate::rig;
+use tuilog::contest::{Contest, Template};
+use tuilog::dxcc;
+use tuilog::mode::Mode;
+use tuilog::qso::LogForm;
+use tuilog::score::{rate, Multiplier, Scoring, Station};
+use tuilog::store::{ContestStore, ProfileStore, QsoFilter, QsoStore, SettingsStore};
+
+// QSOs listed under the entry fields
+const RECENT_QSOS: usize = 8;
+
+// How often the score panel is redrawn, so the rates fall while nothing is logged
+const SCORE_REFRESH: Duration = Duration::from_secs(30);
+
+// Bumped whenever a contest form opens or closes, which stops the refresh thread of the
+// form before it
+static FORM: AtomicU64 = AtomicU64::new(0);
+
+/// Lists the contest sessions to carry on with, and starts new ones.
+pub fn contests(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let open_connection = connection.clone();
+    let new_connection = connection.clone();
+    let qsos = QsoStore::new(connection.clone());
+    let mut select = SelectView::<u64>::new().h_align(HAlign::Left);
+    for contest in ContestStore::new(connection).list()? {
+        let count = qsos
+            .rows(&QsoFilter {
+                contest: Some(contest.id),
+                ..Default::default()
+            })?
+            .len();
+        select.add_item(
+            format!(
+                "{}  {}  ({}, {} QSOs)",
+                contest.started.format("%Y-%m-%d"),
+                contest.name,
+                contest.template.name(),
+                count
+            ),
+            contest.id,
+        );
+    }
+    select.set_on_submit(move |s, id: &u64| {
+        s.pop_layer();
+        if let Err(err) = contest_log(s, open_connection.clone(), *id) {
+            s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+        }
+    });
+    s.pop_layer();
+    s.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(
+                    "Press Enter on a contest to carry on logging it",
+                ))
+                .child(DummyView)
+                .child(select.scrollable().fixed_height(10)),
+        )
+        .button("New Contest", move |s| {
+            if let Err(err) = new_contest(s, new_connection.clone()) {
+                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+            }
+        })
+        .button("Close", |s| {
+            s.pop_layer();
+        })
+        .title("Contests"),
+    );
+    Ok(())
+}
+
+fn selected_template(s: &mut Cursive) -> Option<Template> {
+    s.call_on_name("contest_template", |view: &mut SelectView<Template>| {
+        view.selection().map(|template| *template)
+    })
+    .flatten()
+}
+
+fn selected_profile(s: &mut Cursive) -> Option<u64> {
+    s.call_on_name("contest_profile", |view: &mut SelectView<u64>| {
+        view.selection().map(|profile| *profile)
+    })
+    .flatten()
+}
+
+/// Fills in the sent exchange a template takes from the profile, its state or CQ zone.
+/// Serial number contests send nothing else, so the field is disabled for them.
+fn prefill_sent(s: &mut Cursive, connection: Arc<Mutex<Connection>>) {
+    let template = selected_template(s).unwrap_or(Template::SerialRst);
+    let profile = selected_profile(s).and_then(|id| ProfileStore::new(connection).get(id).ok());
+    let sent = match (template, profile) {
+        (Template::State, Some(profile)) => profile.state,
+        (Template::CqZone, Some(profile)) => profile.cqz,
+        _ => String::new(),
+    };
+    s.call_on_name("contest_sent", |view: &mut EditView| {
+        view.set_content(sent);
+        view.set_enabled(template != Template::SerialRst);
+    });
+}
+
+/// The points table, multiplier and per-band fields of the New Contest and Scoring dialogs.
+fn scoring_fields(scoring: &Scoring) -> LinearLayout {
+    let mut multipliers = LinearLayout::horizontal();
+    for multiplier in Multiplier::all() {
+        multipliers.add_child(
+            Checkbox::new()
+                .with_checked(scoring.multipliers.contains(&multiplier))
+                .with_name(format!("mult_{}", multiplier.key())),
+        );
+        multipliers.add_child(TextView::new(format!(" {}  ", multiplier.name())));
+    }
+    multipliers.add_child(
+        Checkbox::new()
+            .with_checked(scoring.per_band)
+            .with_name("mults_per_band"),
+    );
+    multipliers.add_child(TextView::new(" Per band"));
+    LinearLayout::horizontal()
+        .child(
+            Dialog::around(
+                EditView::new()
+                    .content(scoring.rules_text())
+                    .with_name("contest_points")
+                    .fixed_width(30),
+            )
+            .title("Points (e.g. dx=3, continent=1)"),
+        )
+        .child(Dialog::around(multipliers).title("Multipliers"))
+}
+
+fn fill_scoring(s: &mut Cursive, scoring: &Scoring) {
+    s.call_on_name("contest_points", |view: &mut EditView| {
+        view.set_content(scoring.rules_text())
+    });
+    for multiplier in Multiplier::all() {
+        s.call_on_name(
+            &format!("mult_{}", multiplier.key()),
+            |view: &mut Checkbox| view.set_checked(scoring.multipliers.contains(&multiplier)),
+        );
+    }
+    s.call_on_name("mults_per_band", |view: &mut Checkbox| {
+        view.set_checked(scoring.per_band)
+    });
+}
+
+fn read_scoring(s: &mut Cursive) -> Result<Scoring> {
+    let rules = Scoring::parse_rules(&field_text(s, "contest_points"))
+        .map_err(|err| anyhow!("Points: {}", err))?;
+    let multipliers = Multiplier::all()
+        .into_iter()
+        .filter(|multiplier| {
+            s.call_on_name(
+                &format!("mult_{}", multiplier.key()),
+                |view: &mut Checkbox| view.is_checked(),
+            )
+            .unwrap_or(false)
+        })
+        .collect();
+    let per_band = s
+        .call_on_name("mults_per_band", |view: &mut Checkbox| view.is_checked())
+        .unwrap_or(false);
+    Ok(Scoring {
+        rules,
+        multipliers,
+        per_band,
+    })
+}
+
+fn new_contest(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let profiles = ProfileStore::new(connection.clone()).list()?;
+    if profiles.is_empty() {
+        return Err(anyhow!("Add a profile in Options first"));
+    }
+    let current = current_profile(connection.clone())?.map(|profile| profile.id);
+    let template_connection = connection.clone();
+    let profile_connection = connection.clone();
+    let mut templates = SelectView::<Template>::new().popup();
+    for template in Template::all() {
+        templates.add_item(template.name(), template);
+    }
+    templates.set_on_submit(move |s, template: &Template| {
+        prefill_sent(s, template_connection.clone());
+        fill_scoring(s, &template.scoring());
+    });
+    let mut profile_select = SelectView::<u64>::new().popup();
+    for profile in &profiles {
+        profile_select.add_item(profile_label(profile), profile.id);
+    }
+    if let Some(index) = profiles
+        .iter()
+        .position(|profile| Some(profile.id) == current)
+    {
+        profile_select.set_selection(index);
+    }
+    profile_select.set_on_submit(move |s, _: &u64| prefill_sent(s, profile_connection.clone()));
+    let field = |name: &str, title: &str, width: usize| {
+        Dialog::around(EditView::new().with_name(name).fixed_width(width)).title(title)
+    };
+    s.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(
+                    LinearLayout::horizontal()
+                        .child(field("contest_name", "Name", 20))
+                        .child(field(
+                            "contest_cabrillo",
+                            "Cabrillo Contest (e.g. CQ-WW-CW)",
+                            20,
+                        )),
+                )
+                .child(
+                    LinearLayout::horizontal()
+                        .child(
+                            Dialog::around(templates.with_name("contest_template"))
+                                .title("Exchange"),
+                        )
+                        .child(
+                            Dialog::around(profile_select.with_name("contest_profile"))
+                                .title("Profile"),
+                        )
+                        .child(field("contest_sent", "Sent Exchange", 12)),
+                )
+                .child(scoring_fields(&Template::SerialRst.scoring())),
+        )
+        .button("Start", move |s| {
+            if let Err(err) = create_contest(s, connection.clone()) {
+                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+            }
+        })
+        .button("Cancel", |s| {
+            s.pop_layer();
+        })
+        .title("New Contest"),
+    );
+    s.call_on_name("contest_sent", |view: &mut EditView| view.disable());
+    Ok(())
+}
+
+fn create_contest(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let name = field_text(s, "contest_name");
+    if name.is_empty() {
+        return Err(anyhow!("Give the contest a name"));
+    }
+    let template = selected_template(s).ok_or(anyhow!("No exchange selected"))?;
+    let profile = selected_profile(s).ok_or(anyhow!("No profile selected"))?;
+    let sent_exchange = match template {
+        Template::SerialRst => String::new(),
+        template => {
+            template
+                .parse(&field_text(s, "contest_sent"))
+                .map_err(|err| anyhow!("Sent exchange: {}", err))?
+                .text
+        }
+    };
+    let id = ContestStore::new(connection.clone()).insert(&Contest {
+        id: 0,
+        name,
+        cabrillo: field_text(s, "contest_cabrillo").to_uppercase(),
+        template,
+        sent_exchange,
+        profile,
+        started: Utc::now().naive_utc(),
+        scoring: read_scoring(s)?,
+    })?;
+    s.pop_layer();
+    s.pop_layer();
+    contest_log(s, connection, id)
+}
+
+/// Shows the QSO count, the exchange the next QSO sends, the latest QSOs and the claimed
+/// score and rates in the side panel.
+fn refresh_status(s: &mut Cursive, connection: Arc<Mutex<Connection>>, id: u64) -> Result<()> {
+    let contests = ContestStore::new(connection.clone());
+    let contest = contests.get(id)?;
+    let logs = QsoStore::new(connection.clone()).query(&QsoFilter {
+        contest: Some(id),
+        ..Default::default()
+    })?;
+    let next = contest.sent(contests.next_serial(id)?);
+    let status = format!("{} QSOs, next sends {}", logs.len(), next);
+    let mut recent = String::new();
+    for log in logs.iter().take(RECENT_QSOS) {
+        recent.push_str(&format!(
+            "{} {:<12} {:>5} {:<6} {:>8} {}\n",
+            log.timestamp.format("%H%M"),
+            log.call,
+            log.band,
+            log.mode,
+            log.exchange_sent,
+            log.exchange_rcvd
+        ));
+    }
+    let profile = ProfileStore::new(connection.clone()).get(contest.profile)?;
+    let entity = dxcc::resolve(&SettingsStore::new(connection), &profile.call)?;
+    let score = contest
+        .scoring
+        .score(&Station::new(&profile, entity.as_ref()), &logs);
+    let line = |label: &str, value: String| format!("{:<10}{:>8}\n", label, value);
+    let mut panel = line("QSOs", score.qsos.to_string());
+    panel.push_str(&line("Dupes", score.dupes.to_string()));
+    panel.push_str(&line("Points", score.points.to_string()));
+    for (multiplier, count) in &score.mults {
+        panel.push_str(&line(multiplier.name(), count.to_string()));
+    }
+    if !score.mults.is_empty() {
+        panel.push_str(&line("Mults", score.mult_count().to_string()));
+    }
+    panel.push_str(&line("Score", score.total().to_string()));
+    let now = Utc::now().naive_utc();
+    let last_10 = rate(&logs, now, 10);
+    panel.push_str("\nRate\n");
+    panel.push_str(&line("10 min", format!("{} ({}/h)", last_10, last_10 * 6)));
+    panel.push_str(&line("60 min", rate(&logs, now, 60).to_string()));
+    s.call_on_name("contest_status", |view: &mut TextView| {
+        view.set_content(status)
+    });
+    s.call_on_name("contest_recent", |view: &mut TextView| {
+        view.set_content(recent.trim_end())
+    });
+    s.call_on_name("contest_score", |view: &mut TextView| {
+        view.set_content(panel.trim_end())
+    });
+    Ok(())
+}
+
+/// Redraws the score panel every [`SCORE_REFRESH`] until the form is closed or another
+/// contest form opens.
+fn refresh_periodically(s: &mut Cursive, connection: Arc<Mutex<Connection>>, id: u64) {
+    let form = FORM.fetch_add(1, Ordering::SeqCst) + 1;
+    let cb_sink = s.cb_sink().clone();
+    spawn(move || loop {
+        sleep(SCORE_REFRESH);
+        if FORM.load(Ordering::SeqCst) != form {
+            return;
+        }
+        let connection = connection.clone();
+        let sent = cb_sink.send(Box::new(move |s: &mut Cursive| {
+            if s.find_name::<TextView>("contest_score").is_none() {
+                let _ = FORM.compare_exchange(form, form + 1, Ordering::SeqCst, Ordering::SeqCst);
+                return;
+            }
+            let _ = refresh_status(s, connection, id);
+        }));
+        if sent.is_err() {
+            return;
+        }
+    });
+}
+
+/// Lets the points table and multipliers of a running contest be corrected; the score is
+/// worked out afresh from its QSOs.
+fn edit_scoring(s: &mut Cursive, connection: Arc<Mutex<Connection>>, id: u64) -> Result<()> {
+    let contest = ContestStore::new(connection.clone()).get(id)?;
+    s.add_layer(
+        Dialog::around(scoring_fields(&contest.scoring))
+            .button("Save", move |s| {
+                let saved = read_scoring(s).and_then(|scoring| {
+                    ContestStore::new(connection.clone()).set_scoring(id, &scoring)
+                });
+                match saved {
+                    Ok(()) => {
+                        s.pop_layer();
+                        if let Err(err) = refresh_status(s, connection.clone(), id) {
+                            s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                        }
+                    }
+                    Err(err) => {
+                        s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                    }
+                }
+            })
+            .button("Cancel", |s| {
+                s.pop_layer();
+            })
+            .title(format!("Scoring - {}", contest.name)),
+    );
+    Ok(())
+}
+
+fn log_qso(s: &mut Cursive, connection: Arc<Mutex<Connection>>, contest: &Contest) -> Result<()> {
+    let callsign = field_text(s, "callsign").to_uppercase();
+    if callsign.is_empty() {
+        return Err(anyhow!("No callsign"));
+    }
+    let report = |s: &mut Cursive, name: &str| {
+        if contest.template.uses_rst() {
+            field_text(s, name)
+        } else {
+            String::new()
+        }
+    };
+    let form = LogForm {
+        callsign,
+        profile: contest.profile,
+        band: form_label(s, "band"),
+        frequency: field_text(s, "frequency"),
+        mode: form_label(s, "mode"),
+        rsttx: report(s, "rsttx"),
+        rstrx: report(s, "rstrx"),
+        power: String::new(),
+        comments: String::new(),
+        time_on: None,
+        time_off: None,
+        name: String::new(),
+        qth: String::new(),
+        state: String::new(),
+        gridsquare: String::new(),
+        license_class: String::new(),
+        contest: Some(contest.id),
+        exchange: field_text(s, "exchange"),
+    };
+    QsoStore::new(connection.clone()).insert_log(&form)?;
+    for name in ["callsign", "exchange"] {
+        s.call_on_name(name, |view: &mut EditView| view.set_content(""));
+    }
+    s.call_on_name("worked_before", |view: &mut TextView| view.set_content(""));
+    if let Some(mode) = Mode::from_name(&form.mode) {
+        set_reports(s, &mode, true);
+    }
+    refresh_status(s, connection, contest.id)?;
+    if let Ok(result) = s.focus_name("callsign") {
+        result.process(s);
+    }
+    Ok(())
+}
+
+/// Picks up on the band, frequency and mode of the contest's latest QSO.
+fn resume(s: &mut Cursive, connection: Arc<Mutex<Connection>>, contest: &Contest) -> Result<()> {
+    let last = QsoStore::new(connection).query(&QsoFilter {
+        contest: Some(contest.id),
+        ..Default::default()
+    })?;
+    if let Some(last) = last.first() {
+        s.call_on_name("band", |view: &mut Button| {
+            view.set_label(last.band.as_str())
+        });
+        s.call_on_name("frequency", |view: &mut EditView| {
+            view.set_content(
+                last.frequency
+                    .map(|frequency| frequency.to_string())
+                    .unwrap_or_default(),
+            )
+        });
+        if let Some(mode) = Mode::from_name(&last.mode) {
+            set_mode(s, mode);
+        }
+    }
+    Ok(())
+}
+
+/// The fast-entry form for a contest: type the callsign, Enter, type the exchange, Enter,
+/// and the QSO is logged with the next serial and the form is ready for the next one.
+pub fn contest_log(s: &mut Cursive, connection: Arc<Mutex<Connection>>, id: u64) -> Result<()> {
+    let contest = ContestStore::new(connection.clone()).get(id)?;
+    let profile = ProfileStore::new(connection.clone()).get(contest.profile)?;
+    let dupe_connection = connection.clone();
+    let log_connection = connection.clone();
+    let button_connection = connection.clone();
+    let log_contest = contest.clone();
+    let button_contest = contest.clone();
+    let mut entry = LinearLayout::horizontal().child(
+        Dialog::around(
+            EditView::new()
+                .on_edit(move |s, callsign, _| {
+                    if let Err(err) =
+                        check_dupes(s, dupe_connection.clone(), callsign, None, Some(id))
+                    {
+                        s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                    }
+                })
+                .on_submit(|s, _| {
+                    if let Ok(result) = s.focus_name("exchange") {
+                        result.process(s);
+                    }
+                })
+                .with_name("callsign")
+                .fixed_width(12),
+        )
+        .title("Callsign"),
+    );
+    if contest.template.uses_rst() {
+        entry.add_child(
+            Dialog::around(EditView::new().with_name("rsttx").fixed_width(5)).title("RST TX"),
+        );
+        entry.add_child(
+            Dialog::around(EditView::new().with_name("rstrx").fixed_width(5)).title("RST RX"),
+        );
+    }
+    entry.add_child(
+        Dialog::around(
+            EditView::new()
+                .on_submit(move |s, _| {
+                    if let Err(err) = log_qso(s, log_connection.clone(), &log_contest) {
+                        s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                    }
+                })
+                .with_name("exchange")
+                .fixed_width(14),
+        )
+        .title(contest.template.field_title()),
+    );
+    let scoring_connection = connection.clone();
+    let entry_form = LinearLayout::vertical()
+        .child(
+            TextView::new("Rig: off")
+                .with_name("rig_status")
+                .align_center(),
+        )
+        .child(DummyView)
+        .child(radio_row(connection.clone()))
+        .child(entry.align_center())
+        .child(TextView::new("").with_name("worked_before").align_center())
+        .child(DummyView)
+        .child(TextView::new("").with_name("contest_status").align_center())
+        .child(TextView::new("").with_name("contest_recent"))
+        .child(DummyView)
+        .child(
+            TextView::new("Enter on the callsign moves to the exchange; Enter there logs the QSO")
+                .align_center(),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(Button::new("Log", move |s| {
+                    if let Err(err) = log_qso(s, button_connection.clone(), &button_contest) {
+                        s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                    }
+                }))
+                .child(DummyView)
+                .child(Button::new("Scoring", move |s| {
+                    if let Err(err) = edit_scoring(s, scoring_connection.clone(), id) {
+                        s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                    }
+                }))
+                .child(DummyView)
+                .child(Button::new("Close", |s| {
+                    s.pop_layer();
+                }))
+                .align_center(),
+        );
+    s.pop_layer();
+    s.add_layer(
+        Dialog::around(
+            LinearLayout::horizontal().child(entry_form).child(
+                Dialog::around(TextView::new("").with_name("contest_score").fixed_width(18))
+                    .title("Score"),
+            ),
+        )
+        .title(format!("{} - {}", contest.name, profile.call)),
+    );
+    resume(s, connection.clone(), &contest)?;
+    rig::fill(s);
+    refresh_status(s, connection.clone(), id)?;
+    refresh_periodically(s, connection, id);
+    if let Ok(result) = s.focus_name("callsign") {
+        result.process(s);
+    }
+    Ok(())
+}
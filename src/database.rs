@@ -0,0 +1,84 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use cursive::{
+    view::{Nameable, Resizable},
+    views::{Button, Dialog, DummyView, EditView, LinearLayout},
+    Cursive,
+};
+use cursive_aligned_view::Alignable;
+use rusqlite::Connection;
+use tuilog::store;
+
+pub fn set_title(s: &mut Cursive, path: &Path) {
+    s.set_window_title(format!("TUILog - {}", path.display()));
+}
+
+fn switch_logbook(
+    s: &mut Cursive,
+    connection: Arc<Mutex<Connection>>,
+    create: bool,
+) -> Result<()> {
+    let path = s
+        .call_on_name("logbook_path", |view: &mut EditView| view.get_content())
+        .unwrap();
+    if path.trim().is_empty() {
+        return Err(anyhow!("No logbook path received"));
+    }
+    let path = PathBuf::from(path.trim());
+    if create {
+        if path.exists() {
+            return Err(anyhow!("{} already exists", path.display()));
+        }
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+    } else if !path.is_file() {
+        return Err(anyhow!("{} does not exist", path.display()));
+    }
+    store::reopen(&connection, &path)?;
+    set_title(s, &path);
+    // Drop every screen still showing rows from the previous logbook
+    while s.pop_layer().is_some() {}
+    s.add_layer(
+        Dialog::text(format!("Now using {}", path.display())).title("TUILog"),
+    );
+    Ok(())
+}
+
+pub fn open_logbook(s: &mut Cursive, connection: Arc<Mutex<Connection>>, create: bool) {
+    s.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(DummyView)
+                .child(
+                    Dialog::around(
+                        EditView::new()
+                            .with_name("logbook_path")
+                            .fixed_width(40)
+                            .align_center(),
+                    )
+                    .title("File Path"),
+                )
+                .child(DummyView)
+                .child(
+                    LinearLayout::horizontal()
+                        .child(Button::new(if create { "Create" } else { "Open" }, move |s| {
+                            if let Err(err) = switch_logbook(s, connection.clone(), create) {
+                                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                            }
+                        }))
+                        .child(DummyView)
+                        .child(Button::new("Cancel", |s| {
+                            s.pop_layer();
+                        }))
+                        .align_center(),
+                ),
+        )
+        .title(if create { "New Logbook" } else { "Open Logbook" }),
+    );
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::store::SettingsStore;
+
+/// The DXCC entity a callsign belongs to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entity {
+    pub name: String,
+    /// ADIF DXCC entity number; only cty.csv carries these
+    pub dxcc: Option<u32>,
+    pub cqz: u32,
+    pub ituz: u32,
+    pub cont: String,
+}
+
+/// A Country Files prefix list (cty.dat or cty.csv).
+#[derive(Default)]
+pub struct CountryFile {
+    prefixes: HashMap<String, Entity>,
+    calls: HashMap<String, Entity>,
+}
+
+/// Suffixes that say how a station is operating rather than where
+pub const PORTABLE_SUFFIXES: &[&str] = &["P", "M", "AM", "QRP", "QRPP", "A", "B", "LH", "R", "J"];
+
+impl CountryFile {
+    pub fn load(path: &str) -> Result<CountryFile> {
+        let data = fs::read(path).map_err(|err| anyhow!("Could not read {}: {}", path, err))?;
+        CountryFile::parse(&String::from_utf8_lossy(&data))
+            .map_err(|err| anyhow!("Invalid country file {}: {}", path, err))
+    }
+
+    /// Parses either format, telling them apart by the first record.
+    pub fn parse(data: &str) -> Result<CountryFile> {
+        let first = data
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("");
+        let mut file = CountryFile::default();
+        if first
+            .split(',')
+            .nth(2)
+            .is_some_and(|dxcc| dxcc.trim().parse::<u32>().is_ok())
+        {
+            file.parse_csv(data)?;
+        } else {
+            file.parse_dat(data)?;
+        }
+        if file.prefixes.is_empty() {
+            return Err(anyhow!("no entities found"));
+        }
+        Ok(file)
+    }
+
+    fn parse_dat(&mut self, data: &str) -> Result<()> {
+        for record in data.split(';') {
+            if record.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = record.splitn(9, ':').collect();
+            if fields.len() < 9 {
+                return Err(anyhow!("malformed record '{}'", record.trim()));
+            }
+            let entity = Entity {
+                name: fields[0].trim().to_string(),
+                dxcc: None,
+                cqz: number(fields[1])?,
+                ituz: number(fields[2])?,
+                cont: fields[3].trim().to_string(),
+            };
+            self.add_aliases(&entity, fields[8].split(','));
+        }
+        Ok(())
+    }
+
+    fn parse_csv(&mut self, data: &str) -> Result<()> {
+        for line in data.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.splitn(10, ',').collect();
+            if fields.len() < 10 {
+                return Err(anyhow!("malformed line '{}'", line.trim()));
+            }
+            let entity = Entity {
+                name: fields[1].trim().to_string(),
+                dxcc: Some(number(fields[2])?),
+                cqz: number(fields[4])?,
+                ituz: number(fields[5])?,
+                cont: fields[3].trim().to_string(),
+            };
+            self.add_aliases(&entity, fields[9].trim_end_matches(';').split(' '));
+        }
+        Ok(())
+    }
+
+    /// Adds each alias of `entity`. Aliases starting with `=` are exact callsigns, and
+    /// `(cq)`, `[itu]` and `{cont}` override the entity's zones and continent.
+    fn add_aliases<'a>(&mut self, entity: &Entity, aliases: impl Iterator<Item = &'a str>) {
+        for alias in aliases {
+            let alias = alias.trim();
+            if alias.is_empty() {
+                continue;
+            }
+            let end = alias.find(['(', '[', '<', '{', '~']).unwrap_or(alias.len());
+            let mut resolved = entity.clone();
+            if let Some(cqz) = enclosed(alias, '(', ')').and_then(|cqz| cqz.parse().ok()) {
+                resolved.cqz = cqz;
+            }
+            if let Some(ituz) = enclosed(alias, '[', ']').and_then(|ituz| ituz.parse().ok()) {
+                resolved.ituz = ituz;
+            }
+            if let Some(cont) = enclosed(alias, '{', '}') {
+                resolved.cont = cont.to_string();
+            }
+            match alias[..end].strip_prefix('=') {
+                Some(call) => self.calls.insert(call.to_uppercase(), resolved),
+                None => self.prefixes.insert(alias[..end].to_uppercase(), resolved),
+            };
+        }
+    }
+
+    fn longest_prefix(&self, call: &str) -> Option<&Entity> {
+        (1..=call.len())
+            .rev()
+            .filter(|len| call.is_char_boundary(*len))
+            .find_map(|len| self.prefixes.get(&call[..len]))
+    }
+
+    /// Resolves a callsign to its entity.
+    ///
+    /// Exact-call entries win. Otherwise, for portable calls such as `VP2E/W1AW` or
+    /// `W1AW/VP2E` the side that is a listed prefix (or else the shorter side) is used,
+    /// operating suffixes like `/P` are ignored, and `/MM` has no entity.
+    pub fn resolve(&self, call: &str) -> Option<&Entity> {
+        let call = call.trim().to_uppercase();
+        if call.is_empty() {
+            return None;
+        }
+        if let Some(entity) = self.calls.get(&call) {
+            return Some(entity);
+        }
+        let parts: Vec<&str> = call
+            .split('/')
+            .filter(|part| !part.is_empty() && !PORTABLE_SUFFIXES.contains(part))
+            .collect();
+        if call.split('/').any(|part| part == "MM") {
+            return None;
+        }
+        match parts.as_slice() {
+            [] => None,
+            [single] => self
+                .calls
+                .get(*single)
+                .or_else(|| self.longest_prefix(single)),
+            [home, area] if area.len() == 1 && area.chars().all(|c| c.is_ascii_digit()) => {
+                // A call area change such as W1AW/4 stays in the same entity
+                self.calls.get(*home).or_else(|| self.longest_prefix(home))
+            }
+            [first, second, ..] => {
+                // Prefer a side that is itself a listed prefix, then the shorter side
+                let prefix = if self.prefixes.contains_key(*second)
+                    || (!self.prefixes.contains_key(*first) && second.len() < first.len())
+                {
+                    second
+                } else {
+                    first
+                };
+                self.longest_prefix(prefix)
+            }
+        }
+    }
+}
+
+fn number(field: &str) -> Result<u32> {
+    field
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("'{}' is not a number", field.trim()))
+}
+
+fn enclosed(alias: &str, open: char, close: char) -> Option<&str> {
+    let start = alias.find(open)? + 1;
+    let end = alias[start..].find(close)? + start;
+    Some(&alias[start..end])
+}
+
+type Cached = (PathBuf, Option<SystemTime>, Arc<CountryFile>);
+
+/// Loads the country file named by the `cty_file` setting, if any.
+///
+/// The parsed file is cached and only re-read when the path or its modification time changes,
+/// so it can be called on every keystroke.
+pub fn configured(settings: &SettingsStore) -> Result<Option<Arc<CountryFile>>> {
+    static CACHE: OnceLock<Mutex<Option<Cached>>> = OnceLock::new();
+    let path = match settings
+        .get("cty_file")?
+        .filter(|path| !path.trim().is_empty())
+    {
+        Some(path) => PathBuf::from(path.trim()),
+        None => return Ok(None),
+    };
+    let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+    let mut cache = CACHE
+        .get_or_init(|| Mutex::new(None))
+        .lock()
+        .map_err(|_| anyhow!("Could not lock country file cache"))?;
+    if let Some((cached_path, cached_modified, file)) = cache.as_ref() {
+        if *cached_path == path && *cached_modified == modified {
+            return Ok(Some(file.clone()));
+        }
+    }
+    let file = Arc::new(CountryFile::load(&path.to_string_lossy())?);
+    *cache = Some((path, modified, file.clone()));
+    Ok(Some(file))
+}
+
+/// Resolves `call` with the configured country file, if there is one.
+pub fn resolve(settings: &SettingsStore, call: &str) -> Result<Option<Entity>> {
+    Ok(configured(settings)?.and_then(|file| file.resolve(call).cloned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> CountryFile {
+        CountryFile::parse(include_str!("../tests/fixtures/cty.dat")).unwrap()
+    }
+
+    fn name(file: &CountryFile, call: &str) -> Option<String> {
+        file.resolve(call).map(|entity| entity.name.clone())
+    }
+
+    #[test]
+    fn prefixes_resolve_to_the_longest_match() {
+        let file = fixture();
+        let entity = file.resolve("k1abc").unwrap();
+        assert_eq!(entity.name, "United States");
+        assert_eq!(
+            (entity.cqz, entity.ituz, entity.cont.as_str()),
+            (5, 8, "NA")
+        );
+        assert_eq!(entity.dxcc, None);
+        assert_eq!(name(&file, "KG4XY").as_deref(), Some("Guantanamo Bay"));
+        assert_eq!(name(&file, "2E0ABC").as_deref(), Some("England"));
+        assert_eq!(name(&file, "ZL1ABC"), None);
+    }
+
+    #[test]
+    fn exact_calls_and_zone_overrides() {
+        let file = fixture();
+        let entity = file.resolve("KG4AB").unwrap();
+        assert_eq!(entity.name, "United States");
+        assert_eq!((entity.cqz, entity.ituz), (5, 8));
+        // Only the exact call is overridden, not calls it is a prefix of
+        assert_eq!(name(&file, "KG4ABC").as_deref(), Some("Guantanamo Bay"));
+
+        let entity = file.resolve("W6XYZ").unwrap();
+        assert_eq!(entity.name, "United States");
+        assert_eq!((entity.cqz, entity.ituz), (3, 6));
+
+        let entity = file.resolve("GB0SEA").unwrap();
+        assert_eq!(entity.name, "England");
+        assert_eq!(
+            (entity.cqz, entity.ituz, entity.cont.as_str()),
+            (14, 28, "AF")
+        );
+    }
+
+    #[test]
+    fn portable_calls() {
+        let file = fixture();
+        assert_eq!(name(&file, "VP2E/W1AW").as_deref(), Some("Anguilla"));
+        assert_eq!(name(&file, "W1AW/VP2E").as_deref(), Some("Anguilla"));
+        assert_eq!(name(&file, "PA/G4ABC").as_deref(), Some("Netherlands"));
+        assert_eq!(name(&file, "W1AW/P").as_deref(), Some("United States"));
+        assert_eq!(name(&file, "W6XYZ/QRP").as_deref(), Some("United States"));
+        assert_eq!(file.resolve("W6XYZ/7").unwrap().cqz, 3);
+        assert_eq!(name(&file, "W1AW/MM"), None);
+        assert_eq!(name(&file, "G4ABC/P/MM"), None);
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        assert!(CountryFile::parse("United States: 05: 08: NA;").is_err());
+        assert!(CountryFile::parse("").is_err());
+    }
+}
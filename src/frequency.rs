@@ -0,0 +1,126 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Error};
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+
+/// A radio frequency, stored as a whole number of hertz.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Frequency(pub u64);
+
+impl Frequency {
+    pub fn from_mhz(mhz: f64) -> Frequency {
+        Frequency((mhz * 1_000_000.0).round() as u64)
+    }
+
+    pub fn hz(self) -> u64 {
+        self.0
+    }
+
+    pub fn khz(self) -> f64 {
+        self.0 as f64 / 1_000.0
+    }
+
+    pub fn mhz(self) -> f64 {
+        self.0 as f64 / 1_000_000.0
+    }
+}
+
+/// Parses a frequency in MHz, or in GHz, kHz or Hz when the unit is given (`14.074`, `7074 kHz`).
+impl FromStr for Frequency {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Frequency, Error> {
+        let lower = text.trim().to_lowercase();
+        let (number, scale) = [("ghz", 1e9), ("mhz", 1e6), ("khz", 1e3), ("hz", 1.0)]
+            .iter()
+            .find_map(|(unit, scale)| lower.strip_suffix(unit).map(|number| (number, *scale)))
+            .unwrap_or((lower.as_str(), 1e6));
+        let number = number.trim();
+        let valid = number.chars().any(|c| c.is_ascii_digit())
+            && number.chars().all(|c| c.is_ascii_digit() || c == '.')
+            && number.matches('.').count() <= 1;
+        let value: f64 = if valid { number.parse().ok() } else { None }
+            .ok_or_else(|| anyhow!("'{}' is not a frequency", text.trim()))?;
+        let hz = (value * scale).round();
+        if hz < 1.0 {
+            return Err(anyhow!("'{}' is not a frequency", text.trim()));
+        }
+        Ok(Frequency(hz as u64))
+    }
+}
+
+/// Shows the frequency in MHz with at least three decimal places, e.g. `14.074` or `0.4755`.
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fraction = format!("{:06}", self.0 % 1_000_000);
+        let fraction = fraction.trim_end_matches('0');
+        write!(f, "{}.{:0<3}", self.0 / 1_000_000, fraction)
+    }
+}
+
+impl ToSql for Frequency {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        self.0.to_sql()
+    }
+}
+
+impl FromSql for Frequency {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Frequency> {
+        u64::column_result(value).map(Frequency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mhz_unless_a_unit_is_given() {
+        for (text, hz) in [
+            ("14.074", 14_074_000),
+            (" 7 ", 7_000_000),
+            ("10.1 MHz", 10_100_000),
+            ("7030 kHz", 7_030_000),
+            ("475500Hz", 475_500),
+            ("10.368 GHz", 10_368_000_000),
+            ("14.0740004", 14_074_000),
+        ] {
+            assert_eq!(
+                text.parse::<Frequency>().unwrap(),
+                Frequency(hz),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_text_that_is_not_a_frequency() {
+        for text in [
+            "14.0.7",
+            "fourteen",
+            "",
+            ".",
+            "kHz",
+            "-14",
+            "14 MHz 2",
+            "0.0000001",
+        ] {
+            assert!(text.parse::<Frequency>().is_err(), "{:?} parsed", text);
+        }
+        assert_eq!(
+            "14.0.7".parse::<Frequency>().unwrap_err().to_string(),
+            "'14.0.7' is not a frequency"
+        );
+    }
+
+    #[test]
+    fn displays_mhz_with_at_least_three_decimals() {
+        assert_eq!(Frequency(14_074_000).to_string(), "14.074");
+        assert_eq!(Frequency(7_000_000).to_string(), "7.000");
+        assert_eq!(Frequency(475_500).to_string(), "0.4755");
+        assert_eq!(Frequency(14_074_150).to_string(), "14.07415");
+        assert_eq!(Frequency(1).to_string(), "0.000001");
+        assert_eq!(Frequency::from_mhz(3.5735).to_string(), "3.5735");
+    }
+}
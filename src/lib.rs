@@ -0,0 +1,22 @@
+pub mod adif_io;
+pub mod awards;
+pub mod bandplan;
+pub mod cabrillo;
+pub mod callbook;
+pub mod contest;
+pub mod dxcc;
+pub mod frequency;
+pub mod lotw;
+pub mod maidenhead;
+pub mod migrations;
+pub mod mode;
+pub mod models;
+pub mod n1mm;
+pub mod qsl;
+pub mod qso;
+pub mod report;
+pub mod rigctld;
+pub mod score;
+pub mod settings;
+pub mod store;
+pub mod wsjtx;
@@ -1,29 +1,259 @@
 use std::sync::{Arc, Mutex};
 
 use anyhow::{anyhow, Result};
-use chrono::Utc;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
 use cursive::{
     align::HAlign,
-    view::{Nameable, Resizable},
-    views::{Button, Dialog, DummyView, EditView, LinearLayout, SelectView},
-    Cursive,
+    view::{Nameable, Resizable, Scrollable},
+    views::{Button, Dialog, DummyView, EditView, LinearLayout, NamedView, SelectView, TextView},
+    Cursive, View,
 };
 use cursive_aligned_view::Alignable;
 use rusqlite::Connection;
 
-use crate::models::OperatorConfig;
+use crate::logbook::refresh_table;
+use crate::rig;
+use tuilog::bandplan::{Band, BandPlan};
+use tuilog::callbook;
+use tuilog::contest::{Exchange, Template};
+use tuilog::dxcc::{self, Entity};
+use tuilog::frequency::Frequency;
+use tuilog::maidenhead::{Locator, Path};
+use tuilog::mode::Mode;
+use tuilog::qsl::{self, Qsl, QslService, RCVD_STATUSES, SENT_STATUSES};
+use tuilog::models::{LogbookExt, OperatorConfig};
+use tuilog::n1mm::Change;
+use tuilog::qso::{
+    announce, checked_edit_mode, checked_frequency, checked_grid, checked_mode, is_dupe,
+    parse_timestamp, LogForm,
+};
+use tuilog::report::Report;
+use tuilog::settings::DupeSettings;
+use tuilog::store::{ContestStore, ProfileStore, QsoFilter, QsoStore, SettingsStore};
+
+fn worked_before(
+    connection: Arc<Mutex<Connection>>,
+    callsign: &str,
+    exclude: Option<u64>,
+) -> Result<Vec<LogbookExt>> {
+    QsoStore::new(connection).query(&QsoFilter {
+        call: Some(callsign.to_string()),
+        exclude,
+        ..Default::default()
+    })
+}
+
+pub fn form_label(s: &mut Cursive, name: &str) -> String {
+    s.call_on_name(name, |view: &mut Button| {
+        view.label()
+            .trim_matches(|c| c == '<' || c == '>')
+            .to_string()
+    })
+    .unwrap_or_default()
+}
+
+pub fn check_dupes(
+    s: &mut Cursive,
+    connection: Arc<Mutex<Connection>>,
+    callsign: &str,
+    exclude: Option<u64>,
+    contest: Option<u64>,
+) -> Result<()> {
+    let band = form_label(s, "band");
+    let mode = form_label(s, "mode");
+    // Until a valid time on is typed, the QSO is taken to be happening now
+    let time = s
+        .call_on_name("time_on", |view: &mut EditView| view.get_content())
+        .and_then(|content| parse_timestamp(&content).ok())
+        .unwrap_or_else(|| Utc::now().naive_utc());
+    let settings = DupeSettings::load(&SettingsStore::new(connection.clone()))?;
+    let entries = if callsign.trim().is_empty() {
+        Vec::new()
+    } else {
+        worked_before(connection.clone(), callsign, exclude)?
+    };
+    let mut text = String::new();
+    match dxcc::resolve(&SettingsStore::new(connection.clone()), callsign) {
+        Ok(Some(entity)) => text.push_str(&format!("{}\n", entity_text(&entity))),
+        Ok(None) => {}
+        // A broken country file should not stop logging
+        Err(err) => text.push_str(&format!("{}\n", err)),
+    }
+    let dupe = entries
+        .iter()
+        .any(|entry| is_dupe(&settings, contest, entry, &band, &mode, time));
+    text.push_str(&worked_before_text(&entries, dupe));
+    s.call_on_name("worked_before", |view: &mut TextView| {
+        view.set_content(text.trim_end());
+    });
+    Ok(())
+}
+
+/// `DUPE` when the new QSO would be one, then the latest QSOs with the station, one a line.
+pub fn worked_before_text(entries: &[LogbookExt], dupe: bool) -> String {
+    let mut text = String::new();
+    if dupe {
+        text.push_str("DUPE\n");
+    }
+    for entry in entries.iter().take(5) {
+        text.push_str(&format!(
+            "{} {} {}\n",
+            entry.timestamp.format("%Y-%m-%d"),
+            entry.band,
+            entry.mode
+        ));
+    }
+    if entries.len() > 5 {
+        text.push_str(&format!("+{} more\n", entries.len() - 5));
+    }
+    text
+}
+
+/// One-line summary of an entity, e.g. `Germany (230) CQ 14 ITU 28 EU`.
+pub fn entity_text(entity: &Entity) -> String {
+    let dxcc = entity
+        .dxcc
+        .map(|dxcc| format!(" ({})", dxcc))
+        .unwrap_or_default();
+    format!(
+        "{}{} CQ {} ITU {} {}",
+        entity.name, dxcc, entity.cqz, entity.ituz, entity.cont
+    )
+}
+
+// Re-runs a field's edit callback after something it depends on changed
+fn recheck(s: &mut Cursive, name: &str) {
+    if let Some(cb) = s.call_on_name(name, |view: &mut EditView| {
+        let content = view.get_content();
+        view.set_content(content.as_str())
+    }) {
+        cb(s);
+    }
+}
+
+// Re-runs the callsign field's edit callback so the dupe check sees a new band or mode
+fn recheck_callsign(s: &mut Cursive) {
+    recheck(s, "callsign");
+}
+
+fn form_profile(s: &mut Cursive) -> Option<u64> {
+    s.call_on_name("profile", |view: &mut Button| {
+        let val = view.label();
+        val.find('(').and_then(|parens| val[1..parens].trim().parse().ok())
+    })
+    .flatten()
+}
+
+fn path_text(s: &mut Cursive, connection: Arc<Mutex<Connection>>, grid: &str) -> Result<String> {
+    if grid.trim().is_empty() {
+        return Ok(String::new());
+    }
+    let to: Locator = grid.parse()?;
+    let profile = match form_profile(s) {
+        Some(profile) => ProfileStore::new(connection).get(profile)?,
+        None => return Ok(String::new()),
+    };
+    let from: Locator = profile
+        .grid
+        .parse()
+        .map_err(|err| anyhow!("Profile grid: {}", err))?;
+    Ok(Path::between(&from, &to).to_string())
+}
+
+/// Shows the distance and bearing from the selected profile's grid to the typed grid square.
+fn show_path(s: &mut Cursive, connection: Arc<Mutex<Connection>>, grid: &str) {
+    let text = path_text(s, connection, grid).unwrap_or_else(|err| err.to_string());
+    s.call_on_name("grid_path", |view: &mut TextView| view.set_content(text));
+}
+
+// Station detail fields, in the order shown in the form
+const STATION_FIELDS: [&str; 5] = ["name", "qth", "state", "gridsquare", "license_class"];
+
+pub fn field_text(s: &mut Cursive, name: &str) -> String {
+    s.call_on_name(name, |view: &mut EditView| view.get_content())
+        .unwrap()
+        .trim()
+        .to_string()
+}
+
+/// The callsign last looked up in the callbook, and the station details that put in the form.
+#[derive(Default)]
+struct Lookup {
+    callsign: String,
+    filled: [String; 5],
+}
+
+/// Fills the station details from the callbook while a new QSO's callsign is typed. Only
+/// empty fields and ones still holding the last lookup's details change, so hand-typed
+/// details stay, and nothing is looked up again until the callsign itself changes.
+fn lookup_station(
+    s: &mut Cursive,
+    connection: Arc<Mutex<Connection>>,
+    lookup: &mut Lookup,
+    callsign: &str,
+) -> Result<()> {
+    let callsign = callsign.trim().to_uppercase();
+    if callsign == lookup.callsign {
+        return Ok(());
+    }
+    lookup.callsign = callsign.clone();
+    let entry = match callbook::configured(&SettingsStore::new(connection))? {
+        Some(_) if callsign.is_empty() => None,
+        Some(callbook) => callbook::lookup(callbook.as_ref(), &callsign)?,
+        None => return Ok(()),
+    }
+    .unwrap_or_default();
+    for ((name, value), filled) in STATION_FIELDS
+        .iter()
+        .zip([entry.name, entry.qth, entry.state, entry.grid, entry.class])
+        .zip(lookup.filled.iter_mut())
+    {
+        s.call_on_name(name, |view: &mut EditView| {
+            let content = view.get_content();
+            if content.is_empty() || *content == *filled {
+                view.set_content(value.as_str());
+                view.set_cursor(0);
+                *filled = value;
+            }
+        });
+    }
+    recheck(s, "gridsquare");
+    Ok(())
+}
+
+fn read_time(s: &mut Cursive, name: &str, title: &str) -> Result<Option<NaiveDateTime>> {
+    let content = s
+        .call_on_name(name, |view: &mut EditView| view.get_content())
+        .unwrap();
+    if content.trim().is_empty() {
+        return Ok(None);
+    }
+    parse_timestamp(content.trim())
+        .map(Some)
+        .map_err(|_| anyhow!("{} must be YYYY-MM-DD HH:MM:SS", title))
+}
+
+/// Stamps time-on when a new QSO's callsign is first filled in, and forgets it when the callsign is cleared.
+fn stamp_time_on(s: &mut Cursive, callsign: &str) {
+    s.call_on_name("time_on", |view: &mut EditView| {
+        if callsign.trim().is_empty() {
+            view.set_content("");
+        } else if view.get_content().trim().is_empty() {
+            view.set_content(format_time(Utc::now().naive_utc()));
+            view.set_cursor(0);
+        }
+    });
+}
+
+fn format_time(time: NaiveDateTime) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
 
-fn add_log(s: &mut Cursive, connection: Arc<Mutex<Connection>>) {
+fn read_form(s: &mut Cursive) -> Result<LogForm> {
     let callsign = s
         .call_on_name("callsign", |view: &mut EditView| view.get_content())
         .unwrap();
-    let profile = s
-        .call_on_name("profile", |view: &mut Button| {
-            let val = view.label();
-            let parens = val.find('(').unwrap();
-            val[1..parens].to_string()
-        })
-        .unwrap();
+    let profile = form_profile(s).ok_or(anyhow!("No profile selected"))?;
     let band = s
         .call_on_name("band", |view: &mut Button| {
             view.label()
@@ -31,8 +261,6 @@ fn add_log(s: &mut Cursive, connection: Arc<Mutex<Connection>>) {
                 .to_string()
         })
         .unwrap();
-    let band_str = band.clone();
-    let band_str = band_str.as_str();
     let frequency = s
         .call_on_name("frequency", |view: &mut EditView| view.get_content())
         .unwrap();
@@ -55,252 +283,654 @@ fn add_log(s: &mut Cursive, connection: Arc<Mutex<Connection>>) {
     let comments = s
         .call_on_name("comments", |view: &mut EditView| view.get_content())
         .unwrap();
-    if let Ok(conn) = connection.lock() {
-        let stmt = "INSERT INTO logs (timestamp, call, rsttx, rstrx, band, frequency, mode, power, comments, operator_config) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
-        let timestamp = Utc::now()
-            .naive_utc()
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string();
-        conn.execute(
-            stmt,
-            (
-                timestamp,
-                callsign,
-                rsttx,
-                rstrx,
-                band,
-                frequency,
-                mode,
-                power,
-                comments,
-                profile,
-            ),
-        )
-        .unwrap();
+    let time_on = read_time(s, "time_on", "Time on")?;
+    let time_off = read_time(s, "time_off", "Time off")?;
+    let [name, qth, state, gridsquare, license_class] = STATION_FIELDS.map(|name| field_text(s, name));
+    Ok(LogForm {
+        callsign: callsign.to_string(),
+        profile,
+        band,
+        frequency: frequency.to_string(),
+        mode,
+        rsttx: rsttx.to_string(),
+        rstrx: rstrx.to_string(),
+        power: power.to_string(),
+        comments: comments.to_string(),
+        time_on,
+        time_off,
+        name,
+        qth,
+        state,
+        gridsquare,
+        license_class,
+        contest: None,
+        exchange: String::new(),
+    })
+}
+
+fn fill_form(s: &mut Cursive, log: &LogbookExt) {
+    s.call_on_name("callsign", |view: &mut EditView| {
+        view.set_content(log.call.as_str());
+    });
+    s.call_on_name("profile", |view: &mut Button| {
+        view.set_label(profile_label(&log.operator));
+    });
+    s.call_on_name("band", |view: &mut Button| {
+        view.set_label(log.band.as_str());
+    });
+    s.call_on_name("frequency", |view: &mut EditView| {
+        view.set_content(
+            log.frequency
+                .map(|frequency| frequency.to_string())
+                .unwrap_or_default(),
+        );
+    });
+    s.call_on_name("mode", |view: &mut Button| {
+        view.set_label(log.mode.as_str());
+    });
+    if let Some(mode) = Mode::from_name(&log.mode) {
+        set_reports(s, &mode, false);
     }
+    s.call_on_name("rsttx", |view: &mut EditView| {
+        view.set_content(log.rsttx.as_str());
+    });
+    s.call_on_name("rstrx", |view: &mut EditView| {
+        view.set_content(log.rstrx.as_str());
+    });
+    s.call_on_name("power", |view: &mut EditView| {
+        view.set_content(log.power.as_str());
+    });
+    s.call_on_name("comments", |view: &mut EditView| {
+        view.set_content(log.comments.as_str());
+    });
+    s.call_on_name("time_on", |view: &mut EditView| {
+        view.set_content(format_time(log.timestamp));
+    });
+    s.call_on_name("time_off", |view: &mut EditView| {
+        view.set_content(log.time_off.map(format_time).unwrap_or_default());
+    });
+    for (name, value) in STATION_FIELDS.iter().zip([
+        &log.name,
+        &log.qth,
+        &log.state,
+        &log.gridsquare,
+        &log.license_class,
+    ]) {
+        s.call_on_name(name, |view: &mut EditView| view.set_content(value.as_str()));
+    }
+    for (name, exchange) in [
+        ("exchange_sent", &log.exchange_sent),
+        ("exchange_rcvd", &log.exchange_rcvd),
+    ] {
+        s.call_on_name(name, |view: &mut EditView| view.set_content(exchange.to_string()));
+    }
+    for service in QslService::all() {
+        fill_qsl(s, service, log.qsl(service));
+    }
+    recheck_callsign(s);
+    recheck(s, "gridsquare");
+}
+
+fn add_log(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let form = read_form(s)?;
+    let mode = checked_mode(&form)?;
+    QsoStore::new(connection).insert_log(&form)?;
     s.call_on_name("callsign", |view: &mut EditView| {
         view.set_content("");
     });
     s.call_on_name("comments", |view: &mut EditView| {
         view.set_content("");
     });
-
-    s.call_on_name("rsttx", |view: &mut EditView| {
-        view.set_content(match band_str {
-            "SSB" => "59",
-            "CW" => "599",
-            "FT8" => "599",
-            _ => "59",
+    for name in ["worked_before", "grid_path"] {
+        s.call_on_name(name, |view: &mut TextView| {
+            view.set_content("");
         });
-        view.set_max_content_width(Some(match band_str {
-            "SSB" => 2,
-            "CW" => 3,
-            "FT8" => 3,
-            _ => 2,
-        }));
-    });
-    s.call_on_name("rstrx", |view: &mut EditView| {
-        view.set_content(match band_str {
-            "SSB" => "59",
-            "CW" => "599",
-            "FT8" => "599",
-            _ => "59",
+    }
+    for name in ["time_on", "time_off"].iter().chain(STATION_FIELDS.iter()) {
+        s.call_on_name(name, |view: &mut EditView| {
+            view.set_content("");
         });
-        view.set_max_content_width(Some(match band_str {
-            "SSB" => 2,
-            "CW" => 3,
-            "FT8" => 3,
-            _ => 2,
-        }));
-    });
+    }
+    set_reports(s, &mode, true);
+    // Lets the next QSO's callsign be looked up even if it is the same station again
+    recheck_callsign(s);
+    Ok(())
 }
 
-fn select_band(s: &mut Cursive) {
+fn update_log(s: &mut Cursive, connection: Arc<Mutex<Connection>>, id: u64) -> Result<()> {
+    let form = read_form(s)?;
+    let qsos = QsoStore::new(connection.clone());
+    let mut log = qsos.get(id)?;
+    let frequency = checked_frequency(connection.clone(), &form)?;
+    let mode = checked_edit_mode(&form, &log)?;
+    let gridsquare = checked_grid(&form)?;
+    let time_on = form.time_on.ok_or(anyhow!("Time on is required"))?;
+    if form.time_off.is_some_and(|time_off| time_off < time_on) {
+        return Err(anyhow!("Time off is before time on"));
+    }
+    log.timestamp = time_on;
+    log.time_off = form.time_off;
+    log.name = form.name;
+    log.qth = form.qth;
+    log.state = form.state;
+    log.gridsquare = gridsquare;
+    log.license_class = form.license_class;
+    if log.call != form.callsign {
+        let entity = dxcc::resolve(&SettingsStore::new(connection.clone()), &form.callsign)?;
+        log.set_entity(entity.as_ref());
+    }
+    log.call = form.callsign;
+    log.rsttx = form.rsttx;
+    log.rstrx = form.rstrx;
+    log.band = form.band;
+    log.frequency = Some(frequency);
+    log.mode = mode.name().to_string();
+    log.power = form.power;
+    log.comments = form.comments;
+    for service in QslService::all() {
+        *log.qsl_mut(service) = read_qsl(s, service)?;
+    }
+    let template = match log.contest {
+        Some(id) => Some(ContestStore::new(connection.clone()).get(id)?.template),
+        None => None,
+    };
+    log.exchange_sent = read_exchange(s, "exchange_sent", "Exchange sent", template)?;
+    log.exchange_rcvd = read_exchange(s, "exchange_rcvd", "Exchange received", template)?;
+    log.operator = ProfileStore::new(connection.clone()).get(form.profile)?;
+    log.set_path();
+    qsos.update(&log)?;
+    announce(connection.clone(), Change::Replaced, &log);
+    s.pop_layer();
+    refresh_table(s, connection)
+}
+
+/// Reads an exchange from the edit form, by the contest's template when the QSO has one.
+fn read_exchange(s: &mut Cursive, name: &str, title: &str, template: Option<Template>) -> Result<Exchange> {
+    let value = field_text(s, name);
+    match template {
+        _ if value.is_empty() => Ok(Exchange::default()),
+        Some(template) => template.parse(&value).map_err(|err| anyhow!("{}: {}", title, err)),
+        None => Ok(Exchange::from_text(&value)),
+    }
+}
+
+fn delete_log(s: &mut Cursive, connection: Arc<Mutex<Connection>>, id: u64) -> Result<()> {
+    let qsos = QsoStore::new(connection.clone());
+    let log = qsos.get(id)?;
+    qsos.delete(id)?;
+    announce(connection.clone(), Change::Deleted, &log);
+    s.pop_layer();
+    refresh_table(s, connection)
+}
+
+fn delete(s: &mut Cursive, connection: Arc<Mutex<Connection>>, id: u64) {
+    s.add_layer(Dialog::around(
+        LinearLayout::vertical()
+            .child(TextView::new("Are you sure you want to delete this QSO?"))
+            .child(
+                LinearLayout::horizontal()
+                    .child(Button::new("Yes", move |s| {
+                        s.pop_layer();
+                        if let Err(err) = delete_log(s, connection.clone(), id) {
+                            s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                        }
+                    }))
+                    .child(Button::new("No", |s| {
+                        s.pop_layer();
+                    })),
+            ),
+    ));
+}
+
+fn select_band(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let plan = BandPlan::load(&SettingsStore::new(connection))?;
     let mut select = SelectView::new().h_align(HAlign::Center);
-    select.add_item("160M", "160M");
-    select.add_item("80M", "80M");
-    select.add_item("60M", "60M");
-    select.add_item("40M", "40M");
-    select.add_item("30M", "30M");
-    select.add_item("20M", "20M");
-    select.add_item("17M", "17M");
-    select.add_item("15M", "15M");
-    select.add_item("12M", "12M");
-    select.add_item("10M", "10M");
-    select.add_item("6M", "6M");
-    select.add_item("2M", "2M");
-    select.add_item("70CM", "70CM");
-    select.set_on_submit(|s, band: &str| {
+    for band in plan.bands {
+        select.add_item(band.name.clone(), band);
+    }
+    select.set_on_submit(|s, band: &Band| {
         s.pop_layer();
         s.call_on_name("band", |view: &mut Button| {
-            view.set_label(band);
+            view.set_label(band.name.as_str());
         });
+        let frequency = Frequency::from_mhz(band.default);
         s.call_on_name("frequency", |view: &mut EditView| {
-            view.set_content(match band {
-                "160M" => "1.8",
-                "80M" => "3.5",
-                "60M" => "5.3",
-                "40M" => "7.0",
-                "30M" => "10.1",
-                "20M" => "14.0",
-                "17M" => "18.1",
-                "15M" => "21.0",
-                "12M" => "24.9",
-                "10M" => "28.0",
-                "6M" => "50.0",
-                "2M" => "144.0",
-                "70CM" => "432.0",
-                _ => "14.0",
-            });
+            view.set_content(frequency.to_string());
         });
+        recheck_callsign(s);
+        rig::tune(s, frequency);
     });
     s.add_layer(Dialog::around(select).title("Select Band"));
+    Ok(())
 }
 
-fn select_profile(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
-    let mut select = SelectView::new().h_align(HAlign::Center);
-    if let Ok(conn) = connection.lock() {
-        let mut stmt = conn.prepare("SELECT * FROM operatorconfig ORDER BY id ASC")?;
-        let options_out = stmt.query_map((), |row| {
-            Ok(OperatorConfig {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                call: row.get(2)?,
-                grid: row.get(3)?,
-                cqz: row.get(4)?,
-                ituz: row.get(5)?,
-                dxcc: row.get(6)?,
-                cont: row.get(7)?,
-            })
-        })?;
-        for option in options_out {
-            let option = option?;
-            let label = format!("{} ({})", option.id, option.name);
-            select.add_item(label.clone(), label);
-        }
-    } else {
-        return Err(anyhow!("Could not lock connection"));
+/// Keeps the band button in step with a typed frequency.
+fn sync_band(s: &mut Cursive, connection: Arc<Mutex<Connection>>, frequency: &str) -> Result<()> {
+    let frequency: Frequency = match frequency.parse() {
+        Ok(frequency) => frequency,
+        Err(_) => return Ok(()),
     };
-    select.set_on_submit(|s, profile: &str| {
-        s.pop_layer();
-        s.call_on_name("profile", |view: &mut Button| {
-            view.set_label(profile);
-        });
-    });
-    s.add_layer(Dialog::around(select).title("Select Profile"));
+    let plan = BandPlan::load(&SettingsStore::new(connection))?;
+    let band = plan.band_for(frequency).map(|band| band.name.clone()).unwrap_or_default();
+    if band != form_label(s, "band") {
+        s.call_on_name("band", |view: &mut Button| view.set_label(band));
+        recheck_callsign(s);
+    }
     Ok(())
 }
 
-fn select_mode(s: &mut Cursive) {
+pub fn profile_label(profile: &OperatorConfig) -> String {
+    format!("{} ({})", profile.id, profile.name)
+}
+
+/// Picks the form's profile; with `remember`, it also becomes the current profile that
+/// new forms and QSOs logged from WSJT-X start with.
+fn select_profile(s: &mut Cursive, connection: Arc<Mutex<Connection>>, remember: bool) -> Result<()> {
     let mut select = SelectView::new().h_align(HAlign::Center);
-    select.add_item("SSB", "SSB");
-    select.add_item("USB", "USB");
-    select.add_item("LSB", "LSB");
-    select.add_item("CW", "CW");
-    select.add_item("FT8", "FT8");
-    select.set_on_submit(|s, band: &str| {
+    for option in ProfileStore::new(connection.clone()).list()? {
+        select.add_item(profile_label(&option), option.id);
+    }
+    select.set_on_submit(move |s, profile: &u64| {
         s.pop_layer();
-        s.call_on_name("mode", |view: &mut Button| {
-            view.set_label(band);
-        });
-        s.call_on_name("rsttx", |view: &mut EditView| {
-            view.set_content(match band {
-                "SSB" => "59",
-                "CW" => "599",
-                "FT8" => "599",
-                _ => "59",
-            });
-            view.set_max_content_width(Some(match band {
-                "SSB" => 2,
-                "CW" => 3,
-                "FT8" => 3,
-                _ => 2,
-            }));
-        });
-        s.call_on_name("rstrx", |view: &mut EditView| {
-            view.set_content(match band {
-                "SSB" => "59",
-                "CW" => "599",
-                "FT8" => "599",
-                _ => "59",
+        if remember {
+            let saved = SettingsStore::new(connection.clone()).set("current_profile", &profile.to_string());
+            if let Err(err) = saved {
+                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+            }
+        }
+        if let Ok(profile) = ProfileStore::new(connection.clone()).get(*profile) {
+            s.call_on_name("profile", |view: &mut Button| {
+                view.set_label(profile_label(&profile));
             });
-            view.set_max_content_width(Some(match band {
-                "SSB" => 2,
-                "CW" => 3,
-                "FT8" => 3,
-                _ => 2,
-            }));
+        }
+        recheck(s, "gridsquare");
+    });
+    s.add_layer(Dialog::around(select).title("Select Profile"));
+    Ok(())
+}
+
+fn mode_items(select: &mut SelectView<Mode>, search: &str) {
+    let search = search.trim().to_uppercase();
+    select.clear();
+    for mode in Mode::all().filter(|mode| mode.name().contains(&search) || mode.mode.contains(&search)) {
+        match mode.submode {
+            Some(_) => select.add_item(format!("{} ({})", mode.name(), mode.mode), mode),
+            None => select.add_item(mode.mode, mode),
+        }
+    }
+}
+
+/// Limits the report fields to the width `mode` uses, and resets them to its default report when `reset`.
+pub fn set_reports(s: &mut Cursive, mode: &Mode, reset: bool) {
+    let report = Report::for_mode(mode);
+    for name in ["rsttx", "rstrx"] {
+        s.call_on_name(name, |view: &mut EditView| {
+            if reset {
+                view.set_content(report.default_report());
+            }
+            view.set_max_content_width(Some(report.max_width()));
         });
+    }
+}
+
+/// Shows `mode` in the form, re-checking dupes and resetting the reports to its defaults.
+pub fn set_mode(s: &mut Cursive, mode: Mode) {
+    s.call_on_name("mode", |view: &mut Button| {
+        view.set_label(mode.name());
     });
-    s.add_layer(Dialog::around(select).title("Select Band"));
+    recheck_callsign(s);
+    set_reports(s, &mode, true);
 }
 
-pub fn new_log(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
-    let profile_connection = connection.clone();
+fn pick_mode(s: &mut Cursive, mode: Mode) {
     s.pop_layer();
+    set_mode(s, mode);
+    rig::select_mode(s, mode);
+}
+
+fn select_mode(s: &mut Cursive) {
+    let mut select = SelectView::new().h_align(HAlign::Center);
+    mode_items(&mut select, "");
+    select.set_on_submit(|s, mode: &Mode| pick_mode(s, *mode));
     s.add_layer(
         Dialog::around(
             LinearLayout::vertical()
-                .child(DummyView)
                 .child(
-                    LinearLayout::horizontal().child(
                     Dialog::around(
                         EditView::new()
-                            .with_name("callsign")
-                            .fixed_width(10)
-                            .align_center(),
+                            .on_edit(|s, search, _| {
+                                s.call_on_name("mode_list", |view: &mut SelectView<Mode>| {
+                                    mode_items(view, search)
+                                });
+                            })
+                            .on_submit(|s, _| {
+                                let first = s
+                                    .call_on_name("mode_list", |view: &mut SelectView<Mode>| {
+                                        view.get_item(0).map(|(_, mode)| *mode)
+                                    })
+                                    .flatten();
+                                if let Some(mode) = first {
+                                    pick_mode(s, mode);
+                                }
+                            })
+                            .fixed_width(20),
                     )
-                    .title("Callsign"))
-                    .child(
-                        Dialog::around(Button::new("", move |s| select_profile(s, profile_connection.clone()).unwrap()).with_name("profile"))
-                                .title("Profile")
-                    ).align_center()
+                    .title("Search"),
                 )
+                .child(select.with_name("mode_list").scrollable().fixed_height(15)),
+        )
+        .title("Select Mode"),
+    );
+}
+
+fn status_select(name: String, statuses: &[&'static str]) -> NamedView<SelectView<&'static str>> {
+    let mut select = SelectView::new().popup();
+    for status in statuses {
+        select.add_item(qsl::status_name(status), *status);
+    }
+    select.with_name(name)
+}
+
+/// Sent and received status and dates for one QSL service, named after its columns.
+fn qsl_editor(service: QslService) -> Dialog {
+    let prefix = service.column();
+    Dialog::around(
+        LinearLayout::vertical()
+            .child(
+                LinearLayout::horizontal()
+                    .child(TextView::new("Sent "))
+                    .child(status_select(format!("{}_sent", prefix), SENT_STATUSES))
+                    .child(EditView::new().with_name(format!("{}_sent_date", prefix)).fixed_width(11)),
+            )
+            .child(
+                LinearLayout::horizontal()
+                    .child(TextView::new("Rcvd "))
+                    .child(status_select(format!("{}_rcvd", prefix), RCVD_STATUSES))
+                    .child(EditView::new().with_name(format!("{}_rcvd_date", prefix)).fixed_width(11)),
+            ),
+    )
+    .title(service.name())
+}
+
+fn fill_qsl(s: &mut Cursive, service: QslService, qsl: &Qsl) {
+    let prefix = service.column();
+    for (name, status, date) in [
+        ("sent", &qsl.sent, qsl.sent_date),
+        ("rcvd", &qsl.rcvd, qsl.rcvd_date),
+    ] {
+        s.call_on_name(&format!("{}_{}", prefix, name), |view: &mut SelectView<&'static str>| {
+            let index = view.iter().position(|(_, item)| *item == status.as_str());
+            view.set_selection(index.unwrap_or(0));
+        });
+        s.call_on_name(&format!("{}_{}_date", prefix, name), |view: &mut EditView| {
+            view.set_content(date.map(|date| date.to_string()).unwrap_or_default());
+        });
+    }
+}
+
+fn read_qsl(s: &mut Cursive, service: QslService) -> Result<Qsl> {
+    let prefix = service.column();
+    let status = |s: &mut Cursive, name: &str| {
+        s.call_on_name(&format!("{}_{}", prefix, name), |view: &mut SelectView<&'static str>| {
+            view.selection().map(|status| status.to_string())
+        })
+        .flatten()
+        .unwrap_or(String::from("N"))
+    };
+    let date = |s: &mut Cursive, name: &str| -> Result<Option<NaiveDate>> {
+        let content = field_text(s, &format!("{}_{}_date", prefix, name));
+        if content.is_empty() {
+            return Ok(None);
+        }
+        NaiveDate::parse_from_str(&content, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| anyhow!("{}: '{}' is not a date (YYYY-MM-DD)", service.name(), content))
+    };
+    let mut qsl = Qsl {
+        sent: status(s, "sent"),
+        sent_date: date(s, "sent")?,
+        rcvd: status(s, "rcvd"),
+        rcvd_date: date(s, "rcvd")?,
+    };
+    qsl.fill_dates(Utc::now().date_naive());
+    Ok(qsl)
+}
+
+/// Band, frequency and mode pickers, which the rig keeps in step with the radio.
+pub fn radio_row(connection: Arc<Mutex<Connection>>) -> impl View {
+    let band_connection = connection.clone();
+    let frequency_connection = connection;
+    LinearLayout::horizontal()
+        .child(
+            Dialog::around(
+                Button::new("", move |s| {
+                    if let Err(err) = select_band(s, band_connection.clone()) {
+                        s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                    }
+                })
+                .with_name("band"),
+            )
+                .title("Band"),
+        )
+        .child(
+            Dialog::around(
+                EditView::new()
+                    .on_edit(move |s, frequency, _| {
+                        if let Err(err) = sync_band(s, frequency_connection.clone(), frequency) {
+                            s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                        }
+                    })
+                    .on_submit(|s, frequency| {
+                        if let Ok(frequency) = frequency.parse() {
+                            rig::tune(s, frequency);
+                        }
+                    })
+                    .with_name("frequency")
+                    .fixed_width(10)
+                    .align_center(),
+            )
+                .title("Frequency"),
+        )
+        .child(
+            Dialog::around(Button::new("", select_mode).with_name("mode"))
+                .title("Mode"),
+        ).align_center()
+}
+
+fn log_form(connection: Arc<Mutex<Connection>>, editing: Option<u64>) -> LinearLayout {
+    let dupe_connection = connection.clone();
+    let lookup_connection = connection.clone();
+    let last_lookup = Arc::new(Mutex::new(Lookup::default()));
+    let path_connection = connection.clone();
+    let radio_connection = connection.clone();
+    // An edited contest QSO is dupe-checked against its own contest
+    let contest = editing
+        .and_then(|id| QsoStore::new(connection.clone()).get(id).ok())
+        .and_then(|log| log.contest);
+    let mut form = LinearLayout::vertical();
+    // Rig control only drives the form for new QSOs, never edits of logged ones
+    if editing.is_none() {
+        form.add_child(TextView::new("Rig: off").with_name("rig_status").align_center());
+    }
+    let mut form = form
+        .child(DummyView)
+        .child(
+            LinearLayout::horizontal().child(
+            Dialog::around(
+                EditView::new()
+                    .on_edit(move |s, callsign, _| {
+                        if editing.is_none() {
+                            stamp_time_on(s, callsign);
+                            let mut lookup = last_lookup.lock().unwrap();
+                            if let Err(err) = lookup_station(s, lookup_connection.clone(), &mut lookup, callsign) {
+                                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                            }
+                        }
+                        if let Err(err) = check_dupes(s, dupe_connection.clone(), callsign, editing, contest) {
+                            s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                        }
+                    })
+                    .with_name("callsign")
+                    .fixed_width(10)
+                    .align_center(),
+            )
+            .title("Callsign"))
+            .child(
+                Dialog::around(Button::new("", move |s| {
+                    if let Err(err) = select_profile(s, connection.clone(), editing.is_none()) {
+                        s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                    }
+                }).with_name("profile"))
+                        .title("Profile")
+            ).align_center()
+        )
+        .child(TextView::new("").with_name("worked_before").align_center())
+        .child(radio_row(radio_connection))
+        .child(
+            LinearLayout::horizontal()
                 .child(
-                    LinearLayout::horizontal()
-                        .child(
-                            Dialog::around(Button::new("", select_band).with_name("band"))
-                                .title("Band"),
-                        )
-                        .child(
-                            Dialog::around(EditView::new().with_name("frequency").fixed_width(10).align_center())
-                                .title("Frequency"),
-                        )
-                        .child(
-                            Dialog::around(Button::new("", select_mode).with_name("mode"))
-                                .title("Mode"),
-                        ).align_center(),
+                    Dialog::around(EditView::new().with_name("rsttx").fixed_width(5).align_center())
+                        .title("RST TX"),
                 )
                 .child(
-                    LinearLayout::horizontal()
-                        .child(
-                            Dialog::around(EditView::new().with_name("rsttx").fixed_width(5).align_center())
-                                .title("RST TX"),
-                        )
-                        .child(
-                            Dialog::around(EditView::new().with_name("rstrx").fixed_width(5).align_center())
-                                .title("RST RX"),
-                        )
-                        .child(
-                            Dialog::around(EditView::new().with_name("power").fixed_width(5).align_center())
-                                .title("Power (Watts)"),
-                        ).align_center(),
+                    Dialog::around(EditView::new().with_name("rstrx").fixed_width(5).align_center())
+                        .title("RST RX"),
+                )
+                .child(
+                    Dialog::around(EditView::new().with_name("power").fixed_width(5).align_center())
+                        .title("Power (Watts)"),
+                ).align_center(),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(
+                    Dialog::around(EditView::new().with_name("name").fixed_width(20))
+                        .title("Name"),
+                )
+                .child(
+                    Dialog::around(EditView::new().with_name("qth").fixed_width(15))
+                        .title("QTH"),
+                )
+                .child(
+                    Dialog::around(EditView::new().with_name("state").fixed_width(5))
+                        .title("State"),
                 )
                 .child(
                     Dialog::around(
                         EditView::new()
-                            .with_name("comments")
-                            .fixed_width(20)
-                            .align_center(),
+                            .on_edit(move |s, grid, _| show_path(s, path_connection.clone(), grid))
+                            .with_name("gridsquare")
+                            .fixed_width(8),
                     )
-                    .title("Comments"),
+                    .title("Grid"),
+                )
+                .child(
+                    Dialog::around(EditView::new().with_name("license_class").fixed_width(16))
+                        .title("Class"),
+                ).align_center(),
+        )
+        .child(TextView::new("").with_name("grid_path").align_center())
+        .child(
+            LinearLayout::horizontal()
+                .child(
+                    Dialog::around(EditView::new().with_name("time_on").fixed_width(20))
+                        .title("Time On (UTC)"),
+                )
+                .child(
+                    Dialog::around(EditView::new().with_name("time_off").fixed_width(20))
+                        .title("Time Off (UTC, blank = at Add)"),
+                ).align_center(),
+        )
+        .child(
+            Dialog::around(
+                EditView::new()
+                    .with_name("comments")
+                    .fixed_width(20)
+                    .align_center(),
+            )
+            .title("Comments"),
+        );
+    // QSLs are only exchanged once a QSO is in the log
+    if editing.is_some() {
+        form.add_child(
+            LinearLayout::horizontal()
+                .child(
+                    Dialog::around(EditView::new().with_name("exchange_sent").fixed_width(12))
+                        .title("Exchange Sent"),
                 )
-                .child(DummyView)
+                .child(
+                    Dialog::around(EditView::new().with_name("exchange_rcvd").fixed_width(12))
+                        .title("Exchange Rcvd"),
+                )
+                .align_center(),
+        );
+        let mut qsls = LinearLayout::horizontal();
+        for service in QslService::all() {
+            qsls.add_child(qsl_editor(service));
+        }
+        form.add_child(qsls.align_center());
+    }
+    form.child(DummyView)
+}
+
+pub fn new_log(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let profile_connection = connection.clone();
+    s.pop_layer();
+    s.add_layer(
+        Dialog::around(
+            log_form(profile_connection.clone(), None)
                 .child(Button::new("Add", move |s: &mut Cursive| {
-                    add_log(s, connection.clone())
+                    if let Err(err) = add_log(s, connection.clone()) {
+                        s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                    }
                 })),
         )
         .title("Add Log"),
     );
+    if let Some(profile) = current_profile(profile_connection)? {
+        s.call_on_name("profile", |view: &mut Button| {
+            view.set_label(profile_label(&profile));
+        });
+    }
+    rig::fill(s);
+    Ok(())
+}
+
+/// The profile last picked in the Add Log form, if it still exists.
+pub fn current_profile(connection: Arc<Mutex<Connection>>) -> Result<Option<OperatorConfig>> {
+    let id = SettingsStore::new(connection.clone()).get("current_profile")?;
+    match id.and_then(|id| id.parse().ok()) {
+        Some(id) => Ok(ProfileStore::new(connection).get(id).ok()),
+        None => Ok(None),
+    }
+}
+
+pub fn edit_log(s: &mut Cursive, connection: Arc<Mutex<Connection>>, id: u64) -> Result<()> {
+    let log = QsoStore::new(connection.clone()).get(id)?;
+    let profile_connection = connection.clone();
+    let delete_connection = connection.clone();
+    s.add_layer(
+        Dialog::around(
+            log_form(profile_connection, Some(id)).child(
+                LinearLayout::horizontal()
+                    .child(Button::new("Save", move |s| {
+                        if let Err(err) = update_log(s, connection.clone(), id) {
+                            s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                        }
+                    }))
+                    .child(DummyView)
+                    .child(Button::new("Delete", move |s| {
+                        delete(s, delete_connection.clone(), id);
+                    }))
+                    .child(DummyView)
+                    .child(Button::new("Cancel", |s| {
+                        s.pop_layer();
+                    }))
+                    .align_center(),
+            ),
+        )
+        .title("Edit Log"),
+    );
+    fill_form(s, &log);
     Ok(())
 }
@@ -4,165 +4,532 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use adif::{AdifFile, AdifHeader, AdifRecord, AdifType};
 use anyhow::{anyhow, Result};
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
 use cursive::{
-    view::{Nameable, Resizable},
-    views::{Button, Dialog, DummyView, EditView, LinearLayout, TextView},
+    view::{Nameable, Resizable, Scrollable},
+    views::{
+        Button, Checkbox, Dialog, DummyView, EditView, LinearLayout, OnEventView, SelectView,
+        TextView,
+    },
     Cursive,
 };
 use cursive_aligned_view::Alignable;
 use cursive_table_view::TableView;
-use indexmap::map::IndexMap;
 use rusqlite::Connection;
 
-use crate::models::{Logbook, LogbookColumn, LogbookExt, OperatorConfig};
+use crate::log::edit_log;
+use tuilog::adif_io::{import_adif, write_adif};
+use tuilog::cabrillo::{write_cabrillo, CabrilloHeader};
+use tuilog::lotw;
+use tuilog::models::{Logbook, LogbookColumn, LogbookExt};
+use tuilog::qsl::{self, QslService, RCVD_STATUSES, SENT_STATUSES};
+use tuilog::qso::parse_timestamp;
+use tuilog::store::{ContestStore, ProfileStore, QsoFilter, QsoStore};
+
+fn read_timestamp(s: &mut Cursive, name: &str) -> Result<Option<NaiveDateTime>> {
+    let content = s
+        .call_on_name(name, |view: &mut EditView| view.get_content())
+        .unwrap();
+    if content.is_empty() {
+        return Ok(None);
+    }
+    parse_timestamp(content.as_str()).map(Some)
+}
+
+fn read_export_path(s: &mut Cursive) -> Result<String> {
+    let content = s
+        .call_on_name("export_path", |view: &mut EditView| view.get_content())
+        .unwrap();
+    if content.is_empty() {
+        Err(anyhow!("No export path received"))
+    } else {
+        Ok(content.to_string())
+    }
+}
 
 fn export(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
-    let start_timestamp = s
-        .call_on_name("start_timestamp", |view: &mut EditView| {
-            let content = view.get_content();
-            if content.len() > 0 {
-                Some(NaiveDateTime::parse_from_str(
-                    content.as_str(),
-                    "%Y-%m-%d %H:%M:%S",
-                ))
-            } else {
-                None
-            }
+    let start_timestamp = read_timestamp(s, "start_timestamp")?;
+    let end_timestamp = read_timestamp(s, "end_timestamp")?;
+    let export_path = read_export_path(s)?;
+    let logs = QsoStore::new(connection).query(&QsoFilter {
+        start: start_timestamp,
+        end: end_timestamp,
+        ..Default::default()
+    })?;
+    let mut file = File::create(export_path)?;
+    file.write_all(write_adif(logs)?.as_bytes())?;
+    s.pop_layer();
+    Ok(())
+}
+
+fn export_cabrillo(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let start_timestamp = read_timestamp(s, "start_timestamp")?;
+    let end_timestamp = read_timestamp(s, "end_timestamp")?;
+    let export_path = read_export_path(s)?;
+    let profile = s
+        .call_on_name("cabrillo_profile", |view: &mut SelectView<u64>| {
+            view.selection().map(|profile| *profile)
         })
-        .unwrap();
-    if let Some(Err(start_err)) = start_timestamp {
-        return Err(anyhow!("Could not parse start timestamp: {}", start_err));
+        .unwrap()
+        .ok_or(anyhow!("No profile selected"))?;
+    let contest = s
+        .call_on_name("cabrillo_contest", |view: &mut SelectView<Option<u64>>| {
+            view.selection().and_then(|contest| *contest)
+        })
+        .unwrap()
+        .map(|id| ContestStore::new(connection.clone()).get(id))
+        .transpose()?;
+    let mut field = |name: &str| {
+        s.call_on_name(name, |view: &mut EditView| view.get_content().trim().to_uppercase())
+            .unwrap()
+    };
+    let mut contest_name = field("contest");
+    if contest_name.is_empty() {
+        contest_name = contest
+            .as_ref()
+            .map(|contest| contest.cabrillo.to_uppercase())
+            .unwrap_or_default();
     }
-    let end_timestamp = s
-        .call_on_name("end_timestamp", |view: &mut EditView| {
-            let content = view.get_content();
-            if content.len() > 0 {
-                Some(NaiveDateTime::parse_from_str(
-                    content.as_str(),
-                    "%Y-%m-%d %H:%M:%S",
+    let header = CabrilloHeader {
+        contest: contest_name,
+        location: field("location"),
+        category_operator: field("category_operator"),
+        category_band: field("category_band"),
+        category_mode: field("category_mode"),
+        category_power: field("category_power"),
+        category_station: field("category_station"),
+        sent_exchange: field("sent_exchange"),
+    };
+    let mut logs = QsoStore::new(connection).query(&QsoFilter {
+        start: start_timestamp,
+        end: end_timestamp,
+        profile: Some(profile),
+        contest: contest.map(|contest| contest.id),
+        ..Default::default()
+    })?;
+    logs.reverse();
+    let mut file = File::create(export_path)?;
+    file.write_all(write_cabrillo(&header, &logs)?.as_bytes())?;
+    s.pop_layer();
+    Ok(())
+}
+
+fn lotw_dialog(s: &mut Cursive, connection: Arc<Mutex<Connection>>) {
+    s.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(
+                    "Signs the QSOs marked in the Logbook, or the ones it shows when none are \
+                     marked, with their profile's certificate",
                 ))
-            } else {
-                None
-            }
-        })
+                .child(DummyView)
+                .child(
+                    Dialog::around(
+                        EditView::new()
+                            .secret()
+                            .with_name("lotw_password")
+                            .fixed_width(20),
+                    )
+                    .title("Certificate Password"),
+                )
+                .child(
+                    Dialog::around(
+                        EditView::new()
+                            .content("upload.tq8")
+                            .with_name("lotw_path")
+                            .fixed_width(30),
+                    )
+                    .title("File Path"),
+                )
+                .child(
+                    LinearLayout::horizontal()
+                        .child(Checkbox::new().with_name("lotw_resend"))
+                        .child(TextView::new(" Include QSOs already sent")),
+                )
+                .child(DummyView)
+                .child(
+                    LinearLayout::horizontal()
+                        .child(Button::new("Sign", move |s| {
+                            if let Err(err) = lotw_submit(s, connection.clone()) {
+                                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                            }
+                        }))
+                        .child(DummyView)
+                        .child(Button::new("Cancel", |s| {
+                            s.pop_layer();
+                        }))
+                        .align_center(),
+                ),
+        )
+        .title("Sign for LoTW"),
+    );
+}
+
+fn lotw_submit(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let password = s
+        .call_on_name("lotw_password", |view: &mut EditView| view.get_content())
+        .unwrap();
+    let path = s
+        .call_on_name("lotw_path", |view: &mut EditView| view.get_content())
         .unwrap();
-    if let Some(Err(end_err)) = end_timestamp {
-        return Err(anyhow!("Could not parse start timestamp: {}", end_err));
+    if path.trim().is_empty() {
+        return Err(anyhow!("No file path received"));
     }
-    let export_path = s
-        .call_on_name("export_path", |view: &mut EditView| {
-            let content = view.get_content();
-            if content.len() > 0 {
-                Some(content)
-            } else {
-                None
-            }
-        })
+    let resend = s
+        .call_on_name("lotw_resend", |view: &mut Checkbox| view.is_checked())
         .unwrap();
-    let logs = if let Ok(connection) = connection.lock() {
-        let mut stmt = connection.prepare(
-            "SELECT timestamp, logs.call, rsttx, rstrx, band, frequency, mode, power, comments, operatorconfig.id, name, operatorconfig.call, grid, cqz, ituz, dxcc, cont  FROM logs JOIN operatorconfig ON logs.operator_config = operatorconfig.id ORDER BY timestamp DESC;",
-        ).unwrap();
-        let mut logs: Vec<AdifRecord> = Vec::new();
-        let log_out = stmt
-            .query_map((), |row| {
-                Ok(LogbookExt {
-                    timestamp: row.get(0)?,
-                    call: row.get(1)?,
-                    rsttx: row.get(2)?,
-                    rstrx: row.get(3)?,
-                    band: row.get(4)?,
-                    frequency: row.get(5)?,
-                    mode: row.get(6)?,
-                    power: row.get(7)?,
-                    comments: row.get(8)?,
-                    operator: OperatorConfig {
-                        id: row.get(9)?,
-                        name: row.get(10)?,
-                        call: row.get(11)?,
-                        grid: row.get(12)?,
-                        cqz: row.get(13)?,
-                        ituz: row.get(14)?,
-                        dxcc: row.get(15)?,
-                        cont: row.get(16)?,
-                    },
-                })
+    let (mut ids, _) = marked_rows(s);
+    if ids.is_empty() {
+        ids = s
+            .call_on_name("table", |view: &mut TableView<Logbook, LogbookColumn>| {
+                view.borrow_items().iter().map(|log| log.id).collect()
             })
-            .unwrap();
-        for log in log_out {
-            let log = log.unwrap();
-            if let Some(Ok(start)) = start_timestamp {
-                if log.timestamp < start {
-                    continue;
+            .unwrap_or_default();
+    }
+    let qsos = QsoStore::new(connection.clone());
+    let logs = ids
+        .iter()
+        .map(|id| qsos.get(*id))
+        .collect::<Result<Vec<LogbookExt>>>()?;
+    let logs = lotw::pending(logs, resend);
+    if logs.is_empty() {
+        return Err(anyhow!("No QSOs left to sign"));
+    }
+    let path = path.trim().to_string();
+    s.add_layer(
+        Dialog::text(format!(
+            "Sign {} QSOs into {} and mark them sent to LoTW?",
+            logs.len(),
+            path
+        ))
+        .title("Sign for LoTW")
+        .button("Sign", move |s| {
+            s.pop_layer();
+            let signed =
+                lotw::sign_to_file(connection.clone(), logs.clone(), &password, &path, true);
+            match signed.and_then(|count| refresh_table(s, connection.clone()).map(|_| count)) {
+                Ok(count) => {
+                    clear_marks(s);
+                    s.pop_layer();
+                    s.add_layer(
+                        Dialog::text(format!(
+                            "Signed {} QSOs into {}; upload it at lotw.arrl.org",
+                            count, path
+                        ))
+                        .title("LoTW")
+                        .dismiss_button("OK"),
+                    );
                 }
-            }
-            if let Some(Ok(end)) = end_timestamp {
-                if log.timestamp > end {
-                    continue;
+                Err(err) => {
+                    s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
                 }
             }
-            let mut map: IndexMap<&str, AdifType> = IndexMap::new();
-            map.insert("CALL", AdifType::Str(log.call));
-            map.insert(
-                "QSO_DATE",
-                AdifType::Str(log.timestamp.format("%Y%m%d").to_string()),
-            );
-            map.insert(
-                "TIME_ON",
-                AdifType::Str(log.timestamp.format("%H%M%S").to_string()),
-            );
-            map.insert("FREQ", AdifType::Str(log.frequency.clone()));
-            map.insert("BAND", AdifType::Str(log.band.clone()));
-            map.insert("FREQ_RX", AdifType::Str(log.frequency));
-            map.insert("BAND_RX", AdifType::Str(log.band));
-            map.insert("COMMENT", AdifType::Str(log.comments));
-            if log.mode == "USB" || log.mode == "LSB" {
-                map.insert("MODE", AdifType::Str("SSB".to_string()));
-                map.insert("SUBMODE", AdifType::Str(log.mode));
-            } else {
-                map.insert("MODE", AdifType::Str(log.mode.clone()));
-            }
-            map.insert("MY_GRIDSQUARE", AdifType::Str(log.operator.grid));
-            map.insert("STATION_CALLSIGN", AdifType::Str(log.operator.call.clone()));
-            map.insert("CQZ", AdifType::Str(log.operator.cqz));
-            map.insert("ITUZ", AdifType::Str(log.operator.ituz));
-            map.insert("DXCC", AdifType::Str(log.operator.dxcc));
-            map.insert("CONT", AdifType::Str(log.operator.cont));
-            map.insert("OPERATOR", AdifType::Str(log.operator.call));
-            map.insert("RST_SENT", AdifType::Str(log.rsttx));
-            map.insert("RST_RCVD", AdifType::Str(log.rstrx));
-            map.insert("TX_PWR", AdifType::Str(log.power));
-            logs.push(map.into());
+        })
+        .dismiss_button("Cancel"),
+    );
+    Ok(())
+}
+
+fn import(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let import_path = s
+        .call_on_name("import_path", |view: &mut EditView| view.get_content())
+        .unwrap();
+    if import_path.is_empty() {
+        return Err(anyhow!("No import path received"));
+    }
+    let profile = s
+        .call_on_name("import_profile", |view: &mut SelectView<Option<u64>>| {
+            view.selection().and_then(|profile| *profile)
+        })
+        .unwrap();
+    let (imported, skipped) = import_adif(connection.clone(), import_path.as_str(), profile)?;
+    refresh_table(s, connection)?;
+    let mut report = format!("Imported {} QSOs, skipped {}", imported, skipped.len());
+    for reason in skipped {
+        report.push('\n');
+        report.push_str(&reason);
+    }
+    s.pop_layer();
+    s.add_layer(
+        Dialog::around(TextView::new(report).scrollable().max_height(20))
+            .title("Import Complete")
+            .dismiss_button("OK"),
+    );
+    Ok(())
+}
+
+fn filter_text(s: &mut Cursive, name: &str) -> Option<String> {
+    s.call_on_name(name, |view: &mut EditView| view.get_content())
+        .map(|content| content.trim().to_string())
+        .filter(|content| !content.is_empty())
+}
+
+// Accepts a bare date for the filter bar, covering the whole day
+fn parse_filter_date(value: &str, end_of_day: bool) -> Result<NaiveDateTime> {
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) if end_of_day => Ok(date.and_hms_opt(23, 59, 59).unwrap()),
+        Ok(date) => Ok(date.and_hms_opt(0, 0, 0).unwrap()),
+        Err(_) => parse_timestamp(value),
+    }
+}
+
+fn read_filter(s: &mut Cursive) -> Result<QsoFilter> {
+    Ok(QsoFilter {
+        start: filter_text(s, "filter_start")
+            .map(|start| parse_filter_date(&start, false))
+            .transpose()?,
+        end: filter_text(s, "filter_end")
+            .map(|end| parse_filter_date(&end, true))
+            .transpose()?,
+        profile: s
+            .call_on_name("filter_profile", |view: &mut SelectView<Option<u64>>| {
+                view.selection().and_then(|profile| *profile)
+            })
+            .flatten(),
+        call_pattern: filter_text(s, "filter_call"),
+        band: filter_text(s, "filter_band"),
+        mode: filter_text(s, "filter_mode"),
+        comments: filter_text(s, "filter_comments"),
+        ..Default::default()
+    })
+}
+
+fn clear_filter(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    for name in [
+        "filter_call",
+        "filter_band",
+        "filter_mode",
+        "filter_start",
+        "filter_end",
+        "filter_comments",
+    ] {
+        s.call_on_name(name, |view: &mut EditView| {
+            view.set_content("");
+        });
+    }
+    s.call_on_name("filter_profile", |view: &mut SelectView<Option<u64>>| {
+        view.set_selection(0);
+    });
+    refresh_table(s, connection)
+}
+
+/// Reloads the Logbook table with the rows matching the filter bar, keeping marked rows marked.
+pub fn refresh_table(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let filter = read_filter(s)?;
+    let mut logs = QsoStore::new(connection).rows(&filter)?;
+    let (marked, _) = marked_rows(s);
+    for log in logs.iter_mut() {
+        log.marked = marked.contains(&log.id);
+    }
+    s.call_on_name("table", |view: &mut TableView<Logbook, LogbookColumn>| {
+        view.set_items_stable(logs);
+    });
+    show_count(s);
+    Ok(())
+}
+
+/// The ids of the rows marked in the Logbook table, and how many rows it shows.
+fn marked_rows(s: &mut Cursive) -> (Vec<u64>, usize) {
+    s.call_on_name("table", |view: &mut TableView<Logbook, LogbookColumn>| {
+        let marked = view
+            .borrow_items()
+            .iter()
+            .filter(|log| log.marked)
+            .map(|log| log.id)
+            .collect();
+        (marked, view.len())
+    })
+    .unwrap_or_default()
+}
+
+fn show_count(s: &mut Cursive) {
+    let (marked, shown) = marked_rows(s);
+    let text = match marked.len() {
+        0 => format!("{} QSOs (Space marks a QSO for QSL or LoTW)", shown),
+        marked => format!("{} QSOs, {} marked", shown, marked),
+    };
+    s.call_on_name("logbook_count", |view: &mut TextView| {
+        view.set_content(text)
+    });
+}
+
+/// Marks or unmarks the selected row, then moves down so a run of rows can be marked.
+fn toggle_mark(s: &mut Cursive) {
+    s.call_on_name("table", |view: &mut TableView<Logbook, LogbookColumn>| {
+        let (Some(row), Some(index)) = (view.row(), view.item()) else {
+            return;
+        };
+        if let Some(log) = view.borrow_item_mut(index) {
+            log.marked = !log.marked;
         }
-        logs
-    } else {
-        return Err(anyhow!("Could not lock connection"));
+        if row + 1 < view.len() {
+            view.set_selected_row(row + 1);
+        }
+    });
+    show_count(s);
+}
+
+fn clear_marks(s: &mut Cursive) {
+    s.call_on_name("table", |view: &mut TableView<Logbook, LogbookColumn>| {
+        for log in view.borrow_items_mut() {
+            log.marked = false;
+        }
+    });
+    show_count(s);
+}
+
+/// Sets one QSL status on the QSOs marked in the Logbook table, or after asking, on every
+/// QSO it shows when none are marked.
+fn bulk_qsl(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let service = s
+        .call_on_name("qsl_service", |view: &mut SelectView<QslService>| {
+            view.selection().map(|service| *service)
+        })
+        .flatten()
+        .ok_or(anyhow!("No QSL service selected"))?;
+    let (received, status) = s
+        .call_on_name(
+            "qsl_status",
+            |view: &mut SelectView<(bool, &'static str)>| view.selection().map(|status| *status),
+        )
+        .flatten()
+        .ok_or(anyhow!("No QSL status selected"))?;
+    let date = s
+        .call_on_name("qsl_date", |view: &mut EditView| view.get_content())
+        .unwrap();
+    let date = match date.trim() {
+        "" => None,
+        date => Some(
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| anyhow!("'{}' is not a date (YYYY-MM-DD)", date))?,
+        ),
     };
-    let mut map: IndexMap<&str, AdifType> = IndexMap::new();
-    map.insert("PROGRAMVERSION", AdifType::Str("1.0.0".to_string()));
-    map.insert("PROGRAMID", AdifType::Str("TUILOG".to_string()));
-    let header: AdifHeader = map.into();
-    let file_out = AdifFile { header, body: logs };
-    let mut file = File::create(
-        export_path
-            .ok_or(anyhow!("No export path received"))?
-            .to_string(),
-    )?;
-    file.write_all(
-        file_out
-            .serialize()
-            .map_err(|_| anyhow!("Failed to serialize data"))?
-            .as_bytes(),
-    )?;
-    s.pop_layer();
+    let update = move |s: &mut Cursive, ids: Vec<u64>| -> Result<()> {
+        QsoStore::new(connection.clone()).update_qsl(&ids, service, received, status, date)?;
+        refresh_table(s, connection.clone())?;
+        clear_marks(s);
+        s.pop_layer();
+        s.add_layer(
+            Dialog::text(format!("Updated {} QSOs", ids.len()))
+                .title("QSL")
+                .dismiss_button("OK"),
+        );
+        Ok(())
+    };
+    let (marked, shown) = marked_rows(s);
+    if !marked.is_empty() {
+        return update(s, marked);
+    }
+    let ids: Vec<u64> = s
+        .call_on_name("table", |view: &mut TableView<Logbook, LogbookColumn>| {
+            view.borrow_items().iter().map(|log| log.id).collect()
+        })
+        .unwrap_or_default();
+    s.add_layer(
+        Dialog::text(format!(
+            "No QSOs are marked. Set {} {}: {} on all {} QSOs shown?",
+            service.name(),
+            if received { "Received" } else { "Sent" },
+            qsl::status_name(status),
+            shown
+        ))
+        .title("Update QSLs")
+        .button("Update All", move |s| {
+            s.pop_layer();
+            if let Err(err) = update(s, ids.clone()) {
+                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+            }
+        })
+        .dismiss_button("Cancel"),
+    );
     Ok(())
 }
 
+fn qsl_dialog(s: &mut Cursive, connection: Arc<Mutex<Connection>>) {
+    let applies_to = match marked_rows(s) {
+        (marked, shown) if marked.is_empty() => {
+            format!(
+                "No QSOs are marked; applies to the {} shown in the Logbook",
+                shown
+            )
+        }
+        (marked, _) => format!("Applies to the {} QSOs marked in the Logbook", marked.len()),
+    };
+    let mut services = SelectView::<QslService>::new().popup();
+    for service in QslService::all() {
+        services.add_item(service.name(), service);
+    }
+    let mut statuses = SelectView::<(bool, &'static str)>::new();
+    for status in SENT_STATUSES {
+        statuses.add_item(
+            format!("Sent: {}", qsl::status_name(status)),
+            (false, *status),
+        );
+    }
+    for status in RCVD_STATUSES {
+        statuses.add_item(
+            format!("Received: {}", qsl::status_name(status)),
+            (true, *status),
+        );
+    }
+    s.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(applies_to))
+                .child(DummyView)
+                .child(Dialog::around(services.with_name("qsl_service")).title("Service"))
+                .child(Dialog::around(statuses.with_name("qsl_status")).title("Status"))
+                .child(
+                    Dialog::around(
+                        EditView::new()
+                            .content(Utc::now().date_naive().to_string())
+                            .with_name("qsl_date")
+                            .fixed_width(11),
+                    )
+                    .title("Date (YYYY-MM-DD)"),
+                )
+                .child(DummyView)
+                .child(
+                    LinearLayout::horizontal()
+                        .child(Button::new("Apply", move |s| {
+                            if let Err(err) = bulk_qsl(s, connection.clone()) {
+                                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                            }
+                        }))
+                        .child(DummyView)
+                        .child(Button::new("Cancel", |s| {
+                            s.pop_layer();
+                        }))
+                        .align_center(),
+                ),
+        )
+        .title("Update QSLs"),
+    );
+}
+
+fn filter_field(
+    name: &str,
+    title: &str,
+    width: usize,
+    connection: Arc<Mutex<Connection>>,
+) -> Dialog {
+    Dialog::around(
+        EditView::new()
+            .on_submit(move |s, _| {
+                if let Err(err) = refresh_table(s, connection.clone()) {
+                    s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                }
+            })
+            .with_name(name)
+            .fixed_width(width),
+    )
+    .title(title)
+}
+
 pub fn make_table(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
     let mut table = TableView::<Logbook, LogbookColumn>::new()
+        .column(LogbookColumn::Marked, "", |c| c.width_percent(2))
         .column(LogbookColumn::Timestamp, "Timestamp", |c| {
             c.width_percent(20)
         })
@@ -174,34 +541,35 @@ pub fn make_table(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result
             c.width_percent(10)
         })
         .column(LogbookColumn::Mode, "Mode", |c| c.width_percent(5))
-        .column(LogbookColumn::Comments, "Comments", |c| c.width_percent(45));
-    let logs = if let Ok(connection) = connection.lock() {
-        let mut stmt = connection.prepare(
-            "SELECT timestamp, call, rsttx, rstrx, band, frequency, mode, comments FROM logs ORDER BY timestamp DESC",
-        )?;
-        let mut logs: Vec<Logbook> = Vec::new();
-        let log_out = stmt.query_map((), |row| {
-            Ok(Logbook {
-                timestamp: row.get(0)?,
-                call: row.get(1)?,
-                rsttx: row.get(2)?,
-                rstrx: row.get(3)?,
-                band: row.get(4)?,
-                frequency: row.get(5)?,
-                mode: row.get(6)?,
-                comments: row.get(7)?,
+        .column(LogbookColumn::Duration, "Duration", |c| c.width_percent(5))
+        .column(LogbookColumn::Qsl, "QSL", |c| c.width_percent(5))
+        .column(LogbookColumn::Comments, "Comments", |c| c.width_percent(33))
+        .default_column(LogbookColumn::Timestamp);
+    let edit_conn = connection.clone();
+    table.set_on_submit(move |s, _row, index| {
+        let id = s
+            .call_on_name("table", |view: &mut TableView<Logbook, LogbookColumn>| {
+                view.borrow_item(index).map(|log| log.id)
             })
-        })?;
-        for log in log_out {
-            logs.push(log?);
+            .flatten();
+        if let Some(id) = id {
+            if let Err(err) = edit_log(s, edit_conn.clone(), id) {
+                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+            }
         }
-        logs
-    } else {
-        return Err(anyhow!("Could not lock connection"));
-    };
-
-    table.set_items(logs);
+    });
     let export_conn = connection.clone();
+    let cabrillo_conn = connection.clone();
+    let import_conn = connection.clone();
+    let qsl_conn = connection.clone();
+    let lotw_conn = connection.clone();
+    let apply_conn = connection.clone();
+    let clear_conn = connection.clone();
+    let mut filter_profile = SelectView::<Option<u64>>::new().popup();
+    filter_profile.add_item("Any", None);
+    for option in ProfileStore::new(connection.clone()).list()? {
+        filter_profile.add_item(format!("{} ({})", option.id, option.name), Some(option.id));
+    }
     s.pop_layer();
     s.add_layer(
         Dialog::around(
@@ -245,19 +613,196 @@ pub fn make_table(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result
                                         )
                                         .child(DummyView)
                                         .child(Button::new("Submit", move |s| {
-                                            export(s, export_conn.clone()).unwrap()
+                                            if let Err(err) = export(s, export_conn.clone()) {
+                                                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                                            }
                                         })),
                                 )
                                 .title("Filter"),
                             )
                         }))
+                        .child(Button::new("Cabrillo", move |s| {
+                            let cabrillo_conn = cabrillo_conn.clone();
+                            let mut profiles = SelectView::<u64>::new();
+                            let options = match ProfileStore::new(cabrillo_conn.clone()).list() {
+                                Ok(options) => options,
+                                Err(err) => {
+                                    s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                                    return;
+                                }
+                            };
+                            for option in options {
+                                profiles.add_item(format!("{} ({})", option.id, option.name), option.id);
+                            }
+                            let mut contests = SelectView::<Option<u64>>::new().popup();
+                            contests.add_item("All QSOs", None);
+                            let sessions = match ContestStore::new(cabrillo_conn.clone()).list() {
+                                Ok(sessions) => sessions,
+                                Err(err) => {
+                                    s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                                    return;
+                                }
+                            };
+                            for contest in sessions {
+                                contests.add_item(contest.name, Some(contest.id));
+                            }
+                            let field = |name: &str, title: &str, width: usize| {
+                                Dialog::around(
+                                    EditView::new()
+                                        .with_name(name)
+                                        .fixed_width(width)
+                                        .align_center(),
+                                )
+                                .title(title)
+                            };
+                            s.add_layer(
+                                Dialog::around(
+                                    LinearLayout::vertical()
+                                        .child(DummyView)
+                                        .child(TextView::new("DateTime format YYYY-MM-DD HH:MM:SS"))
+                                        .child(DummyView)
+                                        .child(
+                                            LinearLayout::horizontal()
+                                                .child(field("start_timestamp", "Start Timestamp", 20))
+                                                .child(field("end_timestamp", "End Timestamp", 20))
+                                                .align_center(),
+                                        )
+                                        .child(
+                                            LinearLayout::horizontal()
+                                                .child(
+                                                    Dialog::around(profiles.with_name("cabrillo_profile"))
+                                                        .title("Profile"),
+                                                )
+                                                .child(
+                                                    Dialog::around(contests.with_name("cabrillo_contest"))
+                                                        .title("Session"),
+                                                )
+                                                .child(field("contest", "Contest (blank = session's)", 15))
+                                                .child(field("location", "Location", 8))
+                                                .align_center(),
+                                        )
+                                        .child(
+                                            LinearLayout::horizontal()
+                                                .child(field("category_operator", "Category Operator", 12))
+                                                .child(field("category_band", "Category Band", 8))
+                                                .child(field("category_mode", "Category Mode", 8))
+                                                .align_center(),
+                                        )
+                                        .child(
+                                            LinearLayout::horizontal()
+                                                .child(field("category_power", "Category Power", 8))
+                                                .child(field("category_station", "Category Station", 12))
+                                                .child(field("sent_exchange", "Sent Exchange", 8))
+                                                .align_center(),
+                                        )
+                                        .child(field("export_path", "File Path", 20))
+                                        .child(DummyView)
+                                        .child(Button::new("Submit", move |s| {
+                                            if let Err(err) = export_cabrillo(s, cabrillo_conn.clone()) {
+                                                s.add_layer(
+                                                    Dialog::text(err.to_string())
+                                                        .title("Export Failed")
+                                                        .dismiss_button("OK"),
+                                                );
+                                            }
+                                        })),
+                                )
+                                .title("Cabrillo Export"),
+                            )
+                        }))
+                        .child(Button::new("Import", move |s| {
+                            let import_conn = import_conn.clone();
+                            let mut profiles = SelectView::<Option<u64>>::new();
+                            profiles.add_item("Match STATION_CALLSIGN", None);
+                            let options = match ProfileStore::new(import_conn.clone()).list() {
+                                Ok(options) => options,
+                                Err(err) => {
+                                    s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                                    return;
+                                }
+                            };
+                            for option in options {
+                                profiles.add_item(format!("{} ({})", option.id, option.name), Some(option.id));
+                            }
+                            s.add_layer(
+                                Dialog::around(
+                                    LinearLayout::vertical()
+                                        .child(DummyView)
+                                        .child(
+                                            Dialog::around(
+                                                EditView::new()
+                                                    .with_name("import_path")
+                                                    .fixed_width(20)
+                                                    .align_center(),
+                                            )
+                                            .title("File Path"),
+                                        )
+                                        .child(
+                                            Dialog::around(profiles.with_name("import_profile"))
+                                                .title("Profile"),
+                                        )
+                                        .child(DummyView)
+                                        .child(Button::new("Submit", move |s| {
+                                            if let Err(err) = import(s, import_conn.clone()) {
+                                                s.add_layer(
+                                                    Dialog::text(err.to_string())
+                                                        .title("Import Failed")
+                                                        .dismiss_button("OK"),
+                                                );
+                                            }
+                                        })),
+                                )
+                                .title("Import ADIF"),
+                            )
+                        }))
+                        .child(Button::new("QSL", move |s| {
+                            qsl_dialog(s, qsl_conn.clone());
+                        }))
+                        .child(Button::new("LoTW", move |s| {
+                            lotw_dialog(s, lotw_conn.clone());
+                        }))
                         .align_center(),
                 )
                 .child(DummyView)
-                .child(table.with_name("table").min_size((150, 100))),
+                .child(
+                    LinearLayout::vertical()
+                        .child(
+                            LinearLayout::horizontal()
+                                .child(filter_field("filter_call", "Call (* ?)", 10, connection.clone()))
+                                .child(filter_field("filter_band", "Band", 6, connection.clone()))
+                                .child(filter_field("filter_mode", "Mode", 6, connection.clone()))
+                                .child(filter_field("filter_start", "From", 12, connection.clone()))
+                                .child(filter_field("filter_end", "To", 12, connection.clone()))
+                                .child(
+                                    Dialog::around(filter_profile.with_name("filter_profile"))
+                                        .title("Profile"),
+                                )
+                                .child(filter_field("filter_comments", "Comments", 15, connection.clone()))
+                                .child(
+                                    LinearLayout::vertical()
+                                        .child(Button::new("Apply", move |s| {
+                                            if let Err(err) = refresh_table(s, apply_conn.clone()) {
+                                                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                                            }
+                                        }))
+                                        .child(Button::new("Clear", move |s| {
+                                            if let Err(err) = clear_filter(s, clear_conn.clone()) {
+                                                s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                                            }
+                                        })),
+                                ),
+                        )
+                        .child(TextView::new("").with_name("logbook_count"))
+                        .child(
+                            OnEventView::new(table.with_name("table"))
+                                .on_event(' ', toggle_mark)
+                                .min_size((150, 100)),
+                        ),
+                ),
         )
         .title("Logbook"),
     );
+    refresh_table(s, connection)?;
 
     Ok(())
 }
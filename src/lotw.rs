@@ -0,0 +1,478 @@
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use flate2::{write::GzEncoder, Compression};
+use openssl::{
+    base64,
+    hash::MessageDigest,
+    pkcs12::Pkcs12,
+    pkey::{PKey, Private},
+    provider::Provider,
+    sign::Signer,
+    x509::X509,
+};
+use rusqlite::Connection;
+
+use crate::mode::Mode;
+use crate::models::{LogbookExt, OperatorConfig};
+use crate::qsl::QslService;
+use crate::store::QsoStore;
+
+// Subject entry holding the callsign of a LoTW callsign certificate
+const CALLSIGN_OID: &str = "1.3.6.1.4.1.12348.1.1";
+
+/// A LoTW callsign certificate with its private key, as exported from TQSL.
+pub struct Certificate {
+    call: String,
+    cert: X509,
+    key: PKey<Private>,
+}
+
+impl Certificate {
+    /// Reads a .p12 file, which may be protected by `password` (empty for none).
+    pub fn load(path: &Path, password: &str) -> Result<Certificate> {
+        let der = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let pkcs12 = Pkcs12::from_der(&der)?;
+        // TQSL protects older certificates with ciphers OpenSSL 3 only has in its legacy provider
+        let parsed = match pkcs12.parse2(password) {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                let _legacy = Provider::try_load(None, "legacy", true);
+                pkcs12
+                    .parse2(password)
+                    .map_err(|_| anyhow!("Wrong password for {}", path.display()))?
+            }
+        };
+        let cert = parsed
+            .cert
+            .ok_or(anyhow!("{} holds no certificate", path.display()))?;
+        let key = parsed
+            .pkey
+            .ok_or(anyhow!("{} holds no private key", path.display()))?;
+        let call = cert
+            .subject_name()
+            .entries()
+            .find(|entry| entry.object().to_string() == CALLSIGN_OID)
+            .and_then(|entry| entry.data().to_string().ok())
+            .map(|call| call.to_uppercase())
+            .ok_or(anyhow!(
+                "{} is not a LoTW callsign certificate",
+                path.display()
+            ))?;
+        Ok(Certificate { call, cert, key })
+    }
+
+    pub fn call(&self) -> &str {
+        &self.call
+    }
+}
+
+/// Where the QSOs were made from, which LoTW checks against the certificate.
+#[derive(Clone, Debug)]
+pub struct Station {
+    pub call: String,
+    pub dxcc: u32,
+    pub grid: String,
+    pub cqz: Option<u32>,
+    pub ituz: Option<u32>,
+    pub state: String,
+}
+
+impl Station {
+    pub fn from_profile(profile: &OperatorConfig) -> Result<Station> {
+        let number = |name: &str, value: &str| match value.trim() {
+            "" => Ok(None),
+            value => value.parse().map(Some).map_err(|_| {
+                anyhow!(
+                    "Profile {} has an invalid {} '{}'",
+                    profile.name,
+                    name,
+                    value
+                )
+            }),
+        };
+        let call = profile.call.trim().to_uppercase();
+        if call.is_empty() {
+            return Err(anyhow!("Profile {} has no callsign", profile.name));
+        }
+        Ok(Station {
+            call,
+            dxcc: number("DXCC", &profile.dxcc)?.ok_or(anyhow!(
+                "Profile {} needs a DXCC entity number for LoTW",
+                profile.name
+            ))?,
+            // LoTW takes 4 or 6 character grids
+            grid: profile.grid.trim().chars().take(6).collect(),
+            cqz: number("CQZ", &profile.cqz)?,
+            ituz: number("ITUZ", &profile.ituz)?,
+            state: profile.state.trim().to_uppercase(),
+        })
+    }
+}
+
+/// LoTW's name for a logged mode: USB and LSB count as SSB, other submodes such as FT4
+/// stand on their own.
+fn lotw_mode(mode: &str) -> String {
+    match Mode::from_name(mode) {
+        Some(mode) if mode.mode == "SSB" => String::from("SSB"),
+        Some(mode) => mode.submode.unwrap_or(mode.mode).to_string(),
+        None => mode.trim().to_uppercase(),
+    }
+}
+
+fn field(out: &mut String, name: &str, value: &str) {
+    out.push_str(&format!("<{}:{}>{}\n", name, value.len(), value));
+}
+
+/// Base64 wrapped at 64 columns, the way TQSL writes certificates and signatures.
+fn wrapped_base64(bytes: &[u8]) -> String {
+    let encoded = base64::encode_block(bytes);
+    let mut out = String::new();
+    for line in encoded.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+    out
+}
+
+/// Builds a gzipped GABBI (.tq8) upload of `logs` made from `station`, each QSO signed
+/// with `certificate` following the LoTW 2.0 signing spec.
+pub fn sign(certificate: &Certificate, station: &Station, logs: &[LogbookExt]) -> Result<Vec<u8>> {
+    if certificate.call != station.call {
+        return Err(anyhow!(
+            "The LoTW certificate is for {}, not {}",
+            certificate.call,
+            station.call
+        ));
+    }
+    let mut out = String::new();
+    field(
+        &mut out,
+        "TQSL_IDENT",
+        &format!("TUILog V{}", env!("CARGO_PKG_VERSION")),
+    );
+    out.push('\n');
+
+    field(&mut out, "Rec_Type", "tCERT");
+    field(&mut out, "CERT_UID", "1");
+    field(
+        &mut out,
+        "CERTIFICATE",
+        &wrapped_base64(&certificate.cert.to_der()?),
+    );
+    out.push_str("<eor>\n\n");
+
+    // Station fields are signed in the order of the spec, skipping blank ones
+    let mut station_data = String::new();
+    field(&mut out, "Rec_Type", "tSTATION");
+    field(&mut out, "STATION_UID", "1");
+    field(&mut out, "CERT_UID", "1");
+    field(&mut out, "CALL", &station.call);
+    field(&mut out, "DXCC", &station.dxcc.to_string());
+    let cqz = station.cqz.map(|cqz| cqz.to_string()).unwrap_or_default();
+    let ituz = station
+        .ituz
+        .map(|ituz| ituz.to_string())
+        .unwrap_or_default();
+    for (name, value) in [
+        ("CQZ", cqz.as_str()),
+        ("GRIDSQUARE", station.grid.as_str()),
+        ("ITUZ", ituz.as_str()),
+        ("US_STATE", station.state.as_str()),
+    ] {
+        if !value.is_empty() {
+            field(&mut out, name, value);
+            station_data.push_str(&value.to_uppercase());
+        }
+    }
+    out.push_str("<eor>\n\n");
+
+    for log in logs {
+        let frequency = log
+            .frequency
+            .map(|frequency| frequency.to_string())
+            .unwrap_or_default();
+        let qso = [
+            ("BAND", log.band.to_uppercase()),
+            ("CALL", log.call.trim().to_uppercase()),
+            ("FREQ", frequency),
+            ("MODE", lotw_mode(&log.mode)),
+            ("QSO_DATE", log.timestamp.format("%Y-%m-%d").to_string()),
+            ("QSO_TIME", log.timestamp.format("%H:%M:%SZ").to_string()),
+        ];
+        let mut sign_data = station_data.clone();
+        for (_, value) in &qso {
+            sign_data.push_str(&value.to_uppercase());
+        }
+        let mut signer = Signer::new(MessageDigest::sha1(), &certificate.key)?;
+        signer.update(sign_data.as_bytes())?;
+        let signature = wrapped_base64(&signer.sign_to_vec()?);
+
+        field(&mut out, "Rec_Type", "tCONTACT");
+        field(&mut out, "STATION_UID", "1");
+        for (name, value) in &qso {
+            if !value.is_empty() {
+                field(&mut out, name, value);
+            }
+        }
+        out.push_str(&format!(
+            "<SIGN_LOTW_V2.0:{}:6>{}",
+            signature.len(),
+            signature
+        ));
+        field(&mut out, "SIGNDATA", &sign_data);
+        out.push_str("<eor>\n\n");
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(out.as_bytes())?;
+    Ok(encoder.finish()?)
+}
+
+/// Signs `logs` with the certificate and station location of `profile`.
+pub fn sign_profile(
+    profile: &OperatorConfig,
+    password: &str,
+    logs: &[LogbookExt],
+) -> Result<Vec<u8>> {
+    if profile.lotw_cert.trim().is_empty() {
+        return Err(anyhow!(
+            "Profile {} has no LoTW certificate; set one in Options",
+            profile.name
+        ));
+    }
+    let certificate = Certificate::load(Path::new(profile.lotw_cert.trim()), password)?;
+    sign(&certificate, &Station::from_profile(profile)?, logs)
+}
+
+/// The QSOs in `logs` that have not gone to LoTW yet and are not set to be ignored, or all
+/// of them with `resend`.
+pub fn pending(mut logs: Vec<LogbookExt>, resend: bool) -> Vec<LogbookExt> {
+    if !resend {
+        logs.retain(|log| !matches!(log.lotw_qsl.sent.as_str(), "Y" | "I"));
+    }
+    logs
+}
+
+/// Signs the [`pending`] QSOs in `logs` into a .tq8 file at `path` and marks them sent.
+/// Returns how many were signed.
+pub fn sign_to_file(
+    connection: Arc<Mutex<Connection>>,
+    logs: Vec<LogbookExt>,
+    password: &str,
+    path: &str,
+    resend: bool,
+) -> Result<usize> {
+    let logs = pending(logs, resend);
+    let profile = match logs.first() {
+        Some(log) => log.operator.clone(),
+        None => return Err(anyhow!("No QSOs left to sign")),
+    };
+    // A .tq8 carries one station location, so it can only hold one profile's QSOs
+    if logs.iter().any(|log| log.operator.id != profile.id) {
+        return Err(anyhow!(
+            "The QSOs belong to more than one profile; pick a profile first"
+        ));
+    }
+    fs::write(path, sign_profile(&profile, password, &logs)?)?;
+    let ids: Vec<u64> = logs.iter().map(|log| log.id).collect();
+    QsoStore::new(connection).update_qsl(
+        &ids,
+        QslService::Lotw,
+        false,
+        "Y",
+        Some(Utc::now().date_naive()),
+    )?;
+    Ok(ids.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::Read, path::PathBuf};
+
+    use chrono::NaiveDateTime;
+    use flate2::read::GzDecoder;
+    use openssl::{
+        asn1::Asn1Time,
+        bn::BigNum,
+        pkey::Public,
+        rsa::Rsa,
+        sign::Verifier,
+        x509::{X509Builder, X509NameBuilder},
+    };
+
+    use super::*;
+    use crate::qso::LogForm;
+    use crate::store::{open_in_memory, ProfileStore};
+
+    /// Writes a self-signed LoTW-style certificate for `call` to a .p12 file in `dir`.
+    fn certificate(dir: &Path, call: &str, password: &str) -> (PathBuf, PKey<Public>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "Test Operator").unwrap();
+        name.append_entry_by_text(CALLSIGN_OID, call).unwrap();
+        let name = name.build();
+        let mut cert = X509Builder::new().unwrap();
+        cert.set_version(2).unwrap();
+        let serial = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+        cert.set_serial_number(&serial).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        let cert = cert.build();
+        let pkcs12 = Pkcs12::builder()
+            .name(call)
+            .pkey(&key)
+            .cert(&cert)
+            .build2(password)
+            .unwrap();
+        let path = dir.join(format!("{}.p12", call));
+        fs::write(&path, pkcs12.to_der().unwrap()).unwrap();
+        let public = PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap();
+        (path, public)
+    }
+
+    fn form(profile: u64, callsign: &str, frequency: &str, mode: &str, at: &str) -> LogForm {
+        let at = NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M:%S").unwrap();
+        LogForm {
+            callsign: callsign.to_string(),
+            profile,
+            band: String::from("20M"),
+            frequency: frequency.to_string(),
+            mode: mode.to_string(),
+            rsttx: String::new(),
+            rstrx: String::new(),
+            power: String::new(),
+            comments: String::new(),
+            time_on: Some(at),
+            time_off: Some(at),
+            name: String::new(),
+            qth: String::new(),
+            state: String::new(),
+            gridsquare: String::new(),
+            license_class: String::new(),
+            contest: None,
+            exchange: String::new(),
+        }
+    }
+
+    /// The value of the first `<name:length>` field in an upload record.
+    fn value<'a>(record: &'a str, name: &str) -> &'a str {
+        let start = record.find(&format!("<{}:", name)).unwrap() + name.len() + 2;
+        let end = start + record[start..].find('>').unwrap();
+        let length: usize = record[start..end]
+            .split(':')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        &record[end + 1..end + 1 + length]
+    }
+
+    #[test]
+    fn signs_qsos_and_marks_them_sent() {
+        let dir = env::temp_dir().join(format!("tuilog-lotw-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (cert_path, public_key) = certificate(&dir, "W1AW", "secret");
+
+        let connection = open_in_memory().unwrap();
+        let profiles = ProfileStore::new(connection.clone());
+        let mut profile = OperatorConfig {
+            id: 0,
+            name: String::from("Home"),
+            call: String::from("W1AW"),
+            grid: String::from("FN31pr"),
+            cqz: String::from("5"),
+            ituz: String::from("8"),
+            dxcc: String::from("291"),
+            cont: String::from("NA"),
+            state: String::from("ct"),
+            lotw_cert: cert_path.display().to_string(),
+        };
+        profile.id = profiles.insert(&profile).unwrap();
+        let qsos = QsoStore::new(connection.clone());
+        let logs: Vec<LogbookExt> = [
+            form(profile.id, "dl1abc", "14.025", "CW", "2024-03-01 12:00:00"),
+            form(profile.id, "G4ABC", "14.285", "USB", "2024-03-01 12:05:30"),
+        ]
+        .iter()
+        .map(|form| qsos.insert_log(form).unwrap())
+        .collect();
+
+        let out = dir.join("upload.tq8");
+        let out = out.to_str().unwrap();
+        assert!(sign_to_file(connection.clone(), logs.clone(), "wrong", out, false).is_err());
+        let count = sign_to_file(connection.clone(), logs.clone(), "secret", out, false).unwrap();
+        assert_eq!(count, 2);
+
+        let mut upload = String::new();
+        GzDecoder::new(fs::File::open(out).unwrap())
+            .read_to_string(&mut upload)
+            .unwrap();
+        let records: Vec<&str> = upload.split("<eor>").collect();
+        assert_eq!(value(records[0], "Rec_Type"), "tCERT");
+        assert_eq!(value(records[1], "Rec_Type"), "tSTATION");
+        assert_eq!(value(records[1], "CALL"), "W1AW");
+        assert_eq!(value(records[1], "DXCC"), "291");
+        assert_eq!(value(records[1], "US_STATE"), "CT");
+
+        let contacts: Vec<&str> = records
+            .iter()
+            .copied()
+            .filter(|record| record.contains("<Rec_Type:8>tCONTACT"))
+            .collect();
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(value(contacts[0], "CALL"), "DL1ABC");
+        assert_eq!(value(contacts[1], "MODE"), "SSB");
+        assert_eq!(value(contacts[1], "QSO_TIME"), "12:05:30Z");
+        let expected = [
+            "5FN31PR8CT20MDL1ABC14.025CW2024-03-0112:00:00Z",
+            "5FN31PR8CT20MG4ABC14.285SSB2024-03-0112:05:30Z",
+        ];
+        for (contact, expected) in contacts.iter().zip(expected) {
+            let sign_data = value(contact, "SIGNDATA");
+            assert_eq!(sign_data, expected);
+            let signature = value(contact, "SIGN_LOTW_V2.0").replace('\n', "");
+            let signature = base64::decode_block(&signature).unwrap();
+            let mut verifier = Verifier::new(MessageDigest::sha1(), &public_key).unwrap();
+            verifier.update(sign_data.as_bytes()).unwrap();
+            assert!(verifier.verify(&signature).unwrap());
+        }
+
+        let logs: Vec<LogbookExt> = logs.iter().map(|log| qsos.get(log.id).unwrap()).collect();
+        assert!(logs.iter().all(|log| log.lotw_qsl.sent == "Y"));
+        // Both are sent now, so there is nothing left to sign without resending
+        assert!(sign_to_file(connection, logs, "secret", out, false).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_another_stations_certificate() {
+        let dir = env::temp_dir().join(format!("tuilog-lotw-call-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (cert_path, _) = certificate(&dir, "K1ABC", "");
+        let certificate = Certificate::load(&cert_path, "").unwrap();
+        assert_eq!(certificate.call(), "K1ABC");
+        let station = Station {
+            call: String::from("W1AW"),
+            dxcc: 291,
+            grid: String::from("FN31"),
+            cqz: None,
+            ituz: None,
+            state: String::new(),
+        };
+        assert!(sign(&certificate, &station, &[]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
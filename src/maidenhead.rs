@@ -0,0 +1,176 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Error};
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// A validated 4, 6 or 8 character Maidenhead locator, e.g. `FN31`, `FN31pr` or `FN31pr12`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Locator(String);
+
+/// Parses a locator in any case, normalising it to `FN31pr12` style.
+impl FromStr for Locator {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Locator, Error> {
+        let text = text.trim();
+        let chars: Vec<char> = text.chars().collect();
+        let valid = matches!(chars.len(), 4 | 6 | 8)
+            && chars.iter().enumerate().all(|(index, c)| match index {
+                0 | 1 => ('A'..='R').contains(&c.to_ascii_uppercase()),
+                4 | 5 => ('A'..='X').contains(&c.to_ascii_uppercase()),
+                _ => c.is_ascii_digit(),
+            });
+        if !valid {
+            return Err(anyhow!(
+                "'{}' is not a grid square (e.g. FN31 or FN31pr)",
+                text
+            ));
+        }
+        Ok(Locator(
+            chars
+                .iter()
+                .enumerate()
+                .map(|(index, c)| match index {
+                    0 | 1 => c.to_ascii_uppercase(),
+                    _ => c.to_ascii_lowercase(),
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl fmt::Display for Locator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Locator {
+    /// Latitude and longitude in degrees of the centre of the locator's square.
+    pub fn position(&self) -> (f64, f64) {
+        let bytes = self.0.to_ascii_uppercase().into_bytes();
+        let mut lon = -180.0;
+        let mut lat = -90.0;
+        // Fields are 20 by 10 degrees, then each pair splits the cell 10, 24 and 10 ways
+        let mut width = 20.0;
+        let mut height = 10.0;
+        for (pair, divisions) in bytes.chunks(2).zip([1.0, 10.0, 24.0, 10.0]) {
+            width /= divisions;
+            height /= divisions;
+            let base = if pair[0].is_ascii_digit() { b'0' } else { b'A' };
+            lon += (pair[0] - base) as f64 * width;
+            lat += (pair[1] - base) as f64 * height;
+        }
+        (lat + height / 2.0, lon + width / 2.0)
+    }
+}
+
+/// The great-circle path between two locators.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Path {
+    pub distance_km: f64,
+    /// Short-path bearing in degrees from north
+    pub bearing: f64,
+    pub long_path_bearing: f64,
+}
+
+impl Path {
+    pub fn between(from: &Locator, to: &Locator) -> Path {
+        let (lat1, lon1) = from.position();
+        let (lat2, lon2) = to.position();
+        let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+        let delta_lon = (lon2 - lon1).to_radians();
+        let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+            + lat1.cos() * lat2.cos() * (delta_lon / 2.0).sin().powi(2);
+        let distance_km = 2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin();
+        let bearing = (delta_lon.sin() * lat2.cos())
+            .atan2(lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * delta_lon.cos())
+            .to_degrees()
+            .rem_euclid(360.0);
+        Path {
+            distance_km,
+            bearing,
+            long_path_bearing: (bearing + 180.0) % 360.0,
+        }
+    }
+}
+
+/// Summary of a path for display, e.g. `5861 km, 48° (LP 228°)`.
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.0} km, {:.0}° (LP {:.0}°)",
+            self.distance_km, self.bearing, self.long_path_bearing
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locator(text: &str) -> Locator {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn locators_are_validated_and_normalised() {
+        for (text, normalised) in [
+            ("FN31", "FN31"),
+            ("fn31PR", "FN31pr"),
+            (" RR99xx ", "RR99xx"),
+            ("AA00aa00", "AA00aa00"),
+            ("jo01AB99", "JO01ab99"),
+        ] {
+            assert_eq!(locator(text).to_string(), normalised);
+        }
+        // Fields go up to R and subsquares up to X
+        for text in [
+            "",
+            "FN3",
+            "FN31p",
+            "FN31pr1",
+            "FN31pr123",
+            "SN31",
+            "FS31",
+            "FN3a",
+            "FN31py",
+            "FN31pr1x",
+        ] {
+            assert!(text.parse::<Locator>().is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn positions_are_square_centres() {
+        assert_eq!(locator("JO01").position(), (51.5, 1.0));
+        let (lat, lon) = locator("FN31pr").position();
+        assert!((lat - 41.729).abs() < 0.001 && (lon + 72.708).abs() < 0.001);
+    }
+
+    #[test]
+    fn distance_and_bearings() {
+        let path = Path::between(&locator("FN31pr"), &locator("JO01"));
+        assert!(
+            (path.distance_km - 5489.1).abs() < 0.1,
+            "{}",
+            path.distance_km
+        );
+        assert!((path.bearing - 51.94).abs() < 0.01, "{}", path.bearing);
+        assert_eq!(path.to_string(), "5489 km, 52° (LP 232°)");
+
+        let back = Path::between(&locator("JO01"), &locator("FN31pr"));
+        assert!((back.distance_km - path.distance_km).abs() < 1e-6);
+        assert!((back.bearing - 289.28).abs() < 0.01, "{}", back.bearing);
+
+        for path in [path, back] {
+            assert_eq!(path.long_path_bearing, (path.bearing + 180.0) % 360.0);
+        }
+        assert_eq!(
+            Path::between(&locator("FN31"), &locator("FN31")).distance_km,
+            0.0
+        );
+    }
+}
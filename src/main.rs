@@ -1,24 +1,43 @@
 use anyhow::{anyhow, Result};
+use clap::Parser;
 use cursive::event::Key;
 use cursive::menu::Tree;
 use cursive::views::Dialog;
 use homedir::get_my_home;
-use rusqlite::Connection;
-use std::sync::{Arc, Mutex};
 use std::fs;
+use std::path::PathBuf;
+use tuilog::store;
+
+mod award_screen;
+use award_screen::awards;
+
+mod cli;
+use cli::Cli;
+
+mod contest_log;
+use contest_log::contests;
+
+mod database;
+use database::{open_logbook, set_title};
+
 mod log;
 use log::new_log;
 
 mod logbook;
 use logbook::make_table;
 
-mod models;
-
 mod options;
 use options::options;
 
-fn main() -> Result<()> {
-    let mut homepath = get_my_home().unwrap().unwrap();
+mod rig;
+
+mod wsjtx_listener;
+
+fn default_database() -> Result<PathBuf> {
+    let mut homepath = get_my_home()
+        .ok()
+        .flatten()
+        .ok_or(anyhow!("ERR: Could not find your home folder. Pass --db or set TUILOG_DB."))?;
     homepath.push(".tuilog");
     if let Ok(folder_data) = fs::metadata(&homepath) {
         if !folder_data.is_dir() {
@@ -28,24 +47,33 @@ fn main() -> Result<()> {
         fs::create_dir(&homepath)?;
     }
     homepath.push("tuilog.db");
-    let connection = Connection::open(homepath)?;
-    let query = "
-        CREATE TABLE IF NOT EXISTS operatorconfig (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT, call TEXT, grid TEXT, cqz TEXT, ituz TEXT, dxcc TEXT, cont TEXT);
-    ";
-    connection.execute(query, ())?;
-    let query = "
-        CREATE TABLE IF NOT EXISTS logs (id INTEGER PRIMARY KEY AUTOINCREMENT, timestamp TEXT, call TEXT, rsttx TEXT, rstrx TEXT, band TEXT, frequency TEXT, mode TEXT, power TEXT, comments TEXT, operator_config INTEGER NOT NULL REFERENCES operatorConfig(id));
-    ";
-    connection.execute(query, ())?;
-
-    let connection = Arc::new(Mutex::new(connection));
+    Ok(homepath)
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let path = match cli.db {
+        Some(path) => path,
+        None => default_database()?,
+    };
+    let connection = store::open(&path)?;
+
+    if let Some(command) = cli.command {
+        return cli::run(command, connection);
+    }
 
     let mut siv = cursive::default();
     siv.set_autorefresh(true);
+    rig::start(&mut siv, connection.clone());
+    wsjtx_listener::start(&mut siv, connection.clone());
 
     let new_log_conn = connection.clone();
+    let contest_conn = connection.clone();
     let logbook_conn = connection.clone();
+    let awards_conn = connection.clone();
     let options_conn = connection.clone();
+    let open_conn = connection.clone();
+    let create_conn = connection.clone();
 
     siv.menubar().add_subtree(
         "File",
@@ -53,17 +81,36 @@ fn main() -> Result<()> {
             .leaf("New Log", move |s| {
                 new_log(s, new_log_conn.clone()).unwrap()
             })
+            .leaf("Contest", move |s| {
+                if let Err(err) = contests(s, contest_conn.clone()) {
+                    s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                }
+            })
             .leaf("Logbook", move |s| {
                 make_table(s, logbook_conn.clone()).unwrap()
             })
+            .leaf("Awards", move |s| {
+                if let Err(err) = awards(s, awards_conn.clone()) {
+                    s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                }
+            })
             .leaf("Options", move |s| {
                 options(s, options_conn.clone()).unwrap()
             })
+            .delimiter()
+            .leaf("Open Logbook", move |s| {
+                open_logbook(s, open_conn.clone(), false)
+            })
+            .leaf("New Logbook", move |s| {
+                open_logbook(s, create_conn.clone(), true)
+            })
+            .delimiter()
             .leaf("Quit", |s| s.quit()),
     );
 
     siv.add_global_callback(Key::Esc, |s| s.select_menubar());
 
+    set_title(&mut siv, &path);
     siv.add_layer(Dialog::text("TUILog v1.0.0").title("TUILog"));
 
     siv.run();
@@ -0,0 +1,236 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use rusqlite::Connection;
+
+// Each entry upgrades the schema by one version and is never edited once released.
+// New columns and tables must be appended here rather than changing earlier steps.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema, matching the tables created before versioning existed
+    "
+    CREATE TABLE IF NOT EXISTS operatorconfig (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT, call TEXT, grid TEXT, cqz TEXT, ituz TEXT, dxcc TEXT, cont TEXT);
+    CREATE TABLE IF NOT EXISTS logs (id INTEGER PRIMARY KEY AUTOINCREMENT, timestamp TEXT, call TEXT, rsttx TEXT, rstrx TEXT, band TEXT, frequency TEXT, mode TEXT, power TEXT, comments TEXT, operator_config INTEGER NOT NULL REFERENCES operatorConfig(id));
+    ",
+    // 2: key/value application settings
+    "
+    CREATE TABLE settings (key TEXT PRIMARY KEY, value TEXT);
+    ",
+    // 3: frequency stored as integer Hz, read as MHz unless a GHz, MHz, kHz or Hz unit follows the
+    // number; text that is not such a number is moved into the comments
+    "
+    CREATE TABLE logs_new (id INTEGER PRIMARY KEY AUTOINCREMENT, timestamp TEXT, call TEXT, rsttx TEXT, rstrx TEXT, band TEXT, frequency INTEGER, mode TEXT, power TEXT, comments TEXT, operator_config INTEGER NOT NULL REFERENCES operatorConfig(id));
+    INSERT INTO logs_new (id, timestamp, call, rsttx, rstrx, band, frequency, mode, power, comments, operator_config)
+        SELECT id, timestamp, call, rsttx, rstrx, band,
+            CASE WHEN valid THEN hz END,
+            mode, power,
+            CASE WHEN valid OR TRIM(COALESCE(frequency, '')) = '' THEN comments
+                ELSE TRIM(COALESCE(comments, '') || ' [Unparsed frequency: ' || frequency || ']') END,
+            operator_config
+        FROM (
+            SELECT *, number GLOB '*[0-9]*'
+                AND number NOT GLOB '*[^0-9.]*'
+                AND number NOT GLOB '*.*.*'
+                AND hz >= 1 AS valid
+            FROM (
+                SELECT *, CAST(ROUND(CAST(number AS REAL) * scale) AS INTEGER) AS hz
+                FROM (
+                    SELECT *,
+                        TRIM(CASE WHEN unit = 'hz' THEN SUBSTR(text, 1, LENGTH(text) - 2)
+                            WHEN unit <> '' THEN SUBSTR(text, 1, LENGTH(text) - 3)
+                            ELSE text END) AS number,
+                        CASE unit WHEN 'ghz' THEN 1e9 WHEN 'khz' THEN 1e3 WHEN 'hz' THEN 1 ELSE 1e6 END AS scale
+                    FROM (
+                        SELECT *,
+                            CASE WHEN text LIKE '%ghz' THEN 'ghz' WHEN text LIKE '%mhz' THEN 'mhz'
+                                WHEN text LIKE '%khz' THEN 'khz' WHEN text LIKE '%hz' THEN 'hz'
+                                ELSE '' END AS unit
+                        FROM (SELECT *, LOWER(TRIM(COALESCE(frequency, ''))) AS text FROM logs)
+                    )
+                )
+            )
+        );
+    DROP TABLE logs;
+    ALTER TABLE logs_new RENAME TO logs;
+    ",
+    // 4: time the QSO ended; the existing timestamp is the time it started
+    "
+    ALTER TABLE logs ADD COLUMN time_off TEXT;
+    ",
+    // 5: details about the contacted station, filled from the callbook
+    "
+    ALTER TABLE logs ADD COLUMN name TEXT NOT NULL DEFAULT '';
+    ALTER TABLE logs ADD COLUMN qth TEXT NOT NULL DEFAULT '';
+    ALTER TABLE logs ADD COLUMN state TEXT NOT NULL DEFAULT '';
+    ALTER TABLE logs ADD COLUMN gridsquare TEXT NOT NULL DEFAULT '';
+    ALTER TABLE logs ADD COLUMN license_class TEXT NOT NULL DEFAULT '';
+    ",
+    // 6: DXCC entity of the contacted station
+    "
+    ALTER TABLE logs ADD COLUMN country TEXT NOT NULL DEFAULT '';
+    ALTER TABLE logs ADD COLUMN dxcc INTEGER;
+    ALTER TABLE logs ADD COLUMN cqz INTEGER;
+    ALTER TABLE logs ADD COLUMN ituz INTEGER;
+    ALTER TABLE logs ADD COLUMN cont TEXT NOT NULL DEFAULT '';
+    ",
+    // 7: great-circle path to the contacted station's grid square, in km and degrees
+    "
+    ALTER TABLE logs ADD COLUMN distance REAL;
+    ALTER TABLE logs ADD COLUMN bearing REAL;
+    ",
+    // 8: whether the QSO is confirmed by paper QSL, LoTW or eQSL, as ADIF Y/N/R/I/V
+    "
+    ALTER TABLE logs ADD COLUMN qsl_rcvd TEXT NOT NULL DEFAULT 'N';
+    ALTER TABLE logs ADD COLUMN lotw_qsl_rcvd TEXT NOT NULL DEFAULT 'N';
+    ALTER TABLE logs ADD COLUMN eqsl_qsl_rcvd TEXT NOT NULL DEFAULT 'N';
+    ",
+    // 9: QSL sent status and the dates cards and confirmations went out and came back
+    "
+    ALTER TABLE logs ADD COLUMN qsl_sent TEXT NOT NULL DEFAULT 'N';
+    ALTER TABLE logs ADD COLUMN qsl_sent_date TEXT;
+    ALTER TABLE logs ADD COLUMN qsl_rcvd_date TEXT;
+    ALTER TABLE logs ADD COLUMN lotw_qsl_sent TEXT NOT NULL DEFAULT 'N';
+    ALTER TABLE logs ADD COLUMN lotw_qsl_sent_date TEXT;
+    ALTER TABLE logs ADD COLUMN lotw_qsl_rcvd_date TEXT;
+    ALTER TABLE logs ADD COLUMN eqsl_qsl_sent TEXT NOT NULL DEFAULT 'N';
+    ALTER TABLE logs ADD COLUMN eqsl_qsl_sent_date TEXT;
+    ALTER TABLE logs ADD COLUMN eqsl_qsl_rcvd_date TEXT;
+    ",
+    // 10: LoTW station location state and the .p12 certificate that signs uploads for a profile
+    "
+    ALTER TABLE operatorconfig ADD COLUMN state TEXT NOT NULL DEFAULT '';
+    ALTER TABLE operatorconfig ADD COLUMN lotw_cert TEXT NOT NULL DEFAULT '';
+    ",
+    // 11: contest sessions, and the contest, serials and exchange strings sent and received per QSO
+    "
+    CREATE TABLE contests (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, cabrillo TEXT NOT NULL DEFAULT '', template TEXT NOT NULL, sent_exchange TEXT NOT NULL DEFAULT '', operator_config INTEGER NOT NULL REFERENCES operatorconfig(id), started TEXT NOT NULL);
+    ALTER TABLE logs ADD COLUMN contest INTEGER REFERENCES contests(id);
+    ALTER TABLE logs ADD COLUMN contest_id TEXT NOT NULL DEFAULT '';
+    ALTER TABLE logs ADD COLUMN stx INTEGER;
+    ALTER TABLE logs ADD COLUMN stx_string TEXT NOT NULL DEFAULT '';
+    ALTER TABLE logs ADD COLUMN srx INTEGER;
+    ALTER TABLE logs ADD COLUMN srx_string TEXT NOT NULL DEFAULT '';
+    ",
+    // 12: how each contest is scored: its points table, multiplier types and whether multipliers count per band
+    "
+    ALTER TABLE contests ADD COLUMN points TEXT NOT NULL DEFAULT '';
+    ALTER TABLE contests ADD COLUMN multipliers TEXT NOT NULL DEFAULT '';
+    ALTER TABLE contests ADD COLUMN mults_per_band INTEGER NOT NULL DEFAULT 0;
+    ",
+];
+
+pub fn schema_version(connection: &Connection) -> Result<usize> {
+    let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+fn backup(path: &Path, version: usize) -> Result<()> {
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(format!(
+        ".v{}-{}.bak",
+        version,
+        Utc::now().format("%Y%m%d%H%M%S")
+    ));
+    fs::copy(path, &backup_path)?;
+    Ok(())
+}
+
+fn apply(connection: &mut Connection, version: usize) -> Result<()> {
+    let tx = connection.transaction()?;
+    for migration in &MIGRATIONS[version..] {
+        tx.execute_batch(migration)?;
+    }
+    tx.pragma_update(None, "user_version", MIGRATIONS.len() as i64)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Brings the database at `path` up to the latest schema version.
+///
+/// A copy of the database file is taken before any migration runs, and all
+/// pending migrations are applied in a single transaction.
+pub fn migrate(connection: &mut Connection, path: &Path) -> Result<()> {
+    let version = schema_version(connection)?;
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "ERR: {} has schema version {}, but this TUILog only supports up to {}. Please upgrade TUILog.",
+            path.display(),
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+    if version == MIGRATIONS.len() {
+        return Ok(());
+    }
+    if fs::metadata(path).map(|meta| meta.len() > 0).unwrap_or(false) {
+        backup(path, version)?;
+    }
+    apply(connection, version)
+}
+
+/// Brings an in-memory database up to the latest schema version. There is no file to back up.
+pub fn migrate_in_memory(connection: &mut Connection) -> Result<()> {
+    let version = schema_version(connection)?;
+    apply(connection, version.min(MIGRATIONS.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_memory_database_reaches_latest_version() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate_in_memory(&mut connection).unwrap();
+        assert_eq!(schema_version(&connection).unwrap(), MIGRATIONS.len());
+        // Already current, so nothing runs again
+        migrate_in_memory(&mut connection).unwrap();
+        assert_eq!(schema_version(&connection).unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn text_frequencies_become_hz_or_are_flagged() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        for migration in &MIGRATIONS[..2] {
+            connection.execute_batch(migration).unwrap();
+        }
+        connection.pragma_update(None, "user_version", 2).unwrap();
+        connection
+            .execute("INSERT INTO operatorconfig (name, call) VALUES ('Home', 'W1AW')", ())
+            .unwrap();
+        for (frequency, comments) in [
+            ("14.074", "ft8"),
+            ("7030 kHz", ""),
+            ("14.0.7", "typo"),
+            ("fourteen", ""),
+            ("", "none"),
+        ] {
+            connection
+                .execute(
+                    "INSERT INTO logs (call, frequency, comments, operator_config) VALUES ('K1ABC', ?, ?, 1)",
+                    (frequency, comments),
+                )
+                .unwrap();
+        }
+
+        migrate_in_memory(&mut connection).unwrap();
+        let mut stmt = connection
+            .prepare("SELECT frequency, comments FROM logs ORDER BY id")
+            .unwrap();
+        let rows: Vec<(Option<u64>, String)> = stmt
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                (Some(14_074_000), String::from("ft8")),
+                (Some(7_030_000), String::new()),
+                (None, String::from("typo [Unparsed frequency: 14.0.7]")),
+                (None, String::from("[Unparsed frequency: fourteen]")),
+                (None, String::from("none")),
+            ]
+        );
+    }
+}
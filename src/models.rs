@@ -1,10 +1,18 @@
 use std::cmp::Ordering;
 
-use chrono::NaiveDateTime;
+use chrono::{Duration, NaiveDateTime};
 use cursive_table_view::TableViewItem;
 
+use crate::contest::Exchange;
+use crate::dxcc::Entity;
+use crate::frequency::Frequency;
+use crate::maidenhead::{Locator, Path};
+use crate::qsl::{Qsl, QslService};
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum LogbookColumn {
+    Marked,
     Timestamp,
     Call,
     RSTTX,
@@ -12,34 +20,41 @@ pub enum LogbookColumn {
     Band,
     Frequency,
     Mode,
+    Duration,
+    Qsl,
     Comments,
 }
 
-impl LogbookColumn {
-    fn as_str(&self) -> &str {
-        match *self {
-            LogbookColumn::Timestamp => "Timestamp",
-            LogbookColumn::Call => "Call",
-            LogbookColumn::RSTTX => "RSTTX",
-            LogbookColumn::RSTRX => "RSTRX",
-            LogbookColumn::Band => "Band",
-            LogbookColumn::Frequency => "Frequency",
-            LogbookColumn::Mode => "Mode",
-            LogbookColumn::Comments => "Comments",
-        }
-    }
-}
-
 #[derive(Clone, Debug)]
 pub struct Logbook {
+    pub id: u64,
     pub timestamp: NaiveDateTime,
     pub call: String,
     pub rsttx: String,
     pub rstrx: String,
     pub band: String,
-    pub frequency: String,
+    pub frequency: Option<Frequency>,
     pub mode: String,
     pub comments: String,
+    pub time_off: Option<NaiveDateTime>,
+    /// Paper, LoTW and eQSL status in the short form of [`crate::qsl::summary`]
+    pub qsl: String,
+    /// Picked in the Logbook table for a QSL or LoTW batch
+    pub marked: bool,
+}
+
+impl Logbook {
+    /// How long the QSO lasted, when its end time is known.
+    pub fn duration(&self) -> Option<Duration> {
+        self.time_off.map(|time_off| time_off - self.timestamp)
+    }
+}
+
+// Rows are the same QSO when they share a database id, so edits keep the table selection
+impl PartialEq for Logbook {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -52,32 +67,152 @@ pub struct OperatorConfig {
     pub ituz: String,
     pub dxcc: String,
     pub cont: String,
+    /// US state (or Canadian province) of the station location, as LoTW expects it
+    pub state: String,
+    /// Callsign certificate (.p12) used to sign LoTW uploads
+    pub lotw_cert: String,
 }
 
 #[derive(Clone, Debug)]
 pub struct LogbookExt {
+    pub id: u64,
     pub timestamp: NaiveDateTime,
     pub call: String,
     pub rsttx: String,
     pub rstrx: String,
     pub band: String,
-    pub frequency: String,
+    pub frequency: Option<Frequency>,
     pub mode: String,
     pub power: String,
     pub comments: String,
+    /// When the QSO ended; `timestamp` is when it started
+    pub time_off: Option<NaiveDateTime>,
+    /// Operator name of the contacted station
+    pub name: String,
+    pub qth: String,
+    pub state: String,
+    pub gridsquare: String,
+    pub license_class: String,
+    /// DXCC entity name of the contacted station
+    pub country: String,
+    pub dxcc: Option<u32>,
+    pub cqz: Option<u32>,
+    pub ituz: Option<u32>,
+    pub cont: String,
+    /// Great-circle distance in km from our grid to `gridsquare`
+    pub distance: Option<f64>,
+    /// Short-path bearing in degrees from our grid to `gridsquare`
+    pub bearing: Option<f64>,
+    pub paper_qsl: Qsl,
+    pub lotw_qsl: Qsl,
+    pub eqsl_qsl: Qsl,
+    /// Contest session the QSO was logged in
+    pub contest: Option<u64>,
+    /// ADIF `CONTEST_ID` of the contest, e.g. `CQ-WW-CW`
+    pub contest_id: String,
+    pub exchange_sent: Exchange,
+    pub exchange_rcvd: Exchange,
     pub operator: OperatorConfig,
 }
 
+impl LogbookExt {
+    /// Stores the contacted station's entity, clearing it when the call did not resolve.
+    pub fn set_entity(&mut self, entity: Option<&Entity>) {
+        let entity = entity.cloned().unwrap_or_default();
+        self.country = entity.name;
+        self.dxcc = entity.dxcc;
+        self.cqz = Some(entity.cqz).filter(|cqz| *cqz > 0);
+        self.ituz = Some(entity.ituz).filter(|ituz| *ituz > 0);
+        self.cont = entity.cont;
+    }
+
+    /// Fills in the entity fields still empty from `entity`, keeping any already set, e.g.
+    /// from an ADIF import. Returns whether anything changed.
+    pub fn fill_entity(&mut self, entity: Option<&Entity>) -> bool {
+        let Some(entity) = entity else {
+            return false;
+        };
+        let mut changed = false;
+        if self.country.is_empty() && !entity.name.is_empty() {
+            self.country = entity.name.clone();
+            changed = true;
+        }
+        if self.dxcc.is_none() && entity.dxcc.is_some() {
+            self.dxcc = entity.dxcc;
+            changed = true;
+        }
+        if self.cqz.is_none() && entity.cqz > 0 {
+            self.cqz = Some(entity.cqz);
+            changed = true;
+        }
+        if self.ituz.is_none() && entity.ituz > 0 {
+            self.ituz = Some(entity.ituz);
+            changed = true;
+        }
+        if self.cont.is_empty() && !entity.cont.is_empty() {
+            self.cont = entity.cont.clone();
+            changed = true;
+        }
+        changed
+    }
+
+    /// Whether a paper QSL or LoTW confirms the QSO, which is what the ARRL awards accept.
+    pub fn confirmed(&self) -> bool {
+        self.paper_qsl.received() || self.lotw_qsl.received()
+    }
+
+    pub fn qsl(&self, service: QslService) -> &Qsl {
+        match service {
+            QslService::Paper => &self.paper_qsl,
+            QslService::Lotw => &self.lotw_qsl,
+            QslService::Eqsl => &self.eqsl_qsl,
+        }
+    }
+
+    pub fn qsl_mut(&mut self, service: QslService) -> &mut Qsl {
+        match service {
+            QslService::Paper => &mut self.paper_qsl,
+            QslService::Lotw => &mut self.lotw_qsl,
+            QslService::Eqsl => &mut self.eqsl_qsl,
+        }
+    }
+
+    /// Works out the path from the profile's grid to `gridsquare`, clearing it unless both are valid.
+    pub fn set_path(&mut self) {
+        let path = match (
+            self.operator.grid.parse::<Locator>(),
+            self.gridsquare.parse::<Locator>(),
+        ) {
+            (Ok(from), Ok(to)) => Some(Path::between(&from, &to)),
+            _ => None,
+        };
+        self.distance = path.map(|path| path.distance_km);
+        self.bearing = path.map(|path| path.bearing);
+    }
+}
+
 impl TableViewItem<LogbookColumn> for Logbook {
     fn to_column(&self, column: LogbookColumn) -> String {
         match column {
+            LogbookColumn::Marked => String::from(if self.marked { "*" } else { "" }),
             LogbookColumn::Timestamp => self.timestamp.to_string(),
             LogbookColumn::Call => self.call.clone(),
             LogbookColumn::RSTTX => self.rsttx.clone(),
             LogbookColumn::RSTRX => self.rstrx.clone(),
             LogbookColumn::Band => self.band.clone(),
-            LogbookColumn::Frequency => self.frequency.clone(),
+            LogbookColumn::Frequency => self
+                .frequency
+                .map(|frequency| frequency.to_string())
+                .unwrap_or_default(),
             LogbookColumn::Mode => self.mode.clone(),
+            LogbookColumn::Duration => self
+                .duration()
+                .map(|duration| {
+                    let seconds = duration.num_seconds().max(0);
+                    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+                })
+                .unwrap_or_default(),
+            LogbookColumn::Qsl => self.qsl.clone(),
             LogbookColumn::Comments => self.comments.clone(),
         }
     }
@@ -87,6 +222,7 @@ impl TableViewItem<LogbookColumn> for Logbook {
         Self: Sized,
     {
         match column {
+            LogbookColumn::Marked => self.marked.cmp(&other.marked),
             LogbookColumn::Timestamp => self.timestamp.cmp(&other.timestamp),
             LogbookColumn::Call => self.call.cmp(&other.call),
             LogbookColumn::RSTTX => self.rsttx.cmp(&other.rsttx),
@@ -94,6 +230,8 @@ impl TableViewItem<LogbookColumn> for Logbook {
             LogbookColumn::Band => self.band.cmp(&other.band),
             LogbookColumn::Frequency => self.frequency.cmp(&other.frequency),
             LogbookColumn::Mode => self.mode.cmp(&other.mode),
+            LogbookColumn::Duration => self.duration().cmp(&other.duration()),
+            LogbookColumn::Qsl => self.qsl.cmp(&other.qsl),
             LogbookColumn::Comments => self.comments.cmp(&other.comments),
         }
     }
@@ -0,0 +1,197 @@
+use std::net::UdpSocket;
+
+use anyhow::Result;
+
+use crate::bandplan::BandPlan;
+use crate::models::LogbookExt;
+use crate::store::SettingsStore;
+
+/// What happened to a QSO, which picks the N1MM packet announcing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Replaced,
+    Deleted,
+}
+
+/// Comma-separated host:port list from the `n1mm_targets` setting; blank means off.
+pub fn targets(settings: &SettingsStore) -> Result<Vec<String>> {
+    Ok(settings
+        .get("n1mm_targets")?
+        .unwrap_or_default()
+        .split(',')
+        .map(|target| target.trim().to_string())
+        .filter(|target| !target.is_empty())
+        .collect())
+}
+
+/// Random prefix that, with the QSO id, makes up the ID N1MM listeners match replaces and
+/// deletes on. It is kept per logbook so two logbooks never share IDs.
+fn log_id(settings: &SettingsStore) -> Result<u64> {
+    if let Some(id) = settings
+        .get("n1mm_log_id")?
+        .and_then(|id| u64::from_str_radix(&id, 16).ok())
+    {
+        return Ok(id);
+    }
+    let id = rand::random::<u64>();
+    settings.set("n1mm_log_id", &format!("{:016x}", id))?;
+    Ok(id)
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn element(out: &mut String, name: &str, value: &str) {
+    out.push_str(&format!("\t<{}>{}</{}>\n", name, escape(value), name));
+}
+
+/// N1MM's value for each band. It is fixed per band rather than the band's lower edge,
+/// which moves with the region (160m starts at 1.81 MHz in region 1).
+const BANDS: &[(&str, &str)] = &[
+    ("2200M", "0.136"),
+    ("630M", "0.472"),
+    ("160M", "1.8"),
+    ("80M", "3.5"),
+    ("60M", "5"),
+    ("40M", "7"),
+    ("30M", "10"),
+    ("20M", "14"),
+    ("17M", "18"),
+    ("15M", "21"),
+    ("12M", "24"),
+    ("10M", "28"),
+    ("6M", "50"),
+    ("4M", "70"),
+    ("2M", "144"),
+    ("1.25M", "222"),
+    ("70CM", "420"),
+    ("33CM", "902"),
+    ("23CM", "1240"),
+];
+
+/// N1MM's band name (`1.8`, `3.5`, `14`, `144`). A band only a band plan file adds falls
+/// back to its lower edge in MHz.
+fn band(plan: &BandPlan, log: &LogbookExt) -> String {
+    let name = log.band.trim();
+    match BANDS
+        .iter()
+        .find(|(band, _)| band.eq_ignore_ascii_case(name))
+    {
+        Some((_, value)) => value.to_string(),
+        None => plan
+            .band(name)
+            .map(|band| band.lower.to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// Builds the XML packet N1MM Logger+ broadcasts for `change`, identified by `id`.
+pub fn packet(change: Change, log: &LogbookExt, id: &str, plan: &BandPlan) -> String {
+    let root = match change {
+        Change::Added => "contactinfo",
+        Change::Replaced => "contactreplace",
+        Change::Deleted => "contactdelete",
+    };
+    let timestamp = log.timestamp.format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str(&format!("<{}>\n", root));
+    element(&mut out, "app", "TUILog");
+    if change == Change::Deleted {
+        element(&mut out, "timestamp", &timestamp);
+        element(&mut out, "call", &log.call);
+        element(&mut out, "contestnr", "0");
+        element(&mut out, "StationName", "TUILog");
+        element(&mut out, "ID", id);
+        out.push_str(&format!("</{}>\n", root));
+        return out;
+    }
+    // N1MM sends frequencies in tens of Hz
+    let frequency = log
+        .frequency
+        .map(|frequency| (frequency.0 / 10).to_string())
+        .unwrap_or_default();
+    let zone = log.cqz.map(|cqz| cqz.to_string()).unwrap_or_default();
+    let serial = |serial: Option<u32>| serial.unwrap_or(0).to_string();
+    let contest = match log.contest_id.as_str() {
+        "" => "DX",
+        contest => contest,
+    };
+    element(&mut out, "contestname", contest);
+    element(&mut out, "contestnr", "0");
+    element(&mut out, "timestamp", &timestamp);
+    element(&mut out, "mycall", &log.operator.call);
+    element(&mut out, "band", &band(plan, log));
+    element(&mut out, "rxfreq", &frequency);
+    element(&mut out, "txfreq", &frequency);
+    element(&mut out, "operator", &log.operator.call);
+    element(&mut out, "mode", &log.mode);
+    element(&mut out, "call", &log.call);
+    element(&mut out, "countryprefix", "");
+    element(&mut out, "wpxprefix", "");
+    element(&mut out, "stationprefix", &log.operator.call);
+    element(&mut out, "continent", &log.cont);
+    element(&mut out, "snt", &log.rsttx);
+    element(&mut out, "sntnr", &serial(log.exchange_sent.serial));
+    element(&mut out, "rcv", &log.rstrx);
+    element(&mut out, "rcvnr", &serial(log.exchange_rcvd.serial));
+    element(&mut out, "gridsquare", &log.gridsquare);
+    element(&mut out, "exchange1", &log.exchange_rcvd.text);
+    element(&mut out, "section", "");
+    element(&mut out, "comment", &log.comments);
+    element(&mut out, "qth", &log.qth);
+    element(&mut out, "name", &log.name);
+    element(&mut out, "power", &log.power);
+    element(&mut out, "misctext", "");
+    element(&mut out, "zone", &zone);
+    element(&mut out, "prec", "");
+    element(&mut out, "ck", "0");
+    element(&mut out, "ismultiplier1", "0");
+    element(&mut out, "ismultiplier2", "0");
+    element(&mut out, "ismultiplier3", "0");
+    element(&mut out, "points", "1");
+    element(&mut out, "radionr", "1");
+    element(&mut out, "run1run2", "1");
+    element(&mut out, "RoverLocation", "");
+    element(&mut out, "RadioInterfaced", "0");
+    element(&mut out, "NetworkedCompNr", "0");
+    element(&mut out, "IsOriginal", "True");
+    element(&mut out, "NetBiosName", "");
+    element(&mut out, "IsRunQSO", "0");
+    element(&mut out, "StationName", "TUILog");
+    element(&mut out, "ID", id);
+    element(&mut out, "IsClaimedQso", "1");
+    out.push_str(&format!("</{}>\n", root));
+    out
+}
+
+/// Sends the packet for `change` to every target in the `n1mm_targets` setting. Targets
+/// that cannot be reached are skipped: the QSO is already saved, and UDP listeners come
+/// and go.
+pub fn broadcast(settings: &SettingsStore, change: Change, log: &LogbookExt) -> Result<()> {
+    let targets = targets(settings)?;
+    if targets.is_empty() {
+        return Ok(());
+    }
+    let id = format!("{:016x}{:016x}", log_id(settings)?, log.id);
+    let plan = BandPlan::load(settings)?;
+    let packet = packet(change, log, &id, &plan);
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_broadcast(true)?;
+    for target in targets {
+        let _ = socket.send_to(packet.as_bytes(), target.as_str());
+    }
+    Ok(())
+}
@@ -1,51 +1,38 @@
-use std::{sync::{Arc, Mutex}, thread::sleep, time::Duration};
+use std::{net::ToSocketAddrs, path::Path, sync::{Arc, Mutex}, thread::sleep, time::Duration};
 
-use crate::models::OperatorConfig;
+use tuilog::bandplan::BandPlan;
+use tuilog::dxcc::CountryFile;
+use tuilog::maidenhead::Locator;
+use tuilog::models::OperatorConfig;
+use tuilog::settings::DupeSettings;
+use tuilog::store::{ProfileStore, SettingsStore};
 use anyhow::{anyhow, Result};
 use cursive::{
     view::{Nameable, Resizable},
-    views::{Button, Dialog, DummyView, EditView, LinearLayout, NamedView, SelectView, TextView},
+    views::{Button, Checkbox, Dialog, DummyView, EditView, LinearLayout, NamedView, SelectView, TextView},
     Cursive,
 };
 use cursive_aligned_view::Alignable;
 use rusqlite::Connection;
 
 fn add_option(connection: Arc<Mutex<Connection>>) -> Result<()> {
-    if let Ok(conn) = connection.lock() {
-        conn.execute(
-            "INSERT INTO operatorconfig (name, call, grid, cqz, ituz, dxcc, cont) VALUES ('New Option', '', '', '', '', '', '')",
-            (),
-        )?;
-        Ok(())
-    } else {
-        Err(anyhow!("Could not lock connection"))
-    }
+    ProfileStore::new(connection).insert(&OperatorConfig {
+        id: 0,
+        name: "New Option".to_string(),
+        call: String::new(),
+        grid: String::new(),
+        cqz: String::new(),
+        ituz: String::new(),
+        dxcc: String::new(),
+        cont: String::new(),
+        state: String::new(),
+        lotw_cert: String::new(),
+    })?;
+    Ok(())
 }
 
 fn update_select(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
-    let connection = connection.clone();
-    let options = if let Ok(conn) = connection.lock() {
-        let mut stmt = conn.prepare("SELECT * FROM operatorconfig ORDER BY id ASC")?;
-        let mut options: Vec<OperatorConfig> = Vec::new();
-        let options_out = stmt.query_map((), |row| {
-            Ok(OperatorConfig {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                call: row.get(2)?,
-                grid: row.get(3)?,
-                cqz: row.get(4)?,
-                ituz: row.get(5)?,
-                dxcc: row.get(6)?,
-                cont: row.get(7)?,
-            })
-        })?;
-        for option in options_out {
-            options.push(option?);
-        }
-        options
-    } else {
-        return Err(anyhow!("Could not lock connection"));
-    };
+    let options = ProfileStore::new(connection).list()?;
     s.call_on_name("options", move |view: &mut SelectView<OperatorConfig>| {
         for _ in 0..view.len() {
             view.remove_item(0);
@@ -81,66 +68,132 @@ fn save(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
     let cont = s
         .call_on_name("cont", |view: &mut EditView| view.get_content())
         .unwrap();
-    if let Ok(conn) = connection.lock() {
-        let stmt = "UPDATE operatorconfig SET name = ?, call = ?, grid = ?, cqz = ?, ituz = ?, dxcc = ?, cont = ? WHERE id = ?";
-        conn.execute(
-            stmt,
-            (
-                name,
-                call,
-                grid,
-                cqz,
-                ituz,
-                dxcc,
-                cont,
-                id,
-            ),
-        )?;
-        let cb_sink = s.cb_sink().clone();
-        std::thread::spawn(move || {
-            cb_sink.send(Box::new(|s: &mut Cursive| {
-                s.add_layer(Dialog::text("Save Complete!").title("Saved"));
-            })).unwrap();
-            sleep(Duration::from_secs(2));
-            cb_sink.send(Box::new(|s: &mut Cursive| {
-                s.pop_layer();
-            })).unwrap();
-        });
-        Ok(())
-    } else {
-        Err(anyhow!("Could not lock connection"))
+    let state = s
+        .call_on_name("state", |view: &mut EditView| view.get_content())
+        .unwrap();
+    let lotw_cert = s
+        .call_on_name("lotw_cert", |view: &mut EditView| view.get_content())
+        .unwrap();
+    // Distance and bearing are worked out from this grid, so it has to be a real locator
+    let grid = match grid.trim() {
+        "" => String::new(),
+        grid => grid
+            .parse::<Locator>()
+            .map_err(|err| anyhow!("Grid: {}", err))?
+            .to_string(),
+    };
+    // The password is asked for when signing, so only check the file is there
+    if !lotw_cert.trim().is_empty() && !Path::new(lotw_cert.trim()).is_file() {
+        return Err(anyhow!("LoTW certificate {} does not exist", lotw_cert.trim()));
     }
+    ProfileStore::new(connection).update(&OperatorConfig {
+        id: id.parse()?,
+        name: name.to_string(),
+        call: call.to_string(),
+        grid,
+        cqz: cqz.to_string(),
+        ituz: ituz.to_string(),
+        dxcc: dxcc.to_string(),
+        cont: cont.to_string(),
+        state: state.trim().to_uppercase(),
+        lotw_cert: lotw_cert.trim().to_string(),
+    })?;
+    let cb_sink = s.cb_sink().clone();
+    std::thread::spawn(move || {
+        cb_sink.send(Box::new(|s: &mut Cursive| {
+            s.add_layer(Dialog::text("Save Complete!").title("Saved"));
+        })).unwrap();
+        sleep(Duration::from_secs(2));
+        cb_sink.send(Box::new(|s: &mut Cursive| {
+            s.pop_layer();
+        })).unwrap();
+    });
+    Ok(())
 }
 
 fn delete_option(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
     let id = s
         .call_on_name("id", |view: &mut EditView| view.get_content())
         .unwrap();
-    if let Ok(conn) = connection.lock() {
-        let stmt = "DELETE FROM operatorconfig WHERE id = ?";
-        conn.execute(
-            stmt,
-            (
-                id,
-            ),
-        )?;
-        let cb_sink = s.cb_sink().clone();
-        std::thread::spawn(move || {
-            cb_sink.send(Box::new(|s: &mut Cursive| {
-                s.add_layer(Dialog::text("Delete Complete!").title("Deleted"));
-            })).unwrap();
-            sleep(Duration::from_secs(2));
-            cb_sink.send(Box::new(|s: &mut Cursive| {
-                s.pop_layer();
-            })).unwrap();
-        });
-        Ok(())
+    ProfileStore::new(connection).delete(id.parse()?)?;
+    let cb_sink = s.cb_sink().clone();
+    std::thread::spawn(move || {
+        cb_sink.send(Box::new(|s: &mut Cursive| {
+            s.add_layer(Dialog::text("Delete Complete!").title("Deleted"));
+        })).unwrap();
+        sleep(Duration::from_secs(2));
+        cb_sink.send(Box::new(|s: &mut Cursive| {
+            s.pop_layer();
+        })).unwrap();
+    });
+    Ok(())
+}
+
+fn save_settings(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
+    let block = s
+        .call_on_name("dupe_block", |view: &mut Checkbox| view.is_checked())
+        .unwrap();
+    let window_hours = s
+        .call_on_name("dupe_window_hours", |view: &mut EditView| view.get_content())
+        .unwrap();
+    let window_hours = if window_hours.trim().is_empty() {
+        0
     } else {
-        Err(anyhow!("Could not lock connection"))
+        window_hours
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("Dupe window must be a whole number of hours"))?
+    };
+    let region = s
+        .call_on_name("itu_region", |view: &mut SelectView<u8>| view.selection())
+        .unwrap()
+        .map(|region| *region)
+        .unwrap_or(2);
+    let bandplan_file = s
+        .call_on_name("bandplan_file", |view: &mut EditView| view.get_content())
+        .unwrap();
+    let rig_address = s
+        .call_on_name("rig_address", |view: &mut EditView| view.get_content())
+        .unwrap();
+    let wsjtx_address = s
+        .call_on_name("wsjtx_address", |view: &mut EditView| view.get_content())
+        .unwrap();
+    if !wsjtx_address.trim().is_empty() {
+        wsjtx_address
+            .trim()
+            .to_socket_addrs()
+            .map_err(|err| anyhow!("WSJT-X address: {}", err))?;
+    }
+    let n1mm_targets = s
+        .call_on_name("n1mm_targets", |view: &mut EditView| view.get_content())
+        .unwrap();
+    for target in n1mm_targets.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        target
+            .to_socket_addrs()
+            .map_err(|err| anyhow!("N1MM target {}: {}", target, err))?;
     }
+    let callbook_file = s
+        .call_on_name("callbook_file", |view: &mut EditView| view.get_content())
+        .unwrap();
+    let cty_file = s
+        .call_on_name("cty_file", |view: &mut EditView| view.get_content())
+        .unwrap();
+    if !cty_file.trim().is_empty() {
+        CountryFile::load(cty_file.trim())?;
+    }
+    // Nothing is written until every field has passed its check
+    let mut values = BandPlan::values(region, bandplan_file.trim())?;
+    values.extend([
+        ("rig_address", rig_address.trim().to_string()),
+        ("wsjtx_address", wsjtx_address.trim().to_string()),
+        ("n1mm_targets", n1mm_targets.trim().to_string()),
+        ("callbook_file", callbook_file.trim().to_string()),
+        ("cty_file", cty_file.trim().to_string()),
+    ]);
+    values.extend(DupeSettings { block, window_hours }.values());
+    SettingsStore::new(connection).set_all(&values)
 }
 
-
 fn delete(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()> {
     s.add_layer(Dialog::around(
         LinearLayout::vertical()
@@ -189,10 +242,42 @@ pub fn options(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()
         s.call_on_name("cont", move |view: &mut EditView| {
             view.set_content(item.cont.to_string());
         });
+        s.call_on_name("state", move |view: &mut EditView| {
+            view.set_content(item.state.to_string());
+        });
+        s.call_on_name("lotw_cert", move |view: &mut EditView| {
+            view.set_content(item.lotw_cert.to_string());
+        });
     }).with_name("options");
     let add_connection = connection.clone();
     let save_connection = connection.clone();
     let delete_connection = connection.clone();
+    let settings_connection = connection.clone();
+    let dupe_settings = DupeSettings::load(&SettingsStore::new(connection.clone()))?;
+    let band_plan = BandPlan::load(&SettingsStore::new(connection.clone()))?;
+    let bandplan_file = SettingsStore::new(connection.clone())
+        .get("bandplan_file")?
+        .unwrap_or_default();
+    let rig_address = SettingsStore::new(connection.clone())
+        .get("rig_address")?
+        .unwrap_or_default();
+    let wsjtx_address = SettingsStore::new(connection.clone())
+        .get("wsjtx_address")?
+        .unwrap_or_default();
+    let n1mm_targets = SettingsStore::new(connection.clone())
+        .get("n1mm_targets")?
+        .unwrap_or_default();
+    let callbook_file = SettingsStore::new(connection.clone())
+        .get("callbook_file")?
+        .unwrap_or_default();
+    let cty_file = SettingsStore::new(connection.clone())
+        .get("cty_file")?
+        .unwrap_or_default();
+    let mut region_select = SelectView::new().popup();
+    region_select.add_item("Region 1", 1u8);
+    region_select.add_item("Region 2", 2u8);
+    region_select.add_item("Region 3", 3u8);
+    region_select.set_selection(band_plan.region.clamp(1, 3) as usize - 1);
     s.pop_layer();
     s.add_layer(
         Dialog::around(
@@ -264,10 +349,27 @@ pub fn options(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()
                                 )
                                 .align_center(),
                         )
+                        .child(
+                            LinearLayout::horizontal()
+                                .child(
+                                    Dialog::around(EditView::new().with_name("state").fixed_width(10))
+                                        .title("State"),
+                                )
+                                .child(
+                                    Dialog::around(
+                                        EditView::new().with_name("lotw_cert").fixed_width(30),
+                                    )
+                                    .title("LoTW Certificate (.p12)"),
+                                )
+                                .align_center(),
+                        )
                         .child(
                             LinearLayout::horizontal()
                                 .child(Button::new("Save", move |s| {
-                                    save(s, save_connection.clone()).unwrap();
+                                    if let Err(err) = save(s, save_connection.clone()) {
+                                        s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                                        return;
+                                    }
                                     update_select(s, save_connection.clone()).unwrap();
                                 }))
                                 .child(DummyView)
@@ -275,6 +377,95 @@ pub fn options(s: &mut Cursive, connection: Arc<Mutex<Connection>>) -> Result<()
                                     delete(s, delete_connection.clone()).unwrap();
                                 }))
                                 .align_center(),
+                        )
+                        .child(DummyView)
+                        .child(
+                            LinearLayout::horizontal()
+                                .child(
+                                    Dialog::around(region_select.with_name("itu_region"))
+                                        .title("ITU Region"),
+                                )
+                                .child(
+                                    Dialog::around(
+                                        EditView::new()
+                                            .content(bandplan_file)
+                                            .with_name("bandplan_file")
+                                            .fixed_width(30),
+                                    )
+                                    .title("Band Plan File (optional)"),
+                                )
+                                .child(
+                                    Dialog::around(
+                                        EditView::new()
+                                            .content(rig_address)
+                                            .with_name("rig_address")
+                                            .fixed_width(20),
+                                    )
+                                    .title("rigctld Address (blank = off)"),
+                                )
+                                .child(
+                                    Dialog::around(
+                                        EditView::new()
+                                            .content(wsjtx_address)
+                                            .with_name("wsjtx_address")
+                                            .fixed_width(20),
+                                    )
+                                    .title("WSJT-X UDP (blank = off)"),
+                                )
+                                .child(
+                                    Dialog::around(
+                                        EditView::new()
+                                            .content(n1mm_targets)
+                                            .with_name("n1mm_targets")
+                                            .fixed_width(30),
+                                    )
+                                    .title("N1MM Broadcast (host:port, ...)"),
+                                )
+                                .align_center(),
+                        )
+                        .child(
+                            LinearLayout::horizontal()
+                                .child(
+                                    Dialog::around(
+                                        LinearLayout::horizontal()
+                                            .child(Checkbox::new().with_checked(dupe_settings.block).with_name("dupe_block"))
+                                            .child(TextView::new(" Block")),
+                                    )
+                                    .title("Dupes"),
+                                )
+                                .child(
+                                    Dialog::around(
+                                        EditView::new()
+                                            .content(dupe_settings.window_hours.to_string())
+                                            .with_name("dupe_window_hours")
+                                            .fixed_width(10),
+                                    )
+                                    .title("Dupe Window (hours, 0 = any)"),
+                                )
+                                .child(
+                                    Dialog::around(
+                                        EditView::new()
+                                            .content(callbook_file)
+                                            .with_name("callbook_file")
+                                            .fixed_width(30),
+                                    )
+                                    .title("Callbook File (optional)"),
+                                )
+                                .child(
+                                    Dialog::around(
+                                        EditView::new()
+                                            .content(cty_file)
+                                            .with_name("cty_file")
+                                            .fixed_width(30),
+                                    )
+                                    .title("Country File (cty.dat/cty.csv)"),
+                                )
+                                .child(Button::new("Save Settings", move |s| {
+                                    if let Err(err) = save_settings(s, settings_connection.clone()) {
+                                        s.add_layer(Dialog::text(err.to_string()).title("Error").dismiss_button("OK"));
+                                    }
+                                }))
+                                .align_center(),
                         ),
                 )
                 .align_center(),
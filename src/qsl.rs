@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+
+/// A way of confirming QSOs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QslService {
+    Paper,
+    Lotw,
+    Eqsl,
+}
+
+/// ADIF QSL_SENT values: Yes, No, Requested, Queued, Ignore
+pub const SENT_STATUSES: &[&str] = &["N", "Y", "R", "Q", "I"];
+/// ADIF QSL_RCVD values: Yes, No, Requested, Ignore, Verified
+pub const RCVD_STATUSES: &[&str] = &["N", "Y", "R", "I", "V"];
+
+impl QslService {
+    pub fn all() -> [QslService; 3] {
+        [QslService::Paper, QslService::Lotw, QslService::Eqsl]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QslService::Paper => "Paper QSL",
+            QslService::Lotw => "LoTW",
+            QslService::Eqsl => "eQSL",
+        }
+    }
+
+    /// Prefix of this service's columns in the `logs` table.
+    pub fn column(&self) -> &'static str {
+        match self {
+            QslService::Paper => "qsl",
+            QslService::Lotw => "lotw_qsl",
+            QslService::Eqsl => "eqsl_qsl",
+        }
+    }
+
+    /// The ADIF sent status, received status, sent date and received date fields.
+    pub fn adif_fields(&self) -> [&'static str; 4] {
+        match self {
+            QslService::Paper => ["QSL_SENT", "QSL_RCVD", "QSLSDATE", "QSLRDATE"],
+            QslService::Lotw => [
+                "LOTW_QSL_SENT",
+                "LOTW_QSL_RCVD",
+                "LOTW_QSLSDATE",
+                "LOTW_QSLRDATE",
+            ],
+            QslService::Eqsl => [
+                "EQSL_QSL_SENT",
+                "EQSL_QSL_RCVD",
+                "EQSL_QSLSDATE",
+                "EQSL_QSLRDATE",
+            ],
+        }
+    }
+}
+
+/// Sent and received state of one service for a QSO.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Qsl {
+    pub sent: String,
+    pub sent_date: Option<NaiveDate>,
+    pub rcvd: String,
+    pub rcvd_date: Option<NaiveDate>,
+}
+
+impl Default for Qsl {
+    fn default() -> Qsl {
+        Qsl {
+            sent: String::from("N"),
+            sent_date: None,
+            rcvd: String::from("N"),
+            rcvd_date: None,
+        }
+    }
+}
+
+/// Readable name of an ADIF QSL status.
+pub fn status_name(status: &str) -> &'static str {
+    match status {
+        "Y" => "Yes",
+        "N" => "No",
+        "R" => "Requested",
+        "Q" => "Queued",
+        "I" => "Ignore",
+        "V" => "Verified",
+        _ => "?",
+    }
+}
+
+impl Qsl {
+    /// Dates a status that has just become Y with `today` when no date was given.
+    pub fn fill_dates(&mut self, today: NaiveDate) {
+        if self.sent == "Y" && self.sent_date.is_none() {
+            self.sent_date = Some(today);
+        }
+        if self.received() && self.rcvd_date.is_none() {
+            self.rcvd_date = Some(today);
+        }
+    }
+
+    pub fn received(&self) -> bool {
+        matches!(self.rcvd.as_str(), "Y" | "V")
+    }
+
+    /// Checks both statuses are ADIF values.
+    pub fn validate(&self) -> Result<()> {
+        if !SENT_STATUSES.contains(&self.sent.as_str()) {
+            return Err(anyhow!("'{}' is not a QSL sent status", self.sent));
+        }
+        if !RCVD_STATUSES.contains(&self.rcvd.as_str()) {
+            return Err(anyhow!("'{}' is not a QSL received status", self.rcvd));
+        }
+        Ok(())
+    }
+}
+
+/// Compact status for the Logbook table from the paper, LoTW and eQSL states, one letter
+/// per service (P, L, E): upper case when received, lower case when only sent, and `-`
+/// otherwise. `pL-` is a paper card sent and a LoTW confirmation.
+pub fn summary(qsls: &[Qsl]) -> String {
+    qsls.iter()
+        .zip(['P', 'L', 'E'])
+        .map(|(qsl, letter)| {
+            if qsl.received() {
+                letter
+            } else if qsl.sent == "Y" {
+                letter.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
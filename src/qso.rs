@@ -0,0 +1,120 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use rusqlite::Connection;
+
+use crate::bandplan::BandPlan;
+use crate::contest;
+use crate::frequency::Frequency;
+use crate::maidenhead::Locator;
+use crate::mode::Mode;
+use crate::models::LogbookExt;
+use crate::n1mm::{self, Change};
+use crate::report::Report;
+use crate::settings::DupeSettings;
+use crate::store::SettingsStore;
+
+/// A QSO as typed into the Add Log form or passed on the command line, before it is
+/// checked and completed by [`crate::store::QsoStore::insert_log`].
+pub struct LogForm {
+    pub callsign: String,
+    pub profile: u64,
+    pub band: String,
+    pub frequency: String,
+    pub mode: String,
+    pub rsttx: String,
+    pub rstrx: String,
+    pub power: String,
+    pub comments: String,
+    /// Start of the QSO; defaults to `time_off` when inserting
+    pub time_on: Option<NaiveDateTime>,
+    /// End of the QSO; defaults to now when inserting
+    pub time_off: Option<NaiveDateTime>,
+    pub name: String,
+    pub qth: String,
+    pub state: String,
+    pub gridsquare: String,
+    pub license_class: String,
+    /// Contest session to log the QSO in
+    pub contest: Option<u64>,
+    /// Exchange received, checked against the contest's template
+    pub exchange: String,
+}
+
+/// Parses a `YYYY-MM-DD HH:MM:SS` timestamp, as typed in forms and on the command line.
+pub fn parse_timestamp(value: &str) -> Result<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.trim(), "%Y-%m-%d %H:%M:%S")
+        .map_err(|err| anyhow!("Could not parse timestamp '{}': {}", value, err))
+}
+
+/// Parses the form's frequency and checks it lies inside the selected band.
+pub fn checked_frequency(connection: Arc<Mutex<Connection>>, form: &LogForm) -> Result<Frequency> {
+    let frequency: Frequency = form.frequency.parse()?;
+    BandPlan::load(&SettingsStore::new(connection))?.check(&form.band, frequency)?;
+    Ok(frequency)
+}
+
+/// Checks the form's grid square, normalising its case; an empty grid is allowed.
+pub fn checked_grid(form: &LogForm) -> Result<String> {
+    if form.gridsquare.is_empty() {
+        return Ok(String::new());
+    }
+    let grid: Locator = form.gridsquare.parse()?;
+    Ok(grid.to_string())
+}
+
+/// Resolves the form's mode and checks both reports follow its report rules.
+pub fn checked_mode(form: &LogForm) -> Result<Mode> {
+    checked_reports(form, None)
+}
+
+/// Like [`checked_mode`] for an edit of `stored`. A report left as it was logged is only
+/// checked when the mode changes, so QSOs logged before reports were checked (an FT8 QSO
+/// with `599`) can still be edited.
+pub fn checked_edit_mode(form: &LogForm, stored: &LogbookExt) -> Result<Mode> {
+    checked_reports(form, Some(stored))
+}
+
+fn checked_reports(form: &LogForm, stored: Option<&LogbookExt>) -> Result<Mode> {
+    if form.mode.trim().is_empty() {
+        return Err(anyhow!("No mode selected"));
+    }
+    let mode =
+        Mode::from_name(&form.mode).ok_or_else(|| anyhow!("Unknown mode '{}'", form.mode))?;
+    let unchanged = stored.filter(|stored| stored.mode.eq_ignore_ascii_case(mode.name()));
+    let report = Report::for_mode(&mode);
+    if unchanged.is_none_or(|stored| stored.rsttx != form.rsttx) {
+        report
+            .validate(&form.rsttx)
+            .map_err(|err| anyhow!("RST TX: {}", err))?;
+    }
+    if unchanged.is_none_or(|stored| stored.rstrx != form.rstrx) {
+        report
+            .validate(&form.rstrx)
+            .map_err(|err| anyhow!("RST RX: {}", err))?;
+    }
+    Ok(mode)
+}
+
+/// Whether `entry` makes a new QSO on `band` and `mode` at `time` a dupe. In a contest only
+/// its own QSOs count, by the contest's rules; otherwise the dupe settings decide.
+pub fn is_dupe(
+    settings: &DupeSettings,
+    contest: Option<u64>,
+    entry: &LogbookExt,
+    band: &str,
+    mode: &str,
+    time: NaiveDateTime,
+) -> bool {
+    match contest {
+        Some(contest) => entry.contest == Some(contest) && contest::is_dupe(entry, band, mode),
+        None => settings.is_dupe(entry, band, mode, time),
+    }
+}
+
+/// Tells N1MM listeners about a saved QSO. The QSO is in the log either way, so a failed
+/// broadcast is not reported.
+pub fn announce(connection: Arc<Mutex<Connection>>, change: Change, log: &LogbookExt) {
+    let _ = n1mm::broadcast(&SettingsStore::new(connection), change, log);
+}
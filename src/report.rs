@@ -0,0 +1,87 @@
+use anyhow::{anyhow, Result};
+
+use crate::mode::Mode;
+
+/// The kind of signal report exchanged on a mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Report {
+    /// Readability and strength, e.g. `59`, for phone
+    Rs,
+    /// Readability, strength and tone, e.g. `599`, for CW and keyboard digital modes
+    Rst,
+    /// Signal to noise ratio in dB, e.g. `-12`, for the WSJT-X style weak signal modes
+    Db,
+}
+
+const DB_MODES: &[&str] = &[
+    "FSK441", "FT8", "ISCAT", "JT4", "JT44", "JT65", "JT6M", "JT9", "MSK144", "QRA64", "WSPR",
+];
+const DB_SUBMODES: &[&str] = &["FST4", "FST4W", "FT4", "JS8", "JTMS", "Q65"];
+const PHONE_MODES: &[&str] = &["AM", "DIGITALVOICE", "DSTAR", "FM", "SSB"];
+
+impl Report {
+    pub fn for_mode(mode: &Mode) -> Report {
+        if DB_MODES.contains(&mode.mode)
+            || mode
+                .submode
+                .is_some_and(|submode| DB_SUBMODES.contains(&submode))
+        {
+            Report::Db
+        } else if PHONE_MODES.contains(&mode.mode) {
+            Report::Rs
+        } else {
+            Report::Rst
+        }
+    }
+
+    /// The report filled in when the mode is picked.
+    pub fn default_report(&self) -> &'static str {
+        match self {
+            Report::Rs => "59",
+            Report::Rst => "599",
+            Report::Db => "-10",
+        }
+    }
+
+    pub fn max_width(&self) -> usize {
+        match self {
+            Report::Rs => 2,
+            Report::Rst => 3,
+            Report::Db => 3,
+        }
+    }
+
+    /// Checks a report is well formed for this kind. An empty report is allowed.
+    pub fn validate(&self, report: &str) -> Result<()> {
+        let report = report.trim();
+        if report.is_empty() {
+            return Ok(());
+        }
+        let valid = match self {
+            Report::Rs | Report::Rst => {
+                let digits: Vec<u32> = report.chars().filter_map(|c| c.to_digit(10)).collect();
+                let len = if *self == Report::Rs { 2 } else { 3 };
+                digits.len() == report.len()
+                    && digits.len() == len
+                    && (1..=5).contains(&digits[0])
+                    && digits[1..].iter().all(|digit| (1..=9).contains(digit))
+            }
+            Report::Db => report
+                .parse::<i32>()
+                .is_ok_and(|db| (-50..=50).contains(&db)),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "'{}' is not {} report",
+                report,
+                match self {
+                    Report::Rs => "an RS (e.g. 59)",
+                    Report::Rst => "an RST (e.g. 599)",
+                    Report::Db => "a dB (-50 to +50)",
+                }
+            ))
+        }
+    }
+}
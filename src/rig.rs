@@ -0,0 +1,223 @@
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use cursive::{
+    views::{EditView, TextView},
+    CbSink, Cursive,
+};
+use rusqlite::Connection;
+
+use crate::log::{form_label, set_mode};
+use tuilog::frequency::Frequency;
+use tuilog::mode::Mode;
+use tuilog::rigctld::{hamlib_mode, logbook_mode, Rigctld};
+use tuilog::store::SettingsStore;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, PartialEq)]
+struct RigState {
+    frequency: Frequency,
+    mode: Option<Mode>,
+}
+
+enum RigCommand {
+    Tune(Frequency),
+    Mode(Mode),
+}
+
+/// Handle to the rig polling thread, kept in the Cursive user data.
+struct Rig {
+    commands: Sender<RigCommand>,
+    state: Arc<Mutex<Option<RigState>>>,
+    status: Arc<Mutex<String>>,
+}
+
+/// Starts polling the rigctld at the `rig_address` setting. The address is re-read on
+/// every poll, so changing it in Options or switching logbooks takes effect without a restart.
+pub fn start(siv: &mut Cursive, connection: Arc<Mutex<Connection>>) {
+    let (commands, receiver) = channel();
+    let state = Arc::new(Mutex::new(None));
+    let status = Arc::new(Mutex::new(String::from("Rig: off")));
+    let cb_sink = siv.cb_sink().clone();
+    let thread_state = state.clone();
+    let thread_status = status.clone();
+    spawn(move || poll(connection, cb_sink, receiver, thread_state, thread_status));
+    siv.set_user_data(Rig {
+        commands,
+        state,
+        status,
+    });
+}
+
+fn rig_address(connection: &Arc<Mutex<Connection>>) -> String {
+    SettingsStore::new(connection.clone())
+        .get("rig_address")
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+fn set_status(cb_sink: &CbSink, status: &Arc<Mutex<String>>, text: String) -> bool {
+    if let Ok(mut current) = status.lock() {
+        if *current == text {
+            return true;
+        }
+        *current = text.clone();
+    }
+    cb_sink
+        .send(Box::new(move |s| {
+            s.call_on_name("rig_status", |view: &mut TextView| view.set_content(text));
+        }))
+        .is_ok()
+}
+
+fn poll(
+    connection: Arc<Mutex<Connection>>,
+    cb_sink: CbSink,
+    receiver: Receiver<RigCommand>,
+    state: Arc<Mutex<Option<RigState>>>,
+    status: Arc<Mutex<String>>,
+) {
+    let mut rig: Option<(String, Rigctld)> = None;
+    loop {
+        let address = rig_address(&connection);
+        if address.is_empty() {
+            rig = None;
+            while receiver.try_recv().is_ok() {}
+            if !set_status(&cb_sink, &status, String::from("Rig: off")) {
+                return;
+            }
+            sleep(POLL_INTERVAL);
+            continue;
+        }
+        if rig
+            .as_ref()
+            .is_none_or(|(connected, _)| *connected != address)
+        {
+            match Rigctld::connect(&address) {
+                Ok(client) => rig = Some((address.clone(), client)),
+                Err(err) => {
+                    rig = None;
+                    if !set_status(&cb_sink, &status, format!("Rig: {} ({})", err, address)) {
+                        return;
+                    }
+                    sleep(RETRY_INTERVAL);
+                    continue;
+                }
+            }
+        }
+        let (_, client) = rig.as_mut().unwrap();
+        let last = state.lock().ok().and_then(|state| *state);
+        let result = receiver.try_iter().try_for_each(|command| match command {
+            RigCommand::Tune(frequency) => client.set_frequency(frequency),
+            RigCommand::Mode(mode) => {
+                client.set_mode(hamlib_mode(&mode, last.map(|last| last.frequency)))
+            }
+        });
+        let result = result.and_then(|_| {
+            let frequency = client.frequency()?;
+            let (mode, _) = client.mode()?;
+            Ok(RigState {
+                frequency,
+                mode: logbook_mode(&mode),
+            })
+        });
+        match result {
+            Ok(current) => {
+                if last != Some(current) {
+                    if let Ok(mut state) = state.lock() {
+                        *state = Some(current);
+                    }
+                    let mode_changed = last.map(|last| last.mode) != Some(current.mode);
+                    if cb_sink
+                        .send(Box::new(move |s| show(s, current, mode_changed)))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                if !set_status(&cb_sink, &status, format!("Rig: connected ({})", address)) {
+                    return;
+                }
+            }
+            Err(err) => {
+                rig = None;
+                if !set_status(&cb_sink, &status, format!("Rig: {} ({})", err, address)) {
+                    return;
+                }
+            }
+        }
+        sleep(POLL_INTERVAL);
+    }
+}
+
+/// Copies the radio's frequency, and its mode when `apply_mode`, into the Add Log form if it is open.
+///
+/// The mode is only copied when the radio's mode changes, so tuning a radio left in USB
+/// does not overwrite a digital mode picked in the form.
+fn show(s: &mut Cursive, state: RigState, apply_mode: bool) {
+    if s.find_name::<TextView>("rig_status").is_none() {
+        return;
+    }
+    let frequency = state.frequency.to_string();
+    // Run the frequency field's edit callback too, so the band follows
+    if let Some(cb) = s.call_on_name("frequency", |view: &mut EditView| {
+        view.set_content(frequency)
+    }) {
+        cb(s);
+    }
+    if let Some(mode) = state.mode.filter(|_| apply_mode) {
+        if form_label(s, "mode") != mode.name() {
+            set_mode(s, mode);
+        }
+    }
+}
+
+/// Shows the rig status and the radio's last known settings in a newly opened Add Log form.
+pub fn fill(s: &mut Cursive) {
+    let current = s.user_data::<Rig>().map(|rig| {
+        (
+            rig.state.lock().ok().and_then(|state| *state),
+            rig.status
+                .lock()
+                .map(|status| status.clone())
+                .unwrap_or_default(),
+        )
+    });
+    if let Some((state, status)) = current {
+        s.call_on_name("rig_status", |view: &mut TextView| view.set_content(status));
+        if let Some(state) = state {
+            let apply_mode = form_label(s, "mode").is_empty();
+            show(s, state, apply_mode);
+        }
+    }
+}
+
+fn send(s: &mut Cursive, command: RigCommand) {
+    if s.find_name::<TextView>("rig_status").is_none() {
+        return;
+    }
+    if let Some(rig) = s.user_data::<Rig>() {
+        let _ = rig.commands.send(command);
+    }
+}
+
+/// Tunes the radio when the Add Log form's frequency is changed by hand.
+pub fn tune(s: &mut Cursive, frequency: Frequency) {
+    send(s, RigCommand::Tune(frequency));
+}
+
+/// Switches the radio's mode when the Add Log form's mode is changed by hand.
+pub fn select_mode(s: &mut Cursive, mode: Mode) {
+    send(s, RigCommand::Mode(mode));
+}
@@ -0,0 +1,177 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::frequency::Frequency;
+use crate::mode::Mode;
+
+/// A client for the hamlib rigctld text protocol.
+pub struct Rigctld {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Rigctld {
+    pub fn connect(address: &str) -> Result<Rigctld> {
+        let addr = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or(anyhow!("Could not resolve {}", address))?;
+        let stream = TcpStream::connect_timeout(&addr, Duration::from_secs(2))?;
+        stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(2)))?;
+        Ok(Rigctld {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("rigctld closed the connection"));
+        }
+        let line = line.trim().to_string();
+        match line.strip_prefix("RPRT ") {
+            Some(code) if code != "0" => Err(anyhow!("rigctld error {}", code)),
+            _ => Ok(line),
+        }
+    }
+
+    fn command(&mut self, command: &str) -> Result<()> {
+        self.writer.write_all(format!("{}\n", command).as_bytes())?;
+        Ok(())
+    }
+
+    /// Reads the VFO frequency (`f`).
+    pub fn frequency(&mut self) -> Result<Frequency> {
+        self.command("f")?;
+        let line = self.read_line()?;
+        let hz: f64 = line
+            .parse()
+            .map_err(|_| anyhow!("Unexpected frequency reply '{}'", line))?;
+        Ok(Frequency(hz.round() as u64))
+    }
+
+    /// Reads the hamlib mode name and passband (`m`).
+    pub fn mode(&mut self) -> Result<(String, i64)> {
+        self.command("m")?;
+        let mode = self.read_line()?;
+        let passband = self.read_line()?;
+        let passband = passband
+            .parse()
+            .map_err(|_| anyhow!("Unexpected passband reply '{}'", passband))?;
+        Ok((mode, passband))
+    }
+
+    /// Tunes the VFO (`F`).
+    pub fn set_frequency(&mut self, frequency: Frequency) -> Result<()> {
+        self.command(&format!("F {}", frequency.hz()))?;
+        self.read_line().map(|_| ())
+    }
+
+    /// Sets the hamlib mode, keeping the radio's default passband (`M`).
+    pub fn set_mode(&mut self, mode: &str) -> Result<()> {
+        self.command(&format!("M {} 0", mode))?;
+        self.read_line().map(|_| ())
+    }
+}
+
+/// The logbook mode for a hamlib mode. Data modes such as PKTUSB say nothing about
+/// which digital mode is in use, so they map to `None`.
+pub fn logbook_mode(hamlib: &str) -> Option<Mode> {
+    let name = match hamlib {
+        "USB" | "LSB" | "AM" | "FM" | "CW" | "RTTY" => hamlib,
+        "CWR" => "CW",
+        "RTTYR" => "RTTY",
+        _ => return None,
+    };
+    Mode::from_name(name)
+}
+
+/// The hamlib mode to select on the radio for a logbook mode at `frequency`.
+pub fn hamlib_mode(mode: &Mode, frequency: Option<Frequency>) -> &'static str {
+    match (mode.mode, mode.submode) {
+        ("SSB", Some(sideband)) => sideband,
+        // Without a sideband, follow the convention of LSB below 10 MHz
+        ("SSB", None) if frequency.is_some_and(|frequency| frequency.mhz() < 10.0) => "LSB",
+        ("SSB", None) => "USB",
+        ("CW", _) => "CW",
+        ("RTTY", _) => "RTTY",
+        ("AM", _) => "AM",
+        ("FM", _) => "FM",
+        _ => "PKTUSB",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    /// Answers rigctld commands like a radio on 7.074 MHz USB, refusing unknown modes.
+    fn fake_rigctld() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut frequency = String::from("7074000");
+            let mut mode = String::from("USB");
+            for line in BufReader::new(stream).lines() {
+                let line = line.unwrap();
+                let words: Vec<&str> = line.split_whitespace().collect();
+                let reply = match words.as_slice() {
+                    ["f"] => format!("{}\n", frequency),
+                    ["m"] => format!("{}\n2400\n", mode),
+                    ["F", hz] => {
+                        frequency = hz.to_string();
+                        String::from("RPRT 0\n")
+                    }
+                    ["M", "CW" | "USB" | "LSB", _] => {
+                        mode = words[1].to_string();
+                        String::from("RPRT 0\n")
+                    }
+                    _ => String::from("RPRT -1\n"),
+                };
+                writer.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn reads_and_sets_the_radio() {
+        let mut rig = Rigctld::connect(&fake_rigctld()).unwrap();
+        assert_eq!(rig.frequency().unwrap(), Frequency(7_074_000));
+        assert_eq!(rig.mode().unwrap(), (String::from("USB"), 2400));
+
+        rig.set_frequency(Frequency(14_025_000)).unwrap();
+        rig.set_mode("CW").unwrap();
+        assert_eq!(rig.frequency().unwrap(), Frequency(14_025_000));
+        assert_eq!(rig.mode().unwrap().0, "CW");
+    }
+
+    #[test]
+    fn surfaces_rigctld_errors() {
+        let mut rig = Rigctld::connect(&fake_rigctld()).unwrap();
+        let err = rig.set_mode("BOGUS").unwrap_err();
+        assert_eq!(err.to_string(), "rigctld error -1");
+        // The connection stays usable after a refused command
+        assert_eq!(rig.frequency().unwrap(), Frequency(7_074_000));
+    }
+
+    #[test]
+    fn maps_modes_both_ways() {
+        assert_eq!(logbook_mode("CWR").map(|mode| mode.name()), Some("CW"));
+        assert!(logbook_mode("PKTUSB").is_none());
+        let ssb = Mode::from_name("SSB").unwrap();
+        assert_eq!(hamlib_mode(&ssb, Some(Frequency(7_100_000))), "LSB");
+        assert_eq!(hamlib_mode(&ssb, Some(Frequency(14_200_000))), "USB");
+    }
+}
@@ -0,0 +1,583 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDateTime};
+
+use crate::awards::ModeClass;
+use crate::contest::is_dupe;
+use crate::dxcc::{Entity, PORTABLE_SUFFIXES};
+use crate::models::{LogbookExt, OperatorConfig};
+
+/// Where a contacted station is, seen from our own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Place {
+    /// Same DXCC entity
+    Country,
+    /// Another entity on our continent
+    Continent,
+    /// Another continent
+    Dx,
+}
+
+impl Place {
+    pub fn all() -> [Place; 3] {
+        [Place::Country, Place::Continent, Place::Dx]
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Place::Country => "country",
+            Place::Continent => "continent",
+            Place::Dx => "dx",
+        }
+    }
+}
+
+/// One `selectors=points` rule of a contest's points table, e.g. `cw dx=3`. A rule with no
+/// mode or place selector matches every mode or place.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PointRule {
+    pub mode: Option<ModeClass>,
+    pub place: Option<Place>,
+    pub points: u32,
+}
+
+impl PointRule {
+    fn matches(&self, mode: ModeClass, place: Option<Place>) -> bool {
+        self.mode.is_none_or(|rule| rule == mode)
+            && self.place.is_none_or(|rule| Some(rule) == place)
+    }
+}
+
+impl fmt::Display for PointRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut selectors: Vec<String> = Vec::new();
+        if let Some(mode) = self.mode {
+            selectors.push(mode.name().to_lowercase());
+        }
+        if let Some(place) = self.place {
+            selectors.push(place.key().to_string());
+        }
+        if selectors.is_empty() {
+            selectors.push(String::from("*"));
+        }
+        write!(f, "{}={}", selectors.join(" "), self.points)
+    }
+}
+
+/// What counts as a multiplier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Multiplier {
+    Dxcc,
+    CqZone,
+    /// The received exchange (its last word), e.g. the state or ARRL section
+    Exchange,
+    /// WPX prefixes, e.g. `K1` or `VP2E`
+    Prefix,
+}
+
+impl Multiplier {
+    pub fn all() -> [Multiplier; 4] {
+        [
+            Multiplier::Dxcc,
+            Multiplier::CqZone,
+            Multiplier::Exchange,
+            Multiplier::Prefix,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Multiplier::Dxcc => "DXCC",
+            Multiplier::CqZone => "Zones",
+            Multiplier::Exchange => "Exchange",
+            Multiplier::Prefix => "Prefixes",
+        }
+    }
+
+    /// How the multiplier is stored in the `contests` table.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Multiplier::Dxcc => "dxcc",
+            Multiplier::CqZone => "cqzone",
+            Multiplier::Exchange => "exchange",
+            Multiplier::Prefix => "prefix",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Result<Multiplier> {
+        Multiplier::all()
+            .into_iter()
+            .find(|multiplier| multiplier.key() == key)
+            .ok_or_else(|| anyhow!("Unknown multiplier '{}'", key))
+    }
+
+    /// The multiplier a QSO counts towards, if it is known.
+    pub fn value(&self, log: &LogbookExt) -> Option<String> {
+        match self {
+            Multiplier::Dxcc => log
+                .dxcc
+                .map(|dxcc| dxcc.to_string())
+                .or_else(|| Some(log.country.clone()).filter(|country| !country.is_empty())),
+            Multiplier::CqZone => log.cqz.map(|cqz| cqz.to_string()),
+            Multiplier::Exchange => log
+                .exchange_rcvd
+                .text
+                .split_whitespace()
+                .last()
+                .map(|word| word.to_string()),
+            Multiplier::Prefix => wpx_prefix(&log.call),
+        }
+    }
+}
+
+/// The WPX prefix of a callsign: everything up to its last digit (`K1ABC` is `K1`, `2E0ABC`
+/// is `2E0`). A portable prefix wins (`VP2E/W1AW` is `VP2E`, `PA/W1AW` is `PA0`) and a call
+/// area change replaces the digit (`W1AW/4` is `W4`).
+pub fn wpx_prefix(call: &str) -> Option<String> {
+    let call = call.trim().to_uppercase();
+    let parts: Vec<&str> = call
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != "MM" && !PORTABLE_SUFFIXES.contains(part))
+        .collect();
+    let up_to_digit = |part: &str| -> String {
+        match part.rfind(|c: char| c.is_ascii_digit()) {
+            Some(index) => part[..=index].to_string(),
+            None => format!("{}0", part),
+        }
+    };
+    match parts.as_slice() {
+        [] => None,
+        [single] => Some(up_to_digit(single)),
+        [home, area] if area.len() == 1 && area.chars().all(|c| c.is_ascii_digit()) => {
+            let mut prefix = up_to_digit(home);
+            prefix.pop();
+            Some(prefix + area)
+        }
+        [first, second, ..] => {
+            let portable = if second.len() < first.len() {
+                second
+            } else {
+                first
+            };
+            if portable.chars().any(|c| c.is_ascii_digit()) {
+                Some(portable.to_string())
+            } else {
+                Some(format!("{}0", portable))
+            }
+        }
+    }
+}
+
+/// Our station's entity and continent, which QSO points are counted from.
+#[derive(Clone, Debug, Default)]
+pub struct Station {
+    pub country: String,
+    pub dxcc: Option<u32>,
+    pub cont: String,
+}
+
+impl Station {
+    /// Takes the DXCC number and continent from the profile, and anything the profile
+    /// leaves blank from `entity`, the profile's callsign looked up in the country file.
+    pub fn new(profile: &OperatorConfig, entity: Option<&Entity>) -> Station {
+        let entity = entity.cloned().unwrap_or_default();
+        let cont = match profile.cont.trim() {
+            "" => entity.cont,
+            cont => cont.to_string(),
+        };
+        Station {
+            country: entity.name,
+            dxcc: profile.dxcc.trim().parse().ok().or(entity.dxcc),
+            cont,
+        }
+    }
+
+    /// Where the station in `log` is, when both its location and ours are known.
+    pub fn place_of(&self, log: &LogbookExt) -> Option<Place> {
+        let same_dxcc =
+            matches!((self.dxcc, log.dxcc), (Some(ours), Some(theirs)) if ours == theirs);
+        let same_country = !self.country.is_empty() && self.country == log.country;
+        if same_dxcc || same_country {
+            return Some(Place::Country);
+        }
+        if self.cont.is_empty() || log.cont.is_empty() {
+            return None;
+        }
+        if self.cont.eq_ignore_ascii_case(&log.cont) {
+            Some(Place::Continent)
+        } else {
+            Some(Place::Dx)
+        }
+    }
+}
+
+/// How a contest is scored: points per QSO, what counts as a multiplier, and whether
+/// multipliers count again on each band.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scoring {
+    /// The first rule that matches a QSO gives its points; no rules at all means one point
+    /// a QSO, and a QSO no rule matches scores nothing
+    pub rules: Vec<PointRule>,
+    pub multipliers: Vec<Multiplier>,
+    pub per_band: bool,
+}
+
+impl Scoring {
+    /// Reads a points table such as `dx=3, continent=1, country=0` or `phone=1, *=2`.
+    pub fn parse_rules(value: &str) -> Result<Vec<PointRule>> {
+        let mut rules = Vec::new();
+        for rule in value
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+        {
+            let (selectors, points) = rule
+                .split_once('=')
+                .ok_or_else(|| anyhow!("'{}' is not a selectors=points rule", rule))?;
+            let points = points
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| anyhow!("'{}' is not a number of points", points.trim()))?;
+            let mut parsed = PointRule {
+                mode: None,
+                place: None,
+                points,
+            };
+            for selector in selectors.split_whitespace().map(str::to_lowercase) {
+                if selector == "*" {
+                    continue;
+                }
+                if let Some(mode) = ModeClass::all()
+                    .into_iter()
+                    .find(|mode| mode.name().to_lowercase() == selector)
+                {
+                    parsed.mode = Some(mode);
+                } else if let Some(place) = Place::all()
+                    .into_iter()
+                    .find(|place| place.key() == selector)
+                {
+                    parsed.place = Some(place);
+                } else {
+                    return Err(anyhow!(
+                        "Unknown points selector '{}' (use cw, phone, digital, country, continent, dx or *)",
+                        selector
+                    ));
+                }
+            }
+            rules.push(parsed);
+        }
+        Ok(rules)
+    }
+
+    /// The points table in the form [`Scoring::parse_rules`] reads.
+    pub fn rules_text(&self) -> String {
+        self.rules
+            .iter()
+            .map(|rule| rule.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Comma-separated multiplier keys, as stored.
+    pub fn multipliers_text(&self) -> String {
+        self.multipliers
+            .iter()
+            .map(|multiplier| multiplier.key())
+            .collect::<Vec<&str>>()
+            .join(",")
+    }
+
+    pub fn parse_multipliers(value: &str) -> Result<Vec<Multiplier>> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(Multiplier::from_key)
+            .collect()
+    }
+
+    /// Points for one QSO made from `station`.
+    pub fn points(&self, station: &Station, log: &LogbookExt) -> u32 {
+        if self.rules.is_empty() {
+            return 1;
+        }
+        let mode = ModeClass::of(&log.mode);
+        let place = station.place_of(log);
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(mode, place))
+            .map(|rule| rule.points)
+            .unwrap_or(0)
+    }
+
+    /// Claimed score of a contest session's QSOs, in any order. Dupes score nothing.
+    pub fn score(&self, station: &Station, logs: &[LogbookExt]) -> Score {
+        let mut logs: Vec<&LogbookExt> = logs.iter().collect();
+        logs.sort_by_key(|log| (log.timestamp, log.id));
+        let mut score = Score {
+            mults: self
+                .multipliers
+                .iter()
+                .map(|multiplier| (*multiplier, 0))
+                .collect(),
+            ..Default::default()
+        };
+        let mut counted: HashMap<String, Vec<&LogbookExt>> = HashMap::new();
+        let mut worked: HashSet<(Multiplier, String, String)> = HashSet::new();
+        for log in logs {
+            let earlier = counted.entry(log.call.to_uppercase()).or_default();
+            if earlier
+                .iter()
+                .any(|earlier| is_dupe(earlier, &log.band, &log.mode))
+            {
+                score.dupes += 1;
+                continue;
+            }
+            earlier.push(log);
+            score.qsos += 1;
+            score.points += self.points(station, log);
+            for (multiplier, count) in score.mults.iter_mut() {
+                let Some(value) = multiplier.value(log) else {
+                    continue;
+                };
+                let band = if self.per_band {
+                    log.band.to_uppercase()
+                } else {
+                    String::new()
+                };
+                if worked.insert((*multiplier, band, value)) {
+                    *count += 1;
+                }
+            }
+        }
+        score
+    }
+}
+
+/// A claimed contest score.
+#[derive(Clone, Debug, Default)]
+pub struct Score {
+    pub qsos: usize,
+    pub dupes: usize,
+    pub points: u32,
+    /// Multipliers worked of each type the contest counts
+    pub mults: Vec<(Multiplier, usize)>,
+}
+
+impl Score {
+    pub fn mult_count(&self) -> usize {
+        self.mults.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Points times multipliers, or just the points for a contest without multipliers.
+    pub fn total(&self) -> u64 {
+        if self.mults.is_empty() {
+            self.points as u64
+        } else {
+            self.points as u64 * self.mult_count() as u64
+        }
+    }
+}
+
+/// How many of `logs` were made in the `minutes` up to `now`.
+pub fn rate(logs: &[LogbookExt], now: NaiveDateTime, minutes: i64) -> usize {
+    let since = now - Duration::minutes(minutes);
+    logs.iter()
+        .filter(|log| log.timestamp > since && log.timestamp <= now)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contest::Exchange;
+    use crate::qsl::Qsl;
+
+    fn profile() -> OperatorConfig {
+        OperatorConfig {
+            id: 1,
+            name: String::from("Home"),
+            call: String::from("W1AW"),
+            grid: String::from("FN31pr"),
+            cqz: String::from("5"),
+            ituz: String::from("8"),
+            dxcc: String::from("291"),
+            cont: String::from("NA"),
+            state: String::from("CT"),
+            lotw_cert: String::new(),
+        }
+    }
+
+    fn qso(call: &str, band: &str, mode: &str, dxcc: u32, cont: &str, minute: u32) -> LogbookExt {
+        let timestamp = chrono::NaiveDate::from_ymd_opt(2024, 11, 30)
+            .unwrap()
+            .and_hms_opt(12, minute, 0)
+            .unwrap();
+        LogbookExt {
+            id: minute as u64,
+            timestamp,
+            call: call.to_string(),
+            rsttx: String::from("599"),
+            rstrx: String::from("599"),
+            band: band.to_string(),
+            frequency: None,
+            mode: mode.to_string(),
+            power: String::from("100"),
+            comments: String::new(),
+            time_off: None,
+            name: String::new(),
+            qth: String::new(),
+            state: String::new(),
+            gridsquare: String::new(),
+            license_class: String::new(),
+            country: String::new(),
+            dxcc: Some(dxcc),
+            cqz: None,
+            ituz: None,
+            cont: cont.to_string(),
+            distance: None,
+            bearing: None,
+            paper_qsl: Qsl::default(),
+            lotw_qsl: Qsl::default(),
+            eqsl_qsl: Qsl::default(),
+            contest: Some(1),
+            contest_id: String::new(),
+            exchange_sent: Exchange::default(),
+            exchange_rcvd: Exchange::default(),
+            operator: profile(),
+        }
+    }
+
+    #[test]
+    fn wpx_prefixes() {
+        for (call, prefix) in [
+            ("K1ABC", "K1"),
+            ("2E0ABC", "2E0"),
+            ("VP2E/W1AW", "VP2E"),
+            ("PA/W1AW", "PA0"),
+            ("W1AW/4", "W4"),
+            ("w1aw/p", "W1"),
+            ("W1AW/MM", "W1"),
+            ("RAEM", "RAEM0"),
+        ] {
+            assert_eq!(wpx_prefix(call).as_deref(), Some(prefix), "{}", call);
+        }
+        assert_eq!(wpx_prefix(""), None);
+    }
+
+    #[test]
+    fn points_tables_are_parsed() {
+        let rules = Scoring::parse_rules("cw dx=3, Phone continent=2 , *=1,").unwrap();
+        assert_eq!(
+            rules,
+            [
+                PointRule {
+                    mode: Some(ModeClass::Cw),
+                    place: Some(Place::Dx),
+                    points: 3,
+                },
+                PointRule {
+                    mode: Some(ModeClass::Phone),
+                    place: Some(Place::Continent),
+                    points: 2,
+                },
+                PointRule {
+                    mode: None,
+                    place: None,
+                    points: 1,
+                },
+            ]
+        );
+        let scoring = Scoring {
+            rules,
+            ..Default::default()
+        };
+        assert_eq!(scoring.rules_text(), "cw dx=3, phone continent=2, *=1");
+        assert_eq!(Scoring::parse_rules("").unwrap(), []);
+        assert!(Scoring::parse_rules("dx").is_err());
+        assert!(Scoring::parse_rules("dx=three").is_err());
+        assert!(Scoring::parse_rules("moon=1").is_err());
+    }
+
+    #[test]
+    fn the_first_matching_rule_gives_the_points() {
+        let station = Station::new(&profile(), None);
+        let scoring = Scoring {
+            rules: Scoring::parse_rules("dx=3, continent=1, country=0").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            scoring.points(&station, &qso("DL1ABC", "20m", "CW", 230, "EU", 0)),
+            3
+        );
+        assert_eq!(
+            scoring.points(&station, &qso("VE3ABC", "20m", "CW", 1, "NA", 0)),
+            1
+        );
+        assert_eq!(
+            scoring.points(&station, &qso("K1ABC", "20m", "CW", 291, "NA", 0)),
+            0
+        );
+        // An unknown continent matches no place rule
+        assert_eq!(
+            scoring.points(&station, &qso("ZZ1ZZ", "20m", "CW", 999, "", 0)),
+            0
+        );
+        assert_eq!(
+            Scoring::default().points(&station, &qso("ZZ1ZZ", "20m", "CW", 999, "", 0)),
+            1
+        );
+    }
+
+    #[test]
+    fn dupes_score_nothing() {
+        let station = Station::new(&profile(), None);
+        let scoring = Scoring {
+            rules: Scoring::parse_rules("dx=3, *=1").unwrap(),
+            multipliers: vec![Multiplier::Dxcc],
+            per_band: false,
+        };
+        let logs = [
+            qso("DL1ABC", "20m", "CW", 230, "EU", 1),
+            qso("dl1abc", "20m", "CW", 230, "EU", 2),
+            qso("DL1ABC", "40m", "CW", 230, "EU", 3),
+            qso("DL1ABC", "20m", "SSB", 230, "EU", 4),
+        ];
+        let score = scoring.score(&station, &logs);
+        assert_eq!((score.qsos, score.dupes, score.points), (3, 1, 9));
+        assert_eq!(score.mults, [(Multiplier::Dxcc, 1)]);
+        assert_eq!(score.total(), 9);
+    }
+
+    #[test]
+    fn multipliers_count_once_or_on_each_band() {
+        let station = Station::new(&profile(), None);
+        let logs = [
+            qso("DL1ABC", "20m", "CW", 230, "EU", 1),
+            qso("DL2ABC", "20m", "CW", 230, "EU", 2),
+            qso("DL1ABC", "40m", "CW", 230, "EU", 3),
+            qso("G4ABC", "40m", "CW", 223, "EU", 4),
+        ];
+        let mut scoring = Scoring {
+            rules: Vec::new(),
+            multipliers: vec![Multiplier::Dxcc, Multiplier::Prefix],
+            per_band: false,
+        };
+        let score = scoring.score(&station, &logs);
+        assert_eq!(
+            score.mults,
+            [(Multiplier::Dxcc, 2), (Multiplier::Prefix, 3)]
+        );
+        assert_eq!(score.total(), 4 * 5);
+
+        scoring.per_band = true;
+        let score = scoring.score(&station, &logs);
+        assert_eq!(
+            score.mults,
+            [(Multiplier::Dxcc, 3), (Multiplier::Prefix, 4)]
+        );
+        assert_eq!(score.total(), 4 * 7);
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use chrono::{Duration, NaiveDateTime};
+
+use crate::models::LogbookExt;
+use crate::store::SettingsStore;
+
+#[derive(Clone, Debug)]
+pub struct DupeSettings {
+    /// Refuse to add a QSO that is a dupe instead of only warning about it
+    pub block: bool,
+    /// Only QSOs within this many hours count as dupes; 0 means any time
+    pub window_hours: u32,
+}
+
+impl DupeSettings {
+    pub fn load(settings: &SettingsStore) -> Result<DupeSettings> {
+        Ok(DupeSettings {
+            block: settings.get("dupe_block")?.as_deref() == Some("1"),
+            window_hours: settings
+                .get("dupe_window_hours")?
+                .and_then(|hours| hours.parse().ok())
+                .unwrap_or(0),
+        })
+    }
+
+    pub fn save(&self, settings: &SettingsStore) -> Result<()> {
+        settings.set_all(&self.values())
+    }
+
+    /// The settings keys and values these are stored as.
+    pub fn values(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("dupe_block", (self.block as u8).to_string()),
+            ("dupe_window_hours", self.window_hours.to_string()),
+        ]
+    }
+
+    /// Whether an earlier QSO makes a new one on `band` and `mode` at `time` a dupe. The
+    /// window is measured from the new QSO, so a QSO logged late is checked against the QSOs
+    /// around it rather than the ones before now.
+    pub fn is_dupe(
+        &self,
+        previous: &LogbookExt,
+        band: &str,
+        mode: &str,
+        time: NaiveDateTime,
+    ) -> bool {
+        let in_window = self.window_hours == 0
+            || (time - previous.timestamp).abs() <= Duration::hours(self.window_hours as i64);
+        in_window
+            && previous.band.eq_ignore_ascii_case(band)
+            && previous.mode.eq_ignore_ascii_case(mode)
+    }
+}
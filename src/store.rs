@@ -0,0 +1,1255 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row, ToSql};
+
+use crate::callbook::{self, CallbookEntry};
+use crate::contest::{Contest, Exchange, Template};
+use crate::dxcc;
+use crate::migrations::{migrate, migrate_in_memory};
+use crate::models::{Logbook, LogbookExt, OperatorConfig};
+use crate::n1mm::Change;
+use crate::qsl::{self, Qsl, QslService};
+use crate::qso::{announce, checked_frequency, checked_grid, checked_mode, is_dupe, LogForm};
+use crate::score::Scoring;
+use crate::settings::DupeSettings;
+
+/// Opens the database file at `path`, migrating it to the current schema.
+pub fn open(path: &Path) -> Result<Arc<Mutex<Connection>>> {
+    let mut connection = Connection::open(path)?;
+    migrate(&mut connection, path)?;
+    Ok(Arc::new(Mutex::new(connection)))
+}
+
+/// Replaces the database behind `connection` with the one at `path`, migrating it first.
+///
+/// Every holder of a clone of `connection` sees the new database afterwards.
+pub fn reopen(connection: &Arc<Mutex<Connection>>, path: &Path) -> Result<()> {
+    let mut new_connection = Connection::open(path)?;
+    migrate(&mut new_connection, path)?;
+    *lock(connection)? = new_connection;
+    Ok(())
+}
+
+/// Opens a fresh in-memory database with the current schema, for tools and tests.
+pub fn open_in_memory() -> Result<Arc<Mutex<Connection>>> {
+    let mut connection = Connection::open_in_memory()?;
+    migrate_in_memory(&mut connection)?;
+    Ok(Arc::new(Mutex::new(connection)))
+}
+
+fn lock(connection: &Arc<Mutex<Connection>>) -> Result<MutexGuard<'_, Connection>> {
+    connection
+        .lock()
+        .map_err(|_| anyhow!("Could not lock connection"))
+}
+
+fn format_timestamp(timestamp: &NaiveDateTime) -> String {
+    timestamp.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+const PROFILE_COLUMNS: &str = "id, name, call, grid, cqz, ituz, dxcc, cont, state, lotw_cert";
+
+fn profile_from_row(row: &Row, offset: usize) -> rusqlite::Result<OperatorConfig> {
+    Ok(OperatorConfig {
+        id: row.get(offset)?,
+        name: row.get(offset + 1)?,
+        call: row.get(offset + 2)?,
+        grid: row.get(offset + 3)?,
+        cqz: row.get(offset + 4)?,
+        ituz: row.get(offset + 5)?,
+        dxcc: row.get(offset + 6)?,
+        cont: row.get(offset + 7)?,
+        state: row.get(offset + 8)?,
+        lotw_cert: row.get(offset + 9)?,
+    })
+}
+
+const QSO_SELECT: &str = "SELECT logs.id, timestamp, logs.call, rsttx, rstrx, band, frequency, mode, power, comments, time_off, logs.name, qth, logs.state, gridsquare, license_class, country, logs.dxcc, logs.cqz, logs.ituz, logs.cont, distance, bearing, qsl_sent, qsl_sent_date, qsl_rcvd, qsl_rcvd_date, lotw_qsl_sent, lotw_qsl_sent_date, lotw_qsl_rcvd, lotw_qsl_rcvd_date, eqsl_qsl_sent, eqsl_qsl_sent_date, eqsl_qsl_rcvd, eqsl_qsl_rcvd_date, logs.contest, contest_id, stx, stx_string, srx, srx_string, operatorconfig.id, operatorconfig.name, operatorconfig.call, operatorconfig.grid, operatorconfig.cqz, operatorconfig.ituz, operatorconfig.dxcc, operatorconfig.cont, operatorconfig.state, operatorconfig.lotw_cert FROM logs JOIN operatorconfig ON logs.operator_config = operatorconfig.id";
+
+fn qsl_from_row(row: &Row, offset: usize) -> rusqlite::Result<Qsl> {
+    Ok(Qsl {
+        sent: row.get(offset)?,
+        sent_date: row.get(offset + 1)?,
+        rcvd: row.get(offset + 2)?,
+        rcvd_date: row.get(offset + 3)?,
+    })
+}
+
+fn qso_from_row(row: &Row) -> rusqlite::Result<LogbookExt> {
+    Ok(LogbookExt {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        call: row.get(2)?,
+        rsttx: row.get(3)?,
+        rstrx: row.get(4)?,
+        band: row.get(5)?,
+        frequency: row.get(6)?,
+        mode: row.get(7)?,
+        power: row.get(8)?,
+        comments: row.get(9)?,
+        time_off: row.get(10)?,
+        name: row.get(11)?,
+        qth: row.get(12)?,
+        state: row.get(13)?,
+        gridsquare: row.get(14)?,
+        license_class: row.get(15)?,
+        country: row.get(16)?,
+        dxcc: row.get(17)?,
+        cqz: row.get(18)?,
+        ituz: row.get(19)?,
+        cont: row.get(20)?,
+        distance: row.get(21)?,
+        bearing: row.get(22)?,
+        paper_qsl: qsl_from_row(row, 23)?,
+        lotw_qsl: qsl_from_row(row, 27)?,
+        eqsl_qsl: qsl_from_row(row, 31)?,
+        contest: row.get(35)?,
+        contest_id: row.get(36)?,
+        exchange_sent: exchange_from_row(row, 37)?,
+        exchange_rcvd: exchange_from_row(row, 39)?,
+        operator: profile_from_row(row, 41)?,
+    })
+}
+
+fn exchange_from_row(row: &Row, offset: usize) -> rusqlite::Result<Exchange> {
+    Ok(Exchange {
+        serial: row.get(offset)?,
+        text: row.get(offset + 1)?,
+    })
+}
+
+fn insert_qso(conn: &Connection, log: &LogbookExt) -> Result<u64> {
+    conn.execute(
+        "INSERT INTO logs (timestamp, call, rsttx, rstrx, band, frequency, mode, power, comments, time_off, name, qth, state, gridsquare, license_class, country, dxcc, cqz, ituz, cont, distance, bearing, qsl_sent, qsl_sent_date, qsl_rcvd, qsl_rcvd_date, lotw_qsl_sent, lotw_qsl_sent_date, lotw_qsl_rcvd, lotw_qsl_rcvd_date, eqsl_qsl_sent, eqsl_qsl_sent_date, eqsl_qsl_rcvd, eqsl_qsl_rcvd_date, contest, contest_id, stx, stx_string, srx, srx_string, operator_config) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            format_timestamp(&log.timestamp),
+            &log.call,
+            &log.rsttx,
+            &log.rstrx,
+            &log.band,
+            &log.frequency,
+            &log.mode,
+            &log.power,
+            &log.comments,
+            log.time_off.as_ref().map(format_timestamp),
+            &log.name,
+            &log.qth,
+            &log.state,
+            &log.gridsquare,
+            &log.license_class,
+            &log.country,
+            log.dxcc,
+            log.cqz,
+            log.ituz,
+            &log.cont,
+            log.distance,
+            log.bearing,
+            &log.paper_qsl.sent,
+            log.paper_qsl.sent_date,
+            &log.paper_qsl.rcvd,
+            log.paper_qsl.rcvd_date,
+            &log.lotw_qsl.sent,
+            log.lotw_qsl.sent_date,
+            &log.lotw_qsl.rcvd,
+            log.lotw_qsl.rcvd_date,
+            &log.eqsl_qsl.sent,
+            log.eqsl_qsl.sent_date,
+            &log.eqsl_qsl.rcvd,
+            log.eqsl_qsl.rcvd_date,
+            log.contest,
+            &log.contest_id,
+            log.exchange_sent.serial,
+            &log.exchange_sent.text,
+            log.exchange_rcvd.serial,
+            &log.exchange_rcvd.text,
+            log.operator.id,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+fn update_qso(conn: &Connection, log: &LogbookExt) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE logs SET timestamp = ?, call = ?, rsttx = ?, rstrx = ?, band = ?, frequency = ?, mode = ?, power = ?, comments = ?, time_off = ?, name = ?, qth = ?, state = ?, gridsquare = ?, license_class = ?, country = ?, dxcc = ?, cqz = ?, ituz = ?, cont = ?, distance = ?, bearing = ?, qsl_sent = ?, qsl_sent_date = ?, qsl_rcvd = ?, qsl_rcvd_date = ?, lotw_qsl_sent = ?, lotw_qsl_sent_date = ?, lotw_qsl_rcvd = ?, lotw_qsl_rcvd_date = ?, eqsl_qsl_sent = ?, eqsl_qsl_sent_date = ?, eqsl_qsl_rcvd = ?, eqsl_qsl_rcvd_date = ?, contest = ?, contest_id = ?, stx = ?, stx_string = ?, srx = ?, srx_string = ?, operator_config = ? WHERE id = ?",
+        params![
+            format_timestamp(&log.timestamp),
+            &log.call,
+            &log.rsttx,
+            &log.rstrx,
+            &log.band,
+            &log.frequency,
+            &log.mode,
+            &log.power,
+            &log.comments,
+            log.time_off.as_ref().map(format_timestamp),
+            &log.name,
+            &log.qth,
+            &log.state,
+            &log.gridsquare,
+            &log.license_class,
+            &log.country,
+            log.dxcc,
+            log.cqz,
+            log.ituz,
+            &log.cont,
+            log.distance,
+            log.bearing,
+            &log.paper_qsl.sent,
+            log.paper_qsl.sent_date,
+            &log.paper_qsl.rcvd,
+            log.paper_qsl.rcvd_date,
+            &log.lotw_qsl.sent,
+            log.lotw_qsl.sent_date,
+            &log.lotw_qsl.rcvd,
+            log.lotw_qsl.rcvd_date,
+            &log.eqsl_qsl.sent,
+            log.eqsl_qsl.sent_date,
+            &log.eqsl_qsl.rcvd,
+            log.eqsl_qsl.rcvd_date,
+            log.contest,
+            &log.contest_id,
+            log.exchange_sent.serial,
+            &log.exchange_sent.text,
+            log.exchange_rcvd.serial,
+            &log.exchange_rcvd.text,
+            log.operator.id,
+            log.id,
+        ],
+    )?;
+    if updated == 0 {
+        return Err(anyhow!("No QSO with id {}", log.id));
+    }
+    Ok(())
+}
+
+/// Narrows the QSOs returned by [`QsoStore::query`] and [`QsoStore::rows`]. Unset fields match everything.
+#[derive(Clone, Debug, Default)]
+pub struct QsoFilter {
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+    pub profile: Option<u64>,
+    /// Exact callsign, compared case-insensitively
+    pub call: Option<String>,
+    /// Callsign pattern where `*` matches any run of characters and `?` a single one
+    pub call_pattern: Option<String>,
+    pub band: Option<String>,
+    pub mode: Option<String>,
+    /// Text that must appear somewhere in the comments
+    pub comments: Option<String>,
+    /// Contest session the QSOs were logged in
+    pub contest: Option<u64>,
+    pub exclude: Option<u64>,
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+impl QsoFilter {
+    /// Builds the `WHERE` clause for this filter against the `logs` table, with its parameters.
+    fn where_clause(&self) -> (String, Vec<Box<dyn ToSql>>) {
+        let mut clauses: Vec<&str> = Vec::new();
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+        if let Some(start) = &self.start {
+            clauses.push("logs.timestamp >= ?");
+            params.push(Box::new(format_timestamp(start)));
+        }
+        if let Some(end) = &self.end {
+            clauses.push("logs.timestamp <= ?");
+            params.push(Box::new(format_timestamp(end)));
+        }
+        if let Some(profile) = self.profile {
+            clauses.push("logs.operator_config = ?");
+            params.push(Box::new(profile));
+        }
+        if let Some(call) = &self.call {
+            clauses.push("logs.call = ? COLLATE NOCASE");
+            params.push(Box::new(call.trim().to_string()));
+        }
+        if let Some(pattern) = &self.call_pattern {
+            clauses.push("logs.call LIKE ? ESCAPE '\\'");
+            params.push(Box::new(
+                escape_like(pattern.trim())
+                    .replace('*', "%")
+                    .replace('?', "_"),
+            ));
+        }
+        if let Some(band) = &self.band {
+            clauses.push("logs.band = ? COLLATE NOCASE");
+            params.push(Box::new(band.trim().to_string()));
+        }
+        if let Some(mode) = &self.mode {
+            clauses.push("logs.mode = ? COLLATE NOCASE");
+            params.push(Box::new(mode.trim().to_string()));
+        }
+        if let Some(comments) = &self.comments {
+            clauses.push("logs.comments LIKE ? ESCAPE '\\'");
+            params.push(Box::new(format!("%{}%", escape_like(comments.trim()))));
+        }
+        if let Some(contest) = self.contest {
+            clauses.push("logs.contest = ?");
+            params.push(Box::new(contest));
+        }
+        if let Some(exclude) = self.exclude {
+            clauses.push("logs.id != ?");
+            params.push(Box::new(exclude));
+        }
+        if clauses.is_empty() {
+            (String::new(), params)
+        } else {
+            (format!(" WHERE {}", clauses.join(" AND ")), params)
+        }
+    }
+}
+
+/// Typed access to the `logs` table. QSOs are always read joined with their profile.
+#[derive(Clone)]
+pub struct QsoStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl QsoStore {
+    pub fn new(connection: Arc<Mutex<Connection>>) -> QsoStore {
+        QsoStore { connection }
+    }
+
+    /// Inserts `log` under `log.operator.id` and returns the new row id; `log.id` is ignored.
+    pub fn insert(&self, log: &LogbookExt) -> Result<u64> {
+        let conn = lock(&self.connection)?;
+        insert_qso(&conn, log)
+    }
+
+    /// Inserts every QSO in a single transaction, so either all or none are stored.
+    pub fn insert_all(&self, logs: &[LogbookExt]) -> Result<()> {
+        let mut conn = lock(&self.connection)?;
+        let tx = conn.transaction()?;
+        for log in logs {
+            insert_qso(&tx, log)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Checks and completes a QSO typed into a form and inserts it, ending now unless the
+    /// form says otherwise and refusing dupes when blocking is enabled. Station details
+    /// come from the callbook when none were given, and the entity from the country file.
+    /// Returns the QSO as stored.
+    pub fn insert_log(&self, form: &LogForm) -> Result<LogbookExt> {
+        let connection = self.connection.clone();
+        let settings = SettingsStore::new(connection.clone());
+        let frequency = checked_frequency(connection.clone(), form)?;
+        let mode = checked_mode(form)?;
+        let gridsquare = checked_grid(form)?;
+        let contests = ContestStore::new(connection.clone());
+        let contest = form.contest.map(|id| contests.get(id)).transpose()?;
+        let exchange_rcvd = match &contest {
+            Some(contest) => contest
+                .template
+                .parse(&form.exchange)
+                .map_err(|err| anyhow!("{}: {}", contest.template.field_title(), err))?,
+            None => Exchange::from_text(&form.exchange),
+        };
+        let time_off = form.time_off.unwrap_or_else(|| Utc::now().naive_utc());
+        let time_on = form.time_on.unwrap_or(time_off);
+        if time_off < time_on {
+            return Err(anyhow!("Time off is before time on"));
+        }
+        let dupes = DupeSettings::load(&settings)?;
+        let worked = self.query(&QsoFilter {
+            call: Some(form.callsign.clone()),
+            ..Default::default()
+        })?;
+        if dupes.block
+            && worked
+                .iter()
+                .any(|entry| is_dupe(&dupes, form.contest, entry, &form.band, &form.mode, time_on))
+        {
+            return Err(anyhow!(
+                "DUPE: {} already worked on {} {}",
+                form.callsign,
+                form.band,
+                form.mode
+            ));
+        }
+        let operator = ProfileStore::new(connection.clone()).get(form.profile)?;
+        // Look the station up when nothing was filled in, e.g. from the command line
+        let station = [
+            &form.name,
+            &form.qth,
+            &form.state,
+            &form.gridsquare,
+            &form.license_class,
+        ];
+        let entry = match callbook::configured(&settings)? {
+            Some(callbook) if station.iter().all(|value| value.is_empty()) => {
+                callbook::lookup(callbook.as_ref(), &form.callsign)?
+            }
+            _ => None,
+        };
+        let entry = entry.unwrap_or(CallbookEntry {
+            call: form.callsign.clone(),
+            name: form.name.clone(),
+            qth: form.qth.clone(),
+            state: form.state.clone(),
+            grid: gridsquare,
+            class: form.license_class.clone(),
+        });
+        let entity = dxcc::resolve(&settings, &form.callsign)?;
+        let exchange_sent = match &contest {
+            Some(contest) => contest.sent(contests.next_serial(contest.id)?),
+            None => Exchange::default(),
+        };
+        let mut log = LogbookExt {
+            id: 0,
+            timestamp: time_on,
+            call: form.callsign.clone(),
+            rsttx: form.rsttx.clone(),
+            rstrx: form.rstrx.clone(),
+            band: form.band.clone(),
+            frequency: Some(frequency),
+            mode: mode.name().to_string(),
+            power: form.power.clone(),
+            comments: form.comments.clone(),
+            time_off: Some(time_off),
+            name: entry.name,
+            qth: entry.qth,
+            state: entry.state,
+            gridsquare: entry.grid,
+            license_class: entry.class,
+            country: String::new(),
+            dxcc: None,
+            cqz: None,
+            ituz: None,
+            cont: String::new(),
+            distance: None,
+            bearing: None,
+            paper_qsl: Qsl::default(),
+            lotw_qsl: Qsl::default(),
+            eqsl_qsl: Qsl::default(),
+            contest: form.contest,
+            contest_id: contest
+                .as_ref()
+                .map(|contest| contest.cabrillo.clone())
+                .unwrap_or_default(),
+            exchange_sent,
+            exchange_rcvd,
+            operator,
+        };
+        log.set_entity(entity.as_ref());
+        // A received state or zone is better information than the callbook or country file
+        match contest.map(|contest| contest.template) {
+            Some(Template::State) => log.state = log.exchange_rcvd.text.clone(),
+            Some(Template::CqZone) => log.cqz = log.exchange_rcvd.text.parse().ok(),
+            _ => {}
+        }
+        log.set_path();
+        log.id = self.insert(&log)?;
+        announce(connection, Change::Added, &log);
+        Ok(log)
+    }
+
+    pub fn get(&self, id: u64) -> Result<LogbookExt> {
+        let conn = lock(&self.connection)?;
+        conn.query_row(
+            &format!("{} WHERE logs.id = ?", QSO_SELECT),
+            (id,),
+            qso_from_row,
+        )
+        .optional()?
+        .ok_or(anyhow!("No QSO with id {}", id))
+    }
+
+    /// Returns the QSOs matching `filter`, newest first.
+    pub fn query(&self, filter: &QsoFilter) -> Result<Vec<LogbookExt>> {
+        let conn = lock(&self.connection)?;
+        let (clause, params) = filter.where_clause();
+        let mut stmt = conn.prepare(&format!(
+            "{}{} ORDER BY logs.timestamp DESC",
+            QSO_SELECT, clause
+        ))?;
+        let log_out = stmt.query_map(params_from_iter(params), qso_from_row)?;
+        let mut logs: Vec<LogbookExt> = Vec::new();
+        for log in log_out {
+            logs.push(log?);
+        }
+        Ok(logs)
+    }
+
+    /// Returns the lighter rows shown in the Logbook table for `filter`, newest first.
+    pub fn rows(&self, filter: &QsoFilter) -> Result<Vec<Logbook>> {
+        let conn = lock(&self.connection)?;
+        let (clause, params) = filter.where_clause();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT logs.id, logs.timestamp, logs.call, rsttx, rstrx, band, frequency, mode, comments, time_off, qsl_sent, qsl_rcvd, lotw_qsl_sent, lotw_qsl_rcvd, eqsl_qsl_sent, eqsl_qsl_rcvd FROM logs{} ORDER BY logs.timestamp DESC",
+            clause
+        ))?;
+        let log_out = stmt.query_map(params_from_iter(params), |row| {
+            let mut qsls = Vec::new();
+            for offset in [10, 12, 14] {
+                qsls.push(Qsl {
+                    sent: row.get(offset)?,
+                    rcvd: row.get(offset + 1)?,
+                    ..Default::default()
+                });
+            }
+            Ok(Logbook {
+                id: row.get(0)?,
+                timestamp: row.get(1)?,
+                call: row.get(2)?,
+                rsttx: row.get(3)?,
+                rstrx: row.get(4)?,
+                band: row.get(5)?,
+                frequency: row.get(6)?,
+                mode: row.get(7)?,
+                comments: row.get(8)?,
+                time_off: row.get(9)?,
+                qsl: qsl::summary(&qsls),
+                marked: false,
+            })
+        })?;
+        let mut logs: Vec<Logbook> = Vec::new();
+        for log in log_out {
+            logs.push(log?);
+        }
+        Ok(logs)
+    }
+
+    /// Saves every field of `log` over the row with `log.id`.
+    pub fn update(&self, log: &LogbookExt) -> Result<()> {
+        let conn = lock(&self.connection)?;
+        update_qso(&conn, log)
+    }
+
+    /// Updates every QSO in a single transaction, so either all or none are changed.
+    pub fn update_all(&self, logs: &[LogbookExt]) -> Result<()> {
+        let mut conn = lock(&self.connection)?;
+        let tx = conn.transaction()?;
+        for log in logs {
+            update_qso(&tx, log)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Sets the sent (or, when `received`, the received) status and date of one QSL service
+    /// on every QSO in `ids`, in a single transaction.
+    pub fn update_qsl(
+        &self,
+        ids: &[u64],
+        service: QslService,
+        received: bool,
+        status: &str,
+        date: Option<NaiveDate>,
+    ) -> Result<()> {
+        let column = format!(
+            "{}_{}",
+            service.column(),
+            if received { "rcvd" } else { "sent" }
+        );
+        let mut conn = lock(&self.connection)?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(&format!(
+                "UPDATE logs SET {0} = ?, {0}_date = ? WHERE id = ?",
+                column
+            ))?;
+            for id in ids {
+                stmt.execute(params![status, date, id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn delete(&self, id: u64) -> Result<()> {
+        let conn = lock(&self.connection)?;
+        conn.execute("DELETE FROM logs WHERE id = ?", (id,))?;
+        Ok(())
+    }
+}
+
+/// Typed access to the `operatorconfig` table of station profiles.
+#[derive(Clone)]
+pub struct ProfileStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl ProfileStore {
+    pub fn new(connection: Arc<Mutex<Connection>>) -> ProfileStore {
+        ProfileStore { connection }
+    }
+
+    /// Inserts `profile` and returns its new id; `profile.id` is ignored.
+    pub fn insert(&self, profile: &OperatorConfig) -> Result<u64> {
+        let conn = lock(&self.connection)?;
+        conn.execute(
+            "INSERT INTO operatorconfig (name, call, grid, cqz, ituz, dxcc, cont, state, lotw_cert) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            (
+                &profile.name,
+                &profile.call,
+                &profile.grid,
+                &profile.cqz,
+                &profile.ituz,
+                &profile.dxcc,
+                &profile.cont,
+                &profile.state,
+                &profile.lotw_cert,
+            ),
+        )?;
+        Ok(conn.last_insert_rowid() as u64)
+    }
+
+    pub fn get(&self, id: u64) -> Result<OperatorConfig> {
+        let conn = lock(&self.connection)?;
+        conn.query_row(
+            &format!("SELECT {} FROM operatorconfig WHERE id = ?", PROFILE_COLUMNS),
+            (id,),
+            |row| profile_from_row(row, 0),
+        )
+        .optional()?
+        .ok_or(anyhow!("No profile with id {}", id))
+    }
+
+    /// Returns the oldest profile whose callsign matches `call`, ignoring case.
+    pub fn find_by_call(&self, call: &str) -> Result<Option<OperatorConfig>> {
+        let conn = lock(&self.connection)?;
+        Ok(conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM operatorconfig WHERE call = ? COLLATE NOCASE ORDER BY id ASC LIMIT 1",
+                    PROFILE_COLUMNS
+                ),
+                (call.trim(),),
+                |row| profile_from_row(row, 0),
+            )
+            .optional()?)
+    }
+
+    pub fn list(&self) -> Result<Vec<OperatorConfig>> {
+        let conn = lock(&self.connection)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM operatorconfig ORDER BY id ASC",
+            PROFILE_COLUMNS
+        ))?;
+        let options_out = stmt.query_map((), |row| profile_from_row(row, 0))?;
+        let mut options: Vec<OperatorConfig> = Vec::new();
+        for option in options_out {
+            options.push(option?);
+        }
+        Ok(options)
+    }
+
+    pub fn update(&self, profile: &OperatorConfig) -> Result<()> {
+        let conn = lock(&self.connection)?;
+        conn.execute(
+            "UPDATE operatorconfig SET name = ?, call = ?, grid = ?, cqz = ?, ituz = ?, dxcc = ?, cont = ?, state = ?, lotw_cert = ? WHERE id = ?",
+            (
+                &profile.name,
+                &profile.call,
+                &profile.grid,
+                &profile.cqz,
+                &profile.ituz,
+                &profile.dxcc,
+                &profile.cont,
+                &profile.state,
+                &profile.lotw_cert,
+                profile.id,
+            ),
+        )?;
+        Ok(())
+    }
+
+    pub fn delete(&self, id: u64) -> Result<()> {
+        let conn = lock(&self.connection)?;
+        conn.execute("DELETE FROM operatorconfig WHERE id = ?", (id,))?;
+        Ok(())
+    }
+}
+
+const CONTEST_COLUMNS: &str =
+    "id, name, cabrillo, template, sent_exchange, operator_config, started, points, multipliers, mults_per_band";
+
+fn contest_from_row(row: &Row) -> Result<Contest> {
+    let template: String = row.get(3)?;
+    let points: String = row.get(7)?;
+    let multipliers: String = row.get(8)?;
+    Ok(Contest {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        cabrillo: row.get(2)?,
+        template: Template::from_key(&template)?,
+        sent_exchange: row.get(4)?,
+        profile: row.get(5)?,
+        started: row.get(6)?,
+        scoring: Scoring {
+            rules: Scoring::parse_rules(&points)?,
+            multipliers: Scoring::parse_multipliers(&multipliers)?,
+            per_band: row.get(9)?,
+        },
+    })
+}
+
+/// Typed access to the `contests` table of contest sessions.
+#[derive(Clone)]
+pub struct ContestStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl ContestStore {
+    pub fn new(connection: Arc<Mutex<Connection>>) -> ContestStore {
+        ContestStore { connection }
+    }
+
+    /// Inserts `contest` and returns its new id; `contest.id` is ignored.
+    pub fn insert(&self, contest: &Contest) -> Result<u64> {
+        let conn = lock(&self.connection)?;
+        conn.execute(
+            "INSERT INTO contests (name, cabrillo, template, sent_exchange, operator_config, started, points, multipliers, mults_per_band) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            (
+                &contest.name,
+                &contest.cabrillo,
+                contest.template.key(),
+                &contest.sent_exchange,
+                contest.profile,
+                format_timestamp(&contest.started),
+                contest.scoring.rules_text(),
+                contest.scoring.multipliers_text(),
+                contest.scoring.per_band,
+            ),
+        )?;
+        Ok(conn.last_insert_rowid() as u64)
+    }
+
+    /// Changes how contest `id` is scored.
+    pub fn set_scoring(&self, id: u64, scoring: &Scoring) -> Result<()> {
+        let conn = lock(&self.connection)?;
+        conn.execute(
+            "UPDATE contests SET points = ?, multipliers = ?, mults_per_band = ? WHERE id = ?",
+            (
+                scoring.rules_text(),
+                scoring.multipliers_text(),
+                scoring.per_band,
+                id,
+            ),
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&self, id: u64) -> Result<Contest> {
+        let conn = lock(&self.connection)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM contests WHERE id = ?",
+            CONTEST_COLUMNS
+        ))?;
+        let mut rows = stmt.query((id,))?;
+        match rows.next()? {
+            Some(row) => contest_from_row(row),
+            None => Err(anyhow!("No contest with id {}", id)),
+        }
+    }
+
+    /// Returns every contest session, newest first.
+    pub fn list(&self) -> Result<Vec<Contest>> {
+        let conn = lock(&self.connection)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM contests ORDER BY started DESC, id DESC",
+            CONTEST_COLUMNS
+        ))?;
+        let mut rows = stmt.query(())?;
+        let mut contests: Vec<Contest> = Vec::new();
+        while let Some(row) = rows.next()? {
+            contests.push(contest_from_row(row)?);
+        }
+        Ok(contests)
+    }
+
+    /// The serial number the next QSO in contest `id` sends, one past the highest sent so far.
+    pub fn next_serial(&self, id: u64) -> Result<u32> {
+        let conn = lock(&self.connection)?;
+        let last: Option<u32> = conn.query_row(
+            "SELECT MAX(stx) FROM logs WHERE contest = ?",
+            (id,),
+            |row| row.get(0),
+        )?;
+        Ok(last.unwrap_or(0) + 1)
+    }
+}
+
+/// Typed access to the key/value `settings` table.
+#[derive(Clone)]
+pub struct SettingsStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SettingsStore {
+    pub fn new(connection: Arc<Mutex<Connection>>) -> SettingsStore {
+        SettingsStore { connection }
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        let conn = lock(&self.connection)?;
+        Ok(conn
+            .query_row("SELECT value FROM settings WHERE key = ?", (key,), |row| {
+                row.get(0)
+            })
+            .optional()?)
+    }
+
+    pub fn set(&self, key: &str, value: &str) -> Result<()> {
+        let conn = lock(&self.connection)?;
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?, ?) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            (key, value),
+        )?;
+        Ok(())
+    }
+
+    /// Sets every key in `values` in a single transaction, so either all or none are stored.
+    pub fn set_all(&self, values: &[(&str, String)]) -> Result<()> {
+        let mut conn = lock(&self.connection)?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO settings (key, value) VALUES (?, ?) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            )?;
+            for (key, value) in values {
+                stmt.execute((key, value))?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frequency::Frequency;
+    use crate::qso::checked_edit_mode;
+
+    fn timestamp(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn profile(connection: Arc<Mutex<Connection>>) -> OperatorConfig {
+        let mut profile = OperatorConfig {
+            id: 0,
+            name: String::from("Home"),
+            call: String::from("W1AW"),
+            grid: String::from("FN31pr"),
+            cqz: String::from("5"),
+            ituz: String::from("8"),
+            dxcc: String::from("291"),
+            cont: String::from("NA"),
+            state: String::from("CT"),
+            lotw_cert: String::new(),
+        };
+        profile.id = ProfileStore::new(connection).insert(&profile).unwrap();
+        profile
+    }
+
+    fn qso(operator: &OperatorConfig, call: &str, band: &str, mode: &str, at: &str) -> LogbookExt {
+        LogbookExt {
+            id: 0,
+            timestamp: timestamp(at),
+            call: call.to_string(),
+            rsttx: String::from("599"),
+            rstrx: String::from("579"),
+            band: band.to_string(),
+            frequency: Some(Frequency(14_025_000)),
+            mode: mode.to_string(),
+            power: String::from("100"),
+            comments: String::new(),
+            time_off: Some(timestamp(at)),
+            name: String::new(),
+            qth: String::new(),
+            state: String::new(),
+            gridsquare: String::new(),
+            license_class: String::new(),
+            country: String::new(),
+            dxcc: None,
+            cqz: None,
+            ituz: None,
+            cont: String::new(),
+            distance: None,
+            bearing: None,
+            paper_qsl: Qsl::default(),
+            lotw_qsl: Qsl::default(),
+            eqsl_qsl: Qsl::default(),
+            contest: None,
+            contest_id: String::new(),
+            exchange_sent: Exchange::default(),
+            exchange_rcvd: Exchange::default(),
+            operator: operator.clone(),
+        }
+    }
+
+    fn calls(qsos: &QsoStore, filter: QsoFilter) -> Vec<String> {
+        qsos.query(&filter)
+            .unwrap()
+            .into_iter()
+            .map(|log| log.call)
+            .collect()
+    }
+
+    #[test]
+    fn qso_round_trip() {
+        let connection = open_in_memory().unwrap();
+        let operator = profile(connection.clone());
+        let qsos = QsoStore::new(connection);
+        let mut log = qso(&operator, "DL1ABC", "20M", "CW", "2024-03-01 12:00:00");
+        log.name = String::from("Hans");
+        log.gridsquare = String::from("JO62qm");
+        log.dxcc = Some(230);
+        log.exchange_rcvd = Exchange {
+            serial: Some(12),
+            text: String::new(),
+        };
+        let id = qsos.insert(&log).unwrap();
+
+        let stored = qsos.get(id).unwrap();
+        assert_eq!(stored.id, id);
+        assert_eq!(stored.call, "DL1ABC");
+        assert_eq!(stored.timestamp, log.timestamp);
+        assert_eq!(stored.frequency, Some(Frequency(14_025_000)));
+        assert_eq!(stored.name, "Hans");
+        assert_eq!(stored.gridsquare, "JO62qm");
+        assert_eq!(stored.dxcc, Some(230));
+        assert_eq!(stored.exchange_rcvd, log.exchange_rcvd);
+        assert_eq!(stored.operator.call, "W1AW");
+
+        let mut changed = stored.clone();
+        changed.rstrx = String::from("559");
+        changed.comments = String::from("QSB");
+        qsos.update(&changed).unwrap();
+        let stored = qsos.get(id).unwrap();
+        assert_eq!(stored.rstrx, "559");
+        assert_eq!(stored.comments, "QSB");
+
+        qsos.delete(id).unwrap();
+        assert!(qsos.get(id).is_err());
+    }
+
+    #[test]
+    fn edits_only_check_reports_that_change() {
+        let operator = profile(open_in_memory().unwrap());
+        // Logged before reports were checked
+        let stored = qso(&operator, "JA1ABC", "20M", "FT8", "2024-03-01 12:00:00");
+        let mut form = LogForm {
+            callsign: String::from("JA1ABC"),
+            profile: operator.id,
+            band: String::from("20M"),
+            frequency: String::from("14.074"),
+            mode: String::from("FT8"),
+            rsttx: String::from("599"),
+            rstrx: String::from("579"),
+            power: String::new(),
+            comments: String::from("edited"),
+            time_on: Some(stored.timestamp),
+            time_off: stored.time_off,
+            name: String::new(),
+            qth: String::new(),
+            state: String::new(),
+            gridsquare: String::new(),
+            license_class: String::new(),
+            contest: None,
+            exchange: String::new(),
+        };
+        assert!(checked_mode(&form).is_err());
+        assert_eq!(checked_edit_mode(&form, &stored).unwrap().name(), "FT8");
+
+        form.rstrx = String::from("589");
+        let err = checked_edit_mode(&form, &stored).unwrap_err();
+        assert!(err.to_string().starts_with("RST RX:"), "{}", err);
+        form.rstrx = String::from("-12");
+        assert!(checked_edit_mode(&form, &stored).is_ok());
+
+        // A new mode checks both reports against its rules
+        form.mode = String::from("FT4");
+        let err = checked_edit_mode(&form, &stored).unwrap_err();
+        assert!(err.to_string().starts_with("RST TX:"), "{}", err);
+    }
+
+    #[test]
+    fn insert_log_checks_the_form_and_blocks_dupes() {
+        let connection = open_in_memory().unwrap();
+        let operator = profile(connection.clone());
+        let qsos = QsoStore::new(connection.clone());
+        let mut form = LogForm {
+            callsign: String::from("DL1ABC"),
+            profile: operator.id,
+            band: String::from("20M"),
+            frequency: String::from("14.025"),
+            mode: String::from("CW"),
+            rsttx: String::from("599"),
+            rstrx: String::from("599"),
+            power: String::new(),
+            comments: String::new(),
+            time_on: Some(timestamp("2024-03-01 12:00:00")),
+            time_off: Some(timestamp("2024-03-01 12:02:00")),
+            name: String::new(),
+            qth: String::new(),
+            state: String::new(),
+            gridsquare: String::from("jo62qm"),
+            license_class: String::new(),
+            contest: None,
+            exchange: String::new(),
+        };
+        let log = qsos.insert_log(&form).unwrap();
+        let stored = qsos.get(log.id).unwrap();
+        assert_eq!(stored.gridsquare, "JO62qm");
+        assert_eq!(stored.time_off, form.time_off);
+        assert!(stored.distance.is_some());
+
+        form.frequency = String::from("7.025");
+        assert!(qsos.insert_log(&form).is_err());
+        form.frequency = String::from("14.025");
+
+        let settings = SettingsStore::new(connection);
+        DupeSettings {
+            block: true,
+            window_hours: 0,
+        }
+        .save(&settings)
+        .unwrap();
+        assert!(qsos.insert_log(&form).is_err());
+        form.mode = String::from("SSB");
+        form.rsttx = String::from("59");
+        form.rstrx = String::from("59");
+        qsos.insert_log(&form).unwrap();
+        assert_eq!(qsos.query(&QsoFilter::default()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn dupe_window_is_measured_from_the_new_qso() {
+        let connection = open_in_memory().unwrap();
+        let operator = profile(connection.clone());
+        let qsos = QsoStore::new(connection.clone());
+        let first = qso(&operator, "DL1ABC", "20M", "CW", "2024-03-01 12:00:00");
+        qsos.insert(&first).unwrap();
+        DupeSettings {
+            block: true,
+            window_hours: 24,
+        }
+        .save(&SettingsStore::new(connection))
+        .unwrap();
+        let mut form = LogForm {
+            callsign: String::from("DL1ABC"),
+            profile: operator.id,
+            band: String::from("20M"),
+            frequency: String::from("14.025"),
+            mode: String::from("CW"),
+            rsttx: String::from("599"),
+            rstrx: String::from("599"),
+            power: String::new(),
+            comments: String::new(),
+            time_on: Some(timestamp("2024-03-01 18:00:00")),
+            time_off: Some(timestamp("2024-03-01 18:00:00")),
+            name: String::new(),
+            qth: String::new(),
+            state: String::new(),
+            gridsquare: String::new(),
+            license_class: String::new(),
+            contest: None,
+            exchange: String::new(),
+        };
+        // Years ago, but only hours after the first QSO
+        assert!(qsos.insert_log(&form).is_err());
+        form.time_on = Some(timestamp("2024-03-02 18:00:00"));
+        form.time_off = form.time_on;
+        qsos.insert_log(&form).unwrap();
+    }
+
+    #[test]
+    fn filter_by_call_band_mode_and_date() {
+        let connection = open_in_memory().unwrap();
+        let operator = profile(connection.clone());
+        let qsos = QsoStore::new(connection);
+        qsos.insert_all(&[
+            qso(&operator, "DL1ABC", "20M", "CW", "2024-03-01 12:00:00"),
+            qso(&operator, "DL1ABC", "40M", "SSB", "2024-03-02 12:00:00"),
+            qso(&operator, "DL2XYZ", "20M", "SSB", "2024-03-03 12:00:00"),
+            qso(&operator, "G4ABC", "20M", "CW", "2024-03-04 12:00:00"),
+        ])
+        .unwrap();
+
+        assert_eq!(calls(&qsos, QsoFilter::default()).len(), 4);
+        assert_eq!(
+            calls(
+                &qsos,
+                QsoFilter {
+                    call: Some(String::from("dl1abc")),
+                    ..Default::default()
+                }
+            ),
+            ["DL1ABC", "DL1ABC"]
+        );
+        assert_eq!(
+            calls(
+                &qsos,
+                QsoFilter {
+                    call_pattern: Some(String::from("DL?X*")),
+                    ..Default::default()
+                }
+            ),
+            ["DL2XYZ"]
+        );
+        assert_eq!(
+            calls(
+                &qsos,
+                QsoFilter {
+                    band: Some(String::from("20m")),
+                    mode: Some(String::from("cw")),
+                    ..Default::default()
+                }
+            ),
+            ["G4ABC", "DL1ABC"]
+        );
+        assert_eq!(
+            calls(
+                &qsos,
+                QsoFilter {
+                    start: Some(timestamp("2024-03-02 00:00:00")),
+                    end: Some(timestamp("2024-03-03 23:59:59")),
+                    ..Default::default()
+                }
+            ),
+            ["DL2XYZ", "DL1ABC"]
+        );
+    }
+
+    #[test]
+    fn filter_clause_binds_one_parameter_per_condition() {
+        let filter = QsoFilter {
+            call: Some(String::from(" K1ABC ")),
+            band: Some(String::from("20M")),
+            exclude: Some(3),
+            ..Default::default()
+        };
+        let (clause, params) = filter.where_clause();
+        assert_eq!(
+            clause,
+            " WHERE logs.call = ? COLLATE NOCASE AND logs.band = ? COLLATE NOCASE AND logs.id != ?"
+        );
+        assert_eq!(params.len(), 3);
+        assert_eq!(QsoFilter::default().where_clause().0, "");
+    }
+
+    #[test]
+    fn qsl_status_is_stored_per_service() {
+        let connection = open_in_memory().unwrap();
+        let operator = profile(connection.clone());
+        let qsos = QsoStore::new(connection);
+        let first = qso(&operator, "DL1ABC", "20M", "CW", "2024-03-01 12:00:00");
+        let first = qsos.insert(&first).unwrap();
+        let second = qso(&operator, "G4ABC", "20M", "CW", "2024-03-02 12:00:00");
+        let second = qsos.insert(&second).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 5);
+        qsos.update_qsl(&[first], QslService::Lotw, false, "Y", date)
+            .unwrap();
+        qsos.update_qsl(&[first, second], QslService::Paper, true, "Y", date)
+            .unwrap();
+
+        let first = qsos.get(first).unwrap();
+        assert_eq!(first.lotw_qsl.sent, "Y");
+        assert_eq!(first.lotw_qsl.sent_date, date);
+        assert_eq!(first.paper_qsl.rcvd, "Y");
+        assert_eq!(first.eqsl_qsl, Qsl::default());
+        let second = qsos.get(second).unwrap();
+        assert_eq!(second.lotw_qsl.sent, "N");
+        assert_eq!(second.paper_qsl.rcvd_date, date);
+    }
+
+    #[test]
+    fn update_all_changes_every_qso_or_none() {
+        let connection = open_in_memory().unwrap();
+        let operator = profile(connection.clone());
+        let qsos = QsoStore::new(connection);
+        let mut log = qso(&operator, "DL1ABC", "20M", "CW", "2024-03-01 12:00:00");
+        log.country = String::from("Germany");
+        log.id = qsos.insert(&log).unwrap();
+        let germany = dxcc::Entity {
+            name: String::from("Fed. Rep. of Germany"),
+            dxcc: Some(230),
+            cqz: 14,
+            ituz: 28,
+            cont: String::from("EU"),
+        };
+        assert!(log.fill_entity(Some(&germany)));
+        assert!(!log.clone().fill_entity(Some(&germany)));
+
+        let mut missing = log.clone();
+        missing.id = 99;
+        assert!(qsos.update_all(&[log.clone(), missing]).is_err());
+        assert_eq!(qsos.get(log.id).unwrap().dxcc, None);
+
+        qsos.update_all(&[log.clone()]).unwrap();
+        let stored = qsos.get(log.id).unwrap();
+        assert_eq!(stored.country, "Germany");
+        assert_eq!(stored.dxcc, Some(230));
+        assert_eq!(stored.cqz, Some(14));
+        assert_eq!(stored.cont, "EU");
+    }
+
+    #[test]
+    fn next_serial_counts_up_per_contest() {
+        let connection = open_in_memory().unwrap();
+        let operator = profile(connection.clone());
+        let contests = ContestStore::new(connection.clone());
+        let contest = Contest {
+            id: 0,
+            name: String::from("WPX"),
+            cabrillo: String::from("CQ-WPX-CW"),
+            template: Template::SerialRst,
+            sent_exchange: String::new(),
+            profile: operator.id,
+            started: timestamp("2024-03-30 00:00:00"),
+            scoring: Scoring::default(),
+        };
+        let wpx = contests.insert(&contest).unwrap();
+        let other = contests.insert(&contest).unwrap();
+        assert_eq!(contests.next_serial(wpx).unwrap(), 1);
+
+        let qsos = QsoStore::new(connection);
+        for (serial, call) in [(1, "DL1ABC"), (2, "G4ABC")] {
+            let mut log = qso(&operator, call, "20M", "CW", "2024-03-30 00:01:00");
+            log.contest = Some(wpx);
+            log.exchange_sent = contest.sent(serial);
+            qsos.insert(&log).unwrap();
+        }
+        assert_eq!(contests.next_serial(wpx).unwrap(), 3);
+        assert_eq!(contests.next_serial(other).unwrap(), 1);
+    }
+
+    #[test]
+    fn settings_are_set_together() {
+        let settings = SettingsStore::new(open_in_memory().unwrap());
+        settings.set("rig_address", "localhost:4532").unwrap();
+        let mut values = vec![
+            ("rig_address", String::new()),
+            ("cty_file", String::from("cty.csv")),
+        ];
+        values.extend(
+            DupeSettings {
+                block: true,
+                window_hours: 24,
+            }
+            .values(),
+        );
+        settings.set_all(&values).unwrap();
+        assert_eq!(settings.get("rig_address").unwrap().as_deref(), Some(""));
+        let cty_file = settings.get("cty_file").unwrap();
+        assert_eq!(cty_file.as_deref(), Some("cty.csv"));
+        let dupes = DupeSettings::load(&settings).unwrap();
+        assert!(dupes.block);
+        assert_eq!(dupes.window_hours, 24);
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+
+use crate::frequency::Frequency;
+
+const MAGIC: u32 = 0xadbc_cbda;
+// Julian day number of 0001-01-01, which chrono counts days from
+const JULIAN_DAY_OF_CE: i64 = 1_721_425;
+
+/// A QSO WSJT-X sends when the operator presses Log QSO (message type 5).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QsoLogged {
+    /// Client id, such as `WSJT-X`
+    pub id: String,
+    pub time_off: Option<NaiveDateTime>,
+    pub dx_call: String,
+    pub dx_grid: String,
+    pub frequency: Frequency,
+    pub mode: String,
+    pub report_sent: String,
+    pub report_rcvd: String,
+    pub tx_power: String,
+    pub comments: String,
+    pub name: String,
+    pub time_on: Option<NaiveDateTime>,
+    pub operator_call: String,
+    pub my_call: String,
+    pub my_grid: String,
+    pub exchange_sent: String,
+    pub exchange_rcvd: String,
+    pub prop_mode: String,
+}
+
+/// The WSJT-X messages TUILog acts on; everything else is `Other` with its type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    QsoLogged(Box<QsoLogged>),
+    /// The same QSO as a one-record ADIF document (message type 12)
+    LoggedAdif {
+        id: String,
+        adif: String,
+    },
+    Other(u32),
+}
+
+/// Reads the Qt `QDataStream` encoding the WSJT-X protocol uses, which is big-endian.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        if self.data.len() < len {
+            return Err(anyhow!("WSJT-X message is truncated"));
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into()?))
+    }
+
+    /// A `QByteArray` holding UTF-8; a length of 0xffffffff is a null string.
+    fn utf8(&mut self) -> Result<String> {
+        let len = self.u32()?;
+        if len == u32::MAX {
+            return Ok(String::new());
+        }
+        Ok(String::from_utf8_lossy(self.take(len as usize)?).into_owned())
+    }
+
+    /// Like `utf8`, but empty when an older WSJT-X left the field off the end.
+    fn optional_utf8(&mut self) -> Result<String> {
+        if self.is_empty() {
+            return Ok(String::new());
+        }
+        self.utf8()
+    }
+
+    /// A `QDateTime` converted to UTC: a Julian day, milliseconds since midnight and a
+    /// time spec of local time, UTC, an offset from UTC or a named time zone.
+    fn date_time(&mut self) -> Result<Option<NaiveDateTime>> {
+        let day = self.i64()?;
+        let msecs = self.u32()?;
+        let spec = self.u8()?;
+        let offset = if spec == 2 { self.i32()? } else { 0 };
+        let date = day
+            .checked_sub(JULIAN_DAY_OF_CE)
+            .and_then(|days| i32::try_from(days).ok())
+            .and_then(NaiveDate::from_num_days_from_ce_opt);
+        let date_time = match date {
+            // A null QDateTime has an invalid date, and no time either
+            Some(date) if msecs != u32::MAX => {
+                date.and_hms_opt(0, 0, 0).unwrap() + Duration::milliseconds(msecs as i64)
+            }
+            _ => return Ok(None),
+        };
+        let date_time = match spec {
+            0 => Local
+                .from_local_datetime(&date_time)
+                .single()
+                .map(|local| local.naive_utc())
+                .unwrap_or(date_time),
+            1 => date_time,
+            2 => date_time - Duration::seconds(offset as i64),
+            _ => return Err(anyhow!("WSJT-X time zones are not supported; log in UTC")),
+        };
+        // Logs keep whole seconds
+        Ok(date_time.with_nanosecond(0))
+    }
+}
+
+/// Decodes one WSJT-X datagram.
+pub fn decode(datagram: &[u8]) -> Result<Message> {
+    let mut reader = Reader { data: datagram };
+    if reader.u32()? != MAGIC {
+        return Err(anyhow!("Not a WSJT-X message"));
+    }
+    let _schema = reader.u32()?;
+    let kind = reader.u32()?;
+    match kind {
+        5 => {
+            let id = reader.utf8()?;
+            let time_off = reader.date_time()?;
+            let dx_call = reader.utf8()?;
+            let dx_grid = reader.utf8()?;
+            let frequency = Frequency(reader.u64()?);
+            Ok(Message::QsoLogged(Box::new(QsoLogged {
+                id,
+                time_off,
+                dx_call,
+                dx_grid,
+                frequency,
+                mode: reader.utf8()?,
+                report_sent: reader.utf8()?,
+                report_rcvd: reader.utf8()?,
+                tx_power: reader.utf8()?,
+                comments: reader.utf8()?,
+                name: reader.utf8()?,
+                time_on: reader.date_time()?,
+                operator_call: reader.optional_utf8()?,
+                my_call: reader.optional_utf8()?,
+                my_grid: reader.optional_utf8()?,
+                exchange_sent: reader.optional_utf8()?,
+                exchange_rcvd: reader.optional_utf8()?,
+                prop_mode: reader.optional_utf8()?,
+            })))
+        }
+        12 => Ok(Message::LoggedAdif {
+            id: reader.utf8()?,
+            adif: reader.utf8()?,
+        }),
+        kind => Ok(Message::Other(kind)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Datagrams laid out as WSJT-X 2.6 sends them for one FT8 QSO with DL1ABC, and a status message
+    const QSO_LOGGED: &[u8] = include_bytes!("../tests/fixtures/wsjtx/qso_logged.bin");
+    const LOGGED_ADIF: &[u8] = include_bytes!("../tests/fixtures/wsjtx/logged_adif.bin");
+    const STATUS: &[u8] = include_bytes!("../tests/fixtures/wsjtx/status.bin");
+
+    fn timestamp(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn decodes_qso_logged() {
+        let Message::QsoLogged(qso) = decode(QSO_LOGGED).unwrap() else {
+            panic!("not a QSO Logged message");
+        };
+        assert_eq!(
+            *qso,
+            QsoLogged {
+                id: String::from("WSJT-X"),
+                time_off: Some(timestamp("2024-03-01 12:01:15")),
+                dx_call: String::from("dl1abc"),
+                dx_grid: String::from("JO62"),
+                frequency: Frequency(14_075_500),
+                mode: String::from("FT8"),
+                report_sent: String::from("-10"),
+                report_rcvd: String::from("-15"),
+                tx_power: String::from("50"),
+                comments: String::from("tnx"),
+                name: String::new(),
+                time_on: Some(timestamp("2024-03-01 12:00:00")),
+                operator_call: String::new(),
+                my_call: String::from("W1AW"),
+                my_grid: String::from("FN31PR"),
+                exchange_sent: String::new(),
+                exchange_rcvd: String::new(),
+                prop_mode: String::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_logged_adif() {
+        let Message::LoggedAdif { id, adif } = decode(LOGGED_ADIF).unwrap() else {
+            panic!("not a Logged ADIF message");
+        };
+        assert_eq!(id, "WSJT-X");
+        assert!(adif.contains("<call:6>DL1ABC "));
+        assert!(adif.contains("<time_on:6>120000 "));
+        assert!(adif.trim_end().ends_with("<EOR>"));
+    }
+
+    #[test]
+    fn leaves_fields_off_older_messages_empty() {
+        // WSJT-X before 2.0 stopped after the time on, leaving off six length-prefixed strings
+        let added = 4 * 6 + "W1AW".len() + "FN31PR".len();
+        let old = &QSO_LOGGED[..QSO_LOGGED.len() - added];
+        let Message::QsoLogged(qso) = decode(old).unwrap() else {
+            panic!("not a QSO Logged message");
+        };
+        assert_eq!(qso.time_on, Some(timestamp("2024-03-01 12:00:00")));
+        assert_eq!(qso.my_call, "");
+    }
+
+    #[test]
+    fn passes_over_other_traffic() {
+        assert_eq!(decode(STATUS).unwrap(), Message::Other(1));
+        assert!(decode(b"<call:4>W1AW <eor>").is_err());
+        assert!(decode(&QSO_LOGGED[..40]).is_err());
+    }
+}
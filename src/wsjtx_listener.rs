@@ -0,0 +1,391 @@
+use std::{
+    collections::VecDeque,
+    io::ErrorKind,
+    net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use cursive::{
+    view::{Nameable, Position},
+    views::Dialog,
+    CbSink, Cursive,
+};
+use cursive_table_view::TableView;
+use rusqlite::Connection;
+
+use crate::log::current_profile;
+use crate::logbook::refresh_table;
+use tuilog::adif_io::{self, adif_field, adif_time_off, adif_timestamp};
+use tuilog::bandplan::BandPlan;
+use tuilog::frequency::Frequency;
+use tuilog::mode::Mode;
+use tuilog::models::{Logbook, LogbookColumn};
+use tuilog::qso::LogForm;
+use tuilog::store::{ProfileStore, QsoStore, SettingsStore};
+use tuilog::wsjtx::{self, Message, QsoLogged};
+
+const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+const TOAST_TIME: Duration = Duration::from_secs(3);
+// WSJT-X sends every QSO twice, as type 5 and as type 12, so recent ones are remembered
+const RECENT_QSOS: usize = 20;
+
+static TOASTS: AtomicUsize = AtomicUsize::new(0);
+
+/// A QSO from WSJT-X with the callsign it was made from.
+struct Logged {
+    form: LogForm,
+    my_call: String,
+}
+
+/// Starts listening for WSJT-X at the `wsjtx_address` setting. Like the rig, the address
+/// is re-read while idle, so changing it in Options takes effect without a restart.
+pub fn start(siv: &mut Cursive, connection: Arc<Mutex<Connection>>) {
+    let cb_sink = siv.cb_sink().clone();
+    spawn(move || listen(connection, cb_sink));
+}
+
+fn wsjtx_address(connection: &Arc<Mutex<Connection>>) -> String {
+    SettingsStore::new(connection.clone())
+        .get("wsjtx_address")
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// Binds `address`, joining its group when it is a multicast address.
+fn bind(address: &str) -> Result<UdpSocket> {
+    let address: SocketAddr = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or(anyhow!("Could not resolve {}", address))?;
+    let socket = match address {
+        SocketAddr::V4(v4) if v4.ip().is_multicast() => {
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, v4.port()))?;
+            socket.join_multicast_v4(v4.ip(), &Ipv4Addr::UNSPECIFIED)?;
+            socket
+        }
+        address => UdpSocket::bind(address)?,
+    };
+    socket.set_read_timeout(Some(IDLE_INTERVAL))?;
+    Ok(socket)
+}
+
+fn listen(connection: Arc<Mutex<Connection>>, cb_sink: CbSink) {
+    let mut socket: Option<(String, UdpSocket)> = None;
+    // Only the first of a run of identical bind errors is shown
+    let mut failure: Option<String> = None;
+    let mut recent: VecDeque<(String, Option<NaiveDateTime>)> = VecDeque::new();
+    let mut buffer = vec![0; 65536];
+    loop {
+        let address = wsjtx_address(&connection);
+        if address.is_empty() {
+            socket = None;
+            sleep(IDLE_INTERVAL);
+            continue;
+        }
+        if socket.as_ref().is_none_or(|(bound, _)| *bound != address) {
+            match bind(&address) {
+                Ok(bound) => {
+                    socket = Some((address.clone(), bound));
+                    failure = None;
+                }
+                Err(err) => {
+                    socket = None;
+                    let text = format!("Cannot listen on {} ({})", address, err);
+                    if failure.as_ref() != Some(&text) {
+                        failure = Some(text.clone());
+                        if !send_toast(&cb_sink, text) {
+                            return;
+                        }
+                    }
+                    sleep(RETRY_INTERVAL);
+                    continue;
+                }
+            }
+        }
+        let (_, udp) = socket.as_ref().unwrap();
+        let len = match udp.recv(&mut buffer) {
+            Ok(len) => len,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                continue
+            }
+            Err(_) => {
+                socket = None;
+                continue;
+            }
+        };
+        let Some(text) = receive(&connection, &mut recent, &buffer[..len]) else {
+            continue;
+        };
+        let refresh_connection = connection.clone();
+        let sent = cb_sink.send(Box::new(move |s| {
+            toast(s, text);
+            let table_open = s
+                .call_on_name("table", |_: &mut TableView<Logbook, LogbookColumn>| ())
+                .is_some();
+            if table_open {
+                let _ = refresh_table(s, refresh_connection);
+            }
+        }));
+        if sent.is_err() {
+            return;
+        }
+    }
+}
+
+/// Logs the QSO in a datagram unless it was just logged, and says what happened. Anything
+/// that is not a WSJT-X QSO passes silently, as the port may be shared with other programs.
+fn receive(
+    connection: &Arc<Mutex<Connection>>,
+    recent: &mut VecDeque<(String, Option<NaiveDateTime>)>,
+    datagram: &[u8],
+) -> Option<String> {
+    let logged = match decode(connection, datagram) {
+        Ok(logged) => logged?,
+        Err(err) => return Some(format!("QSO from WSJT-X not logged: {}", err)),
+    };
+    let key = (logged.form.callsign.clone(), logged.form.time_on);
+    if recent.contains(&key) {
+        return None;
+    }
+    recent.push_back(key);
+    if recent.len() > RECENT_QSOS {
+        recent.pop_front();
+    }
+    let call = logged.form.callsign.clone();
+    Some(
+        match log_qso(connection.clone(), logged.form, &logged.my_call) {
+            Ok(text) => text,
+            Err(err) => format!("QSO with {} not logged: {}", call, err),
+        },
+    )
+}
+
+/// Turns a QSO Logged or Logged ADIF datagram into a form for `QsoStore::insert_log`; other
+/// messages, such as the status and decodes WSJT-X sends all the time, and datagrams that
+/// do not decode at all give `None`.
+fn decode(connection: &Arc<Mutex<Connection>>, datagram: &[u8]) -> Result<Option<Logged>> {
+    let mut logged = match wsjtx::decode(datagram) {
+        Ok(Message::QsoLogged(qso)) => qso_form(*qso),
+        Ok(Message::LoggedAdif { adif, .. }) => adif_form(&adif)?,
+        // Not from WSJT-X, or a message this version does not understand
+        Ok(Message::Other(_)) | Err(_) => return Ok(None),
+    };
+    if logged.form.band.is_empty() {
+        let frequency: Frequency = logged.form.frequency.parse()?;
+        logged.form.band = BandPlan::load(&SettingsStore::new(connection.clone()))?
+            .band_for(frequency)
+            .map(|band| band.name.clone())
+            .ok_or_else(|| anyhow!("{} MHz is not inside any band", frequency))?;
+    }
+    Ok(Some(logged))
+}
+
+fn qso_form(qso: QsoLogged) -> Logged {
+    Logged {
+        form: LogForm {
+            callsign: qso.dx_call.trim().to_uppercase(),
+            profile: 0,
+            band: String::new(),
+            frequency: qso.frequency.to_string(),
+            mode: qso.mode.trim().to_uppercase(),
+            rsttx: qso.report_sent.trim().to_string(),
+            rstrx: qso.report_rcvd.trim().to_string(),
+            power: qso.tx_power.trim().to_string(),
+            comments: qso.comments.trim().to_string(),
+            time_on: qso.time_on,
+            time_off: qso.time_off,
+            name: qso.name.trim().to_string(),
+            qth: String::new(),
+            state: String::new(),
+            gridsquare: qso.dx_grid.trim().to_string(),
+            license_class: String::new(),
+            contest: None,
+            exchange: String::new(),
+        },
+        my_call: qso.my_call,
+    }
+}
+
+fn adif_form(adif: &str) -> Result<Logged> {
+    let file =
+        adif_io::parse(adif).map_err(|err| anyhow!("Failed to parse the logged ADIF: {}", err))?;
+    let record = file
+        .body
+        .iter()
+        .find(|record| !record.is_empty())
+        .ok_or(anyhow!("The logged ADIF has no QSO"))?;
+    let field = |key: &str| adif_field(record, key).unwrap_or_default();
+    let time_on = adif_timestamp(record, "QSO_DATE", "TIME_ON")?;
+    let mode = Mode::from_adif(&field("MODE"), adif_field(record, "SUBMODE").as_deref())
+        .map(|mode| mode.name().to_string())
+        .unwrap_or_else(|| field("MODE").to_uppercase());
+    Ok(Logged {
+        form: LogForm {
+            callsign: field("CALL").to_uppercase(),
+            profile: 0,
+            band: field("BAND").to_uppercase(),
+            frequency: field("FREQ"),
+            mode,
+            rsttx: field("RST_SENT"),
+            rstrx: field("RST_RCVD"),
+            power: field("TX_PWR"),
+            comments: field("COMMENT"),
+            time_on: Some(time_on),
+            time_off: adif_time_off(record, time_on)?,
+            name: field("NAME"),
+            qth: field("QTH"),
+            state: field("STATE"),
+            gridsquare: field("GRIDSQUARE"),
+            license_class: String::new(),
+            contest: None,
+            exchange: String::new(),
+        },
+        my_call: field("STATION_CALLSIGN"),
+    })
+}
+
+/// Logs the QSO against the current profile, or the profile for the callsign WSJT-X
+/// was operating as when none has been picked yet.
+fn log_qso(connection: Arc<Mutex<Connection>>, mut form: LogForm, my_call: &str) -> Result<String> {
+    let profile = match current_profile(connection.clone())? {
+        Some(profile) => profile,
+        None => ProfileStore::new(connection.clone())
+            .find_by_call(my_call)?
+            .ok_or(anyhow!("pick a profile in New Log first"))?,
+    };
+    form.profile = profile.id;
+    QsoStore::new(connection).insert_log(&form)?;
+    Ok(format!(
+        "Logged {} on {} {} from WSJT-X",
+        form.callsign, form.band, form.mode
+    ))
+}
+
+fn send_toast(cb_sink: &CbSink, text: String) -> bool {
+    cb_sink.send(Box::new(move |s| toast(s, text))).is_ok()
+}
+
+/// Shows `text` in the bottom right corner for a few seconds.
+fn toast(s: &mut Cursive, text: String) {
+    let name = format!("toast_{}", TOASTS.fetch_add(1, Ordering::Relaxed));
+    s.screen_mut().add_layer_at(
+        Position::absolute((usize::MAX, usize::MAX)),
+        Dialog::text(text).title("WSJT-X").with_name(name.as_str()),
+    );
+    let cb_sink = s.cb_sink().clone();
+    spawn(move || {
+        sleep(TOAST_TIME);
+        let _ = cb_sink.send(Box::new(move |s| {
+            let screen = s.screen_mut();
+            if let Some(position) = screen.find_layer_from_name(&name) {
+                screen.remove_layer(position);
+            }
+        }));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tuilog::models::OperatorConfig;
+    use tuilog::store::{open_in_memory, QsoFilter};
+
+    const QSO_LOGGED: &[u8] = include_bytes!("../tests/fixtures/wsjtx/qso_logged.bin");
+    const LOGGED_ADIF: &[u8] = include_bytes!("../tests/fixtures/wsjtx/logged_adif.bin");
+    const STATUS: &[u8] = include_bytes!("../tests/fixtures/wsjtx/status.bin");
+
+    fn timestamp(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn logbook(call: &str) -> Arc<Mutex<Connection>> {
+        let connection = open_in_memory().unwrap();
+        ProfileStore::new(connection.clone())
+            .insert(&OperatorConfig {
+                id: 0,
+                name: String::from("Home"),
+                call: call.to_string(),
+                grid: String::from("FN31pr"),
+                cqz: String::new(),
+                ituz: String::new(),
+                dxcc: String::new(),
+                cont: String::new(),
+                state: String::new(),
+                lotw_cert: String::new(),
+            })
+            .unwrap();
+        connection
+    }
+
+    #[test]
+    fn logs_each_qso_once() {
+        let connection = logbook("W1AW");
+        let listener = bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut recent = VecDeque::new();
+        let mut buffer = vec![0; 65536];
+        let mut texts = Vec::new();
+        // WSJT-X sends the QSO twice; the status and a stray ADIF line are other traffic
+        for datagram in [
+            STATUS,
+            QSO_LOGGED,
+            LOGGED_ADIF,
+            b"<call:4>W1AW <eor>".as_slice(),
+        ] {
+            sender
+                .send_to(datagram, listener.local_addr().unwrap())
+                .unwrap();
+            let len = listener.recv(&mut buffer).unwrap();
+            texts.push(receive(&connection, &mut recent, &buffer[..len]));
+        }
+        assert_eq!(
+            texts,
+            [
+                None,
+                Some(String::from("Logged DL1ABC on 20M FT8 from WSJT-X")),
+                None,
+                None
+            ]
+        );
+
+        let logs = QsoStore::new(connection)
+            .query(&QsoFilter::default())
+            .unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].call, "DL1ABC");
+        assert_eq!(logs[0].rstrx, "-15");
+        assert_eq!(logs[0].gridsquare, "JO62");
+        assert_eq!(logs[0].operator.call, "W1AW");
+    }
+
+    #[test]
+    fn logs_adif_on_its_own() {
+        let connection = logbook("W1AW");
+        let text = receive(&connection, &mut VecDeque::new(), LOGGED_ADIF);
+        assert_eq!(text.unwrap(), "Logged DL1ABC on 20M FT8 from WSJT-X");
+        let log = &QsoStore::new(connection)
+            .query(&QsoFilter::default())
+            .unwrap()[0];
+        assert_eq!(log.time_off, Some(timestamp("2024-03-01 12:01:15")));
+        assert_eq!(log.comments, "tnx");
+    }
+
+    #[test]
+    fn reports_qsos_it_cannot_log() {
+        // No profile is picked and none matches the station callsign WSJT-X sends
+        let connection = logbook("K1ABC");
+        let text = receive(&connection, &mut VecDeque::new(), QSO_LOGGED).unwrap();
+        assert!(text.starts_with("QSO with DL1ABC not logged"), "{}", text);
+    }
+}